@@ -1,15 +1,15 @@
 use crate::ray::Ray;
 use std::iter::zip;
 use nalgebra::Vector3;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Aabb {
     pub bounds: [PlaneBounds; 3],
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Deserialize, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct PlaneBounds {
     pub low: f32, 
     pub high: f32,
@@ -19,6 +19,42 @@ impl Aabb {
     pub fn centroid(&self) -> Vector3<f32> {
         Vector3::from_iterator(self.bounds.iter().map(|b| 0.5 * (b.low + b.high)))
     }
+    pub fn empty() -> Self { // contains nothing, anything unioned with it gives back the other
+        Aabb { bounds: [PlaneBounds {low: f32::INFINITY, high: f32::NEG_INFINITY}; 3] }
+    }
+    pub fn from_points<'p, I: IntoIterator<Item = &'p Vector3<f32>>>(points: I) -> Self {
+        points.into_iter().fold(Self::empty(), |aabb, p| aabb.union(&Aabb {
+            bounds: [0, 1, 2].map(|a| PlaneBounds {low: p[a], high: p[a]}),
+        }))
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            bounds: [0, 1, 2].map(|a| PlaneBounds {
+                low: self.bounds[a].low.min(other.bounds[a].low),
+                high: self.bounds[a].high.max(other.bounds[a].high),
+            }),
+        }
+    }
+    pub fn intersection(&self, other: &Aabb) -> Aabb { // can come out inverted (empty) if they don't overlap
+        Aabb {
+            bounds: [0, 1, 2].map(|a| PlaneBounds {
+                low: self.bounds[a].low.max(other.bounds[a].low),
+                high: self.bounds[a].high.min(other.bounds[a].high),
+            }),
+        }
+    }
+    pub fn surface_area(&self) -> f32 { // 0 for an empty box
+        let [x, y, z] = self.bounds.map(|b| (b.high - b.low).max(0.0));
+        2.0 * (x * y + y * z + z * x)
+    }
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let [x, y, z] = self.bounds;
+        core::array::from_fn(|i| Vector3::new(
+            if i & 1 == 0 { x.low } else { x.high },
+            if i & 2 == 0 { y.low } else { y.high },
+            if i & 4 == 0 { z.low } else { z.high },
+        ))
+    }
     pub fn get_entry_exit(&self, ray: &Ray) -> Option<((usize, f32), (usize, f32))> { // return axis, entry exit t for ray
         // adapted from https://gamedev.stackexchange.com/questions/18436/most-efficient-aabb-vs-ray-collision-algorithms/18459#18459
 
@@ -0,0 +1,295 @@
+use super::{Aabb, PlaneBounds, PARALLEL_BUILD_MIN};
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+// bounding volume hierarchy over primitive indices only, so it can live next to whatever owns the
+// primitives (e.g. in an Arc) without borrowing them like the KdTree does
+pub struct Bvh {
+    pub(super) nodes: Vec<BvhNode>,
+    pub(super) prim_idxs: Vec<usize>, // leaves point at ranges of this
+}
+
+pub(super) struct BvhNode {
+    pub(super) aabb: Aabb,
+    pub(super) kind: BvhNodeKind,
+}
+
+pub(super) enum BvhNodeKind {
+    Inner { low: usize, high: usize },
+    Leaf { first: usize, count: usize },
+}
+
+const SAH_BINS: usize = 16;
+const MAX_LEAF_PRIMS: usize = 16; // past this a leaf gets split even when the sah says it isn't worth it
+const TRAVERSAL_COST: f32 = 1.0; // relative to one primitive intersection
+
+impl Bvh {
+    pub fn build(aabbs: &[Aabb]) -> Self {
+        let mut prim_idxs: Vec<usize> = (0..aabbs.len()).collect();
+        let centroids: Vec<Vector3<f32>> = aabbs.par_iter().map(|aabb| aabb.centroid()).collect();
+        let nodes = build_subtree(aabbs, &centroids, &mut prim_idxs, 0);
+        Bvh { nodes, prim_idxs }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        self.nodes.first().map(|n| n.aabb).unwrap_or(Aabb::empty())
+    }
+}
+
+// nodes for idxs, which start at first in the whole prim_idxs, root first and child indices relative to it.
+// big halves get built on other threads and spliced in after
+fn build_subtree(aabbs: &[Aabb], centroids: &[Vector3<f32>], idxs: &mut [usize], first: usize) -> Vec<BvhNode> {
+    let aabb = idxs.iter().fold(Aabb::empty(), |a, i| a.union(&aabbs[*i]));
+    let centroid_bounds = Aabb::from_points(idxs.iter().map(|i| &centroids[*i]));
+
+    let leaf = BvhNode { aabb, kind: BvhNodeKind::Leaf { first, count: idxs.len() } };
+    if idxs.len() <= 1 {
+        return vec![leaf];
+    }
+
+    let mid = match sah_split(aabbs, centroids, idxs, &aabb, &centroid_bounds) {
+        Some(mid) => mid,
+        None if idxs.len() <= MAX_LEAF_PRIMS => return vec![leaf],
+        None => median_split(centroids, idxs, &centroid_bounds),
+    };
+
+    let parallel = idxs.len() >= PARALLEL_BUILD_MIN;
+    let (low_idxs, high_idxs) = idxs.split_at_mut(mid);
+    let mut build_low = || build_subtree(aabbs, centroids, low_idxs, first);
+    let mut build_high = || build_subtree(aabbs, centroids, high_idxs, first + mid);
+    let (low, high) = if parallel { rayon::join(build_low, build_high) } else { (build_low(), build_high()) };
+
+    let high_root = 1 + low.len();
+    let mut nodes = Vec::with_capacity(high_root + high.len());
+    nodes.push(BvhNode { aabb, kind: BvhNodeKind::Inner { low: 1, high: high_root } });
+    for (offset, subtree) in [(1, low), (high_root, high)] {
+        nodes.extend(subtree.into_iter().map(|mut node| {
+            if let BvhNodeKind::Inner { low, high } = &mut node.kind {
+                *low += offset;
+                *high += offset;
+            }
+            node
+        }));
+    }
+    nodes
+}
+
+// bins the centroids along each axis and takes the cheapest plane between bins by the surface area heuristic,
+// partitioning idxs around it. None when no split beats leaving them all in one leaf
+fn sah_split(aabbs: &[Aabb], centroids: &[Vector3<f32>], idxs: &mut [usize], aabb: &Aabb, centroid_bounds: &Aabb) -> Option<usize> {
+    let leaf_cost = idxs.len() as f32;
+    let parent_area = aabb.surface_area();
+    let mut best: Option<(f32, usize, usize)> = None; // cost, axis, first bin above the plane
+    for axis in 0..3 {
+        let span = centroid_bounds.bounds[axis];
+        if span.high - span.low <= 0.0 {
+            continue;
+        }
+        let mut bins = [(Aabb::empty(), 0usize); SAH_BINS];
+        for i in idxs.iter() {
+            let bin = &mut bins[bin_of(centroids[*i][axis], &span)];
+            *bin = (bin.0.union(&aabbs[*i]), bin.1 + 1);
+        }
+
+        // sweep from the high end so each plane knows what's above it
+        let mut above = [(0.0, 0usize); SAH_BINS];
+        let mut acc = (Aabb::empty(), 0);
+        for b in (1..SAH_BINS).rev() {
+            acc = (acc.0.union(&bins[b].0), acc.1 + bins[b].1);
+            above[b] = (acc.0.surface_area(), acc.1);
+        }
+        let mut below = (Aabb::empty(), 0);
+        for b in 1..SAH_BINS {
+            below = (below.0.union(&bins[b - 1].0), below.1 + bins[b - 1].1);
+            let (above_area, above_count) = above[b];
+            if below.1 == 0 || above_count == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST + (below.0.surface_area() * below.1 as f32 + above_area * above_count as f32) / parent_area;
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+
+    let (cost, axis, split_bin) = best?;
+    if cost >= leaf_cost && idxs.len() <= MAX_LEAF_PRIMS {
+        return None;
+    }
+    let span = centroid_bounds.bounds[axis];
+    let mut mid = 0;
+    for i in 0..idxs.len() {
+        if bin_of(centroids[idxs[i]][axis], &span) < split_bin {
+            idxs.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some(mid)
+}
+
+fn bin_of(c: f32, span: &PlaneBounds) -> usize {
+    (((c - span.low) / (span.high - span.low) * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+}
+
+// halves along the widest spread of centroids, for when the sah can't separate things (e.g. infinite boxes)
+fn median_split(centroids: &[Vector3<f32>], idxs: &mut [usize], centroid_bounds: &Aabb) -> usize {
+    let axis = (0..3)
+        .max_by(|a, b| {
+            let ext = |ax: &usize| centroid_bounds.bounds[*ax].high - centroid_bounds.bounds[*ax].low;
+            ext(a).total_cmp(&ext(b))
+        })
+        .unwrap();
+    let mid = idxs.len() / 2;
+    idxs.select_nth_unstable_by(mid, |a, b| centroids[*a][axis].total_cmp(&centroids[*b][axis]));
+    mid
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::accel::TreeStats;
+    use crate::ray::Ray;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    // rendering goes through the WideBvh collapsed from these, plain binary traversal is kept as a reference here
+    impl Bvh {
+        pub fn stats(&self) -> TreeStats {
+            let mut stats = TreeStats::default();
+            let mut stack: Vec<(usize, usize)> = if self.nodes.is_empty() { vec![] } else { vec![(0, 0)] };
+            while let Some((node_idx, depth)) = stack.pop() {
+                match self.nodes[node_idx].kind {
+                    BvhNodeKind::Leaf { count, .. } => stats.leaf(count, depth),
+                    BvhNodeKind::Inner { low, high } => {
+                        stats.branch(depth);
+                        stack.extend([(low, depth + 1), (high, depth + 1)]);
+                    },
+                }
+            }
+            stats
+        }
+
+        // closest primitive hit along ray, hit_prim gives the ray length for a primitive index if it gets hit
+        pub fn closest_hit<F: FnMut(usize) -> Option<f32>>(&self, ray: &Ray, mut hit_prim: F) -> Option<(usize, f32)> {
+            if self.nodes.is_empty() {
+                return None;
+            }
+            let inv_d = ray.d.map(|d| 1.0 / d);
+            let mut closest: Option<(usize, f32)> = None;
+            let mut max_l = f32::INFINITY;
+            let mut stack: Vec<usize> = vec![0];
+
+            while let Some(node_idx) = stack.pop() {
+                let node = &self.nodes[node_idx];
+                if slab_entry(&node.aabb, &ray.o, &inv_d, max_l).is_none() {
+                    continue;
+                }
+                match node.kind {
+                    BvhNodeKind::Leaf { first, count } => {
+                        for prim in &self.prim_idxs[first..first + count] {
+                            if let Some(l) = hit_prim(*prim) {
+                                if l < max_l {
+                                    max_l = l;
+                                    closest = Some((*prim, l));
+                                }
+                            }
+                        }
+                    },
+                    BvhNodeKind::Inner { low, high } => {
+                        // visit the nearer child first so max_l shrinks sooner
+                        let low_l = slab_entry(&self.nodes[low].aabb, &ray.o, &inv_d, max_l);
+                        let high_l = slab_entry(&self.nodes[high].aabb, &ray.o, &inv_d, max_l);
+                        match (low_l, high_l) {
+                            (Some(ll), Some(hl)) => {
+                                if ll < hl { stack.extend([high, low]); } else { stack.extend([low, high]); }
+                            },
+                            (Some(_), None) => stack.push(low),
+                            (None, Some(_)) => stack.push(high),
+                            (None, None) => {},
+                        }
+                    },
+                }
+            }
+
+            closest
+        }
+    }
+
+    // entry distance of ray into aabb if it enters before max_l
+    fn slab_entry(aabb: &Aabb, o: &Vector3<f32>, inv_d: &Vector3<f32>, max_l: f32) -> Option<f32> {
+        let mut entry = 0.0f32;
+        let mut exit = max_l;
+        for a in 0..3 {
+            let l0 = (aabb.bounds[a].low - o[a]) * inv_d[a];
+            let l1 = (aabb.bounds[a].high - o[a]) * inv_d[a];
+            let (near, far) = if l0 < l1 { (l0, l1) } else { (l1, l0) };
+            entry = entry.max(near); // max/min drop NaN from 0 * inf, treating that axis as unbounded
+            exit = exit.min(far);
+        }
+        if entry <= exit { Some(entry) } else { None }
+    }
+
+    fn sphere_hit(c: &Vector3<f32>, r: f32, ray: &Ray) -> Option<f32> {
+        let oc = ray.o - c;
+        let b = ray.d.dot(&oc);
+        let disc = b * b - (oc.dot(&oc) - r * r);
+        if disc < 0.0 {
+            return None;
+        }
+        Some(-b - disc.sqrt()).filter(|l| *l > 0.0)
+    }
+
+    #[test]
+    fn test_sah_tree_finds_the_same_hits_as_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        // a dense clump and a sparse spread, the kind of unevenness the sah is for
+        let spheres: Vec<(Vector3<f32>, f32)> = (0..600).map(|i| {
+            let spread = if i % 3 == 0 { 40.0 } else { 2.0 };
+            let c = Vector3::from_fn(|_, _| rng.gen_range(-spread..spread));
+            (c, rng.gen_range(0.05..0.5))
+        }).collect();
+        let aabbs: Vec<Aabb> = spheres.iter().map(|(c, r)| Aabb {
+            bounds: [0, 1, 2].map(|a| PlaneBounds { low: c[a] - r, high: c[a] + r }),
+        }).collect();
+        let bvh = Bvh::build(&aabbs);
+
+        for _ in 0..2000 {
+            let o = Vector3::from_fn(|_, _| rng.gen_range(-50.0..50.0));
+            let d = (Vector3::from_fn(|_, _| rng.gen_range(-3.0..3.0)) - o).normalize();
+            let ray = Ray { o, d };
+            let expected = spheres.iter().enumerate()
+                .filter_map(|(i, (c, r))| sphere_hit(c, *r, &ray).map(|l| (i, l)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let got = bvh.closest_hit(&ray, |i| sphere_hit(&spheres[i].0, spheres[i].1, &ray));
+            assert_eq!(got.map(|(i, _)| i), expected.map(|(i, _)| i));
+        }
+    }
+
+    #[test]
+    fn test_parallel_build_keeps_every_primitive_once() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let n = PARALLEL_BUILD_MIN * 3;
+        let spheres: Vec<(Vector3<f32>, f32)> = (0..n).map(|_| (Vector3::from_fn(|_, _| rng.gen_range(-100.0..100.0)), 0.3)).collect();
+        let aabbs: Vec<Aabb> = spheres.iter().map(|(c, r)| Aabb {
+            bounds: [0, 1, 2].map(|a| PlaneBounds { low: c[a] - r, high: c[a] + r }),
+        }).collect();
+        let bvh = Bvh::build(&aabbs);
+
+        let mut seen = bvh.prim_idxs.clone();
+        seen.sort();
+        assert_eq!(seen, (0..n).collect::<Vec<_>>());
+        let stats = bvh.stats();
+        assert_eq!(stats.leaf_prims, n);
+        assert_eq!(stats.nodes, bvh.nodes.len());
+
+        for _ in 0..200 {
+            let o = Vector3::from_fn(|_, _| rng.gen_range(-120.0..120.0));
+            let d = (Vector3::from_fn(|_, _| rng.gen_range(-20.0..20.0)) - o).normalize();
+            let ray = Ray { o, d };
+            let expected = spheres.iter().enumerate()
+                .filter_map(|(i, (c, r))| sphere_hit(c, *r, &ray).map(|l| (i, l)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let got = bvh.closest_hit(&ray, |i| sphere_hit(&spheres[i].0, spheres[i].1, &ray));
+            assert_eq!(got.map(|(i, _)| i), expected.map(|(i, _)| i));
+        }
+    }
+}
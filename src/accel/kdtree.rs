@@ -1,7 +1,7 @@
 use crate::elements::Renderable;
-use super::{Aabb, PlaneBounds};
+use super::{Aabb, PlaneBounds, TreeStats, PARALLEL_BUILD_MIN};
+use rayon::prelude::*;
 use crate::ray::{Ray, closest_ray_hit, ClosestRayHit};
-use nalgebra::Vector3;
 
 // TODO: Make this GPU compatible
 // pub struct GPUKdTree<'k> {
@@ -23,7 +23,7 @@ pub enum Node<'n> {
 }
 
 impl<'k> KdTree<'k> {
-    pub fn build(elems_and_aabbs: &Vec<(usize, Renderable<'k>, Aabb)>, unconditional: &'k Vec<(usize, Renderable<'k>)>, max_build_depth: usize) -> Self {
+    pub fn build(elems_and_aabbs: &Vec<(usize, Renderable<'k>, Aabb)>, unconditional: &'k Vec<(usize, Renderable<'k>)>) -> Self {
         let aabbs: Vec<&Aabb> = elems_and_aabbs.iter().map(|(_,_,aabb)| aabb).collect();
 
         let aabb = {
@@ -51,10 +51,25 @@ impl<'k> KdTree<'k> {
         KdTree {
             aabb,
             unconditional,
-            node: node_from_elems(&elems_and_aabbs.iter().map(|(i, e, aabb)| (*i, *e, aabb)).collect(), 0, max_build_depth),
+            node: node_from_elems(&elems_and_aabbs.iter().map(|(i, e, aabb)| (*i, *e, aabb)).collect(), &aabb, 0, auto_depth(elems_and_aabbs.len())),
         }
     }
 
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        let mut stack: Vec<(&Node, usize)> = vec![(&self.node, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            match node {
+                Node::Leaf(elems) => stats.leaf(elems.len(), depth),
+                Node::Branch { low, high, .. } => {
+                    stats.branch(depth);
+                    stack.extend([(low.as_ref(), depth + 1), (high.as_ref(), depth + 1)]);
+                },
+            }
+        }
+        stats
+    }
+
     pub fn closest_ray_hit(&self, ray: &Ray) -> ClosestRayHit {
         let enters_domain = self.aabb.get_entry_exit(ray);
         match enters_domain {
@@ -102,37 +117,229 @@ impl<'k> KdTree<'k> {
 
         closest_ray_hit(ray, self.unconditional.iter().map(|e| *e))
     }
-}
 
-fn node_from_elems<'n>(elems_and_aabbs: &Vec<(usize, Renderable<'n>, &Aabb)>, depth: usize, max_depth: usize) -> Node<'n> {
-    let axis = depth % 3;
-    if depth > max_depth || elems_and_aabbs.len() <= 1 {
-        Node::Leaf(elems_and_aabbs.iter().map(|(i, e, _)| (*i, *e)).collect())
-    } else {
-        let aabbs: Vec<&Aabb> = elems_and_aabbs.iter().map(|(_,_,aabb)| *aabb).collect();
-        let split = (&aabbs).into_iter().map(|aabb| aabb.centroid()).sum::<Vector3<f32>>() / (aabbs.len() as f32);
+    // whether anything gets hit closer than max_l, for shadow rays. same walk as stack_search but nodes past max_l
+    // are never entered, and it's over at the first hit found in any leaf, closest or not
+    pub fn occluded(&self, ray: &Ray, max_l: f32) -> bool {
+        if self.unconditional.iter().any(|(_, e)| e.occludes(ray, max_l)) {
+            return true;
+        }
+        let (entry_t, exit_t) = match self.aabb.get_entry_exit(ray) {
+            Some(((_, entry_t), (_, exit_t))) => (entry_t, exit_t.min(max_l)),
+            None => return false,
+        };
+        if entry_t > exit_t {
+            return false;
+        }
+        let mut stack: Vec<(&Node, f32, f32)> = vec![(&self.node, entry_t, exit_t)];
+        // elements straddling several leaves only get tested in the first, big ones would be tested in every leaf
+        // the ray crosses otherwise
+        let mut tested: Vec<usize> = vec![]; // sorted, a ray only ever sees a handful so it beats hashing
 
-        let (low, high): (Vec<(usize, Renderable, &Aabb)>, Vec<(usize, Renderable, &Aabb)>) = {
-            let mut low: Vec<(usize, Renderable, &Aabb)> = vec![];
-            let mut high: Vec<(usize, Renderable, &Aabb)> = vec![];
-    
-            elems_and_aabbs.iter().for_each(|(i, e, aabb)| {
-                // this can handle case of element in both nodes
-                if aabb.bounds[axis].high >= split[axis] {
-                    high.push((*i, *e, aabb));
+        use Node::*;
+        while let Some((mut current_node, entry_t, mut exit_t)) = stack.pop() {
+            while let Branch {axis, split, low, high} = current_node {
+                let a = *axis;
+                let d = if ray.d[a].abs() < crate::EPS { 
+                    if ray.d[a] < 0.0 { -crate::EPS } else { crate:: EPS}
+                } else { ray.d[a] };
+                let t = (split - ray.o[a]) / d;
+                let (near, far) = if d > 0.0 {(low, high)} else {(high, low)};
+                if t >= exit_t {
+                    current_node = near;
+                } else if t <= entry_t {
+                    current_node = far;
+                } else {
+                    stack.push((far, t, exit_t));
+                    current_node = near;
+                    exit_t = t;
                 }
-                if aabb.bounds[axis].low <= split[axis] {
-                    low.push((*i, *e, aabb));
+            }
+
+            if let Leaf(elems) = current_node {
+                for (i, e) in elems {
+                    match tested.binary_search(i) {
+                        Ok(_) => continue,
+                        Err(at) => tested.insert(at, *i),
+                    }
+                    if e.occludes(ray, max_l) {
+                        return true;
+                    }
                 }
-            });
-            (low, high)
-        };
+            }
+        }
+        false
+    }
+}
+
+// sah costs, relative to intersecting one primitive
+const TRAVERSAL_COST: f32 = 0.7;
+const EMPTY_BONUS: f32 = 0.2; // cut off this share of the cost for a plane that leaves one side empty
+
+// deep enough for the sah to find what it wants on any sane scene, it stops on its own long before this mostly
+pub fn auto_depth(prims: usize) -> usize {
+    (8.0 + 1.3 * (prims.max(1) as f32).log2()) as usize
+}
+
+fn node_from_elems<'n>(elems_and_aabbs: &Vec<(usize, Renderable<'n>, &Aabb)>, bounds: &Aabb, depth: usize, max_depth: usize) -> Node<'n> {
+    let leaf = || Node::Leaf(elems_and_aabbs.iter().map(|(i, e, _)| (*i, *e)).collect());
+    if depth >= max_depth || elems_and_aabbs.len() <= 1 {
+        return leaf();
+    }
+    let parallel = elems_and_aabbs.len() >= PARALLEL_BUILD_MIN;
+    let clipped: Vec<Aabb> = elems_and_aabbs.par_iter().with_min_len(PARALLEL_BUILD_MIN).map(|(_, _, aabb)| aabb.intersection(bounds)).collect();
+    let (axis, split) = match sah_plane(&clipped, bounds) {
+        Some(plane) => plane,
+        None => return leaf(),
+    };
+
+    // this can handle case of element in both nodes, flat ones lying in the plane go low
+    let side = |goes: fn(&PlaneBounds, f32) -> bool| -> Vec<(usize, Renderable, &Aabb)> {
+        elems_and_aabbs.par_iter().zip(&clipped)
+            .with_min_len(PARALLEL_BUILD_MIN)
+            .filter(|(_, c)| goes(&c.bounds[axis], split))
+            .map(|((i, e, aabb), _)| (*i, *e, *aabb))
+            .collect()
+    };
+    let low = side(|b, split| b.low < split || b.high <= split);
+    let high = side(|b, split| b.high > split);
+
+    let (mut low_bounds, mut high_bounds) = (*bounds, *bounds);
+    low_bounds.bounds[axis].high = split;
+    high_bounds.bounds[axis].low = split;
+    let build_low = || Box::new(node_from_elems(&low, &low_bounds, depth + 1, max_depth));
+    let build_high = || Box::new(node_from_elems(&high, &high_bounds, depth + 1, max_depth));
+    let (low, high) = if parallel { rayon::join(build_low, build_high) } else { (build_low(), build_high()) };
+    Node::Branch { axis, split, low, high }
+}
+
+// sweeps every primitive's clipped bounds along each axis as a candidate plane, None when splitting isn't worth
+// more than intersecting everything in one leaf
+fn sah_plane(clipped: &[Aabb], bounds: &Aabb) -> Option<(usize, f32)> {
+    let on_axis = |axis: usize| sah_on_axis(clipped, bounds, axis).map(|(cost, p)| (cost, axis, p));
+    let cheapest = |a: &(f32, usize, f32), b: &(f32, usize, f32)| a.0.total_cmp(&b.0);
+    let best = if clipped.len() >= PARALLEL_BUILD_MIN {
+        (0..3).into_par_iter().filter_map(on_axis).min_by(cheapest)
+    } else {
+        (0..3).filter_map(on_axis).min_by(cheapest)
+    };
+
+    best.filter(|(cost, _, _)| *cost < clipped.len() as f32).map(|(_, axis, p)| (axis, p))
+}
 
-        Node::Branch { 
-            axis, 
-            split: split[axis], 
-            low: Box::new(node_from_elems(&low, depth + 1, max_depth)), 
-            high: Box::new(node_from_elems(&high, depth + 1, max_depth))
+// cheapest plane and its cost along one axis
+fn sah_on_axis(clipped: &[Aabb], bounds: &Aabb, axis: usize) -> Option<(f32, f32)> {
+    let area = bounds.surface_area();
+    let PlaneBounds { low, high } = bounds.bounds[axis];
+    // ends sort before flat primitives before starts at the same spot, so a plane there sees them right
+    let mut events: Vec<(f32, u8)> = clipped.iter().flat_map(|c| {
+        let b = c.bounds[axis];
+        if b.low == b.high { vec![(b.low, 1)] } else { vec![(b.low, 2), (b.high, 0)] }
+    }).collect();
+    events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    let mut best: Option<(f32, f32)> = None;
+    let (mut below, mut above) = (0, clipped.len());
+    let mut e = 0;
+    while e < events.len() {
+        let p = events[e].0;
+        let mut counts = [0; 3];
+        while e < events.len() && events[e].0 == p {
+            counts[events[e].1 as usize] += 1;
+            e += 1;
+        }
+        let [ending, flat, starting] = counts;
+        above -= ending + flat;
+        if p > low && p < high {
+            let (mut low_part, mut high_part) = (*bounds, *bounds);
+            low_part.bounds[axis].high = p;
+            high_part.bounds[axis].low = p;
+            let (n_low, n_high) = (below + flat, above);
+            let bonus = if n_low == 0 || n_high == 0 { 1.0 - EMPTY_BONUS } else { 1.0 };
+            let cost = bonus * (TRAVERSAL_COST + (low_part.surface_area() * n_low as f32 + high_part.surface_area() * n_high as f32) / area);
+            if best.is_none_or(|(c, _)| cost < c) {
+                best = Some((cost, p));
+            }
+        }
+        below += starting + flat;
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nalgebra::Vector3;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use crate::elements::sphere::{Sphere, Coloring};
+    use crate::elements::triangle::{FreeTriangle, FreeNorm, FreeColor};
+    use crate::material::{UniformDiffuseSpec, DivertRayMethod};
+
+    fn mat() -> UniformDiffuseSpec {
+        UniformDiffuseSpec { emissive: None, divert_ray: DivertRayMethod::Diff }
+    }
+
+    fn triangle(verts: [Vector3<f32>; 3]) -> FreeTriangle {
+        FreeTriangle {
+            verts,
+            norm: FreeNorm { face: (verts[1] - verts[0]).cross(&(verts[2] - verts[0])).normalize(), verts: None, normal_map: None },
+            rgb: FreeColor { factor: Vector3::zeros(), verts: None, texture: None },
+            diverts_ray: mat(),
+            type_name: "FreeTriangle",
+        }
+    }
+
+    #[test]
+    fn test_sah_tree_finds_the_same_hits_as_brute_force() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let mut point = |spread: f32| Vector3::from_fn(|_, _| rng.gen_range(-spread..spread));
+        let spheres: Vec<Sphere> = (0..150).map(|_| Sphere {
+            c: point(20.0),
+            r: 0.5,
+            coloring: Coloring::Solid(Vector3::zeros()),
+            mat: mat(),
+            animation: None,
+            tracks: None,
+        }).collect();
+        let mut triangles: Vec<FreeTriangle> = vec![];
+        for i in 0..450 {
+            let c = point(20.0);
+            let verts = [c, c + point(2.0), c + point(2.0)];
+            triangles.push(triangle(match i % 3 {
+                // flat along one axis, and lying in a few shared planes so split candidates tie
+                0 => {
+                    let a = i / 3 % 3;
+                    let plane = ((i / 9) % 5) as f32 * 4.0 - 8.0;
+                    verts.map(|mut v| { v[a] = plane; v })
+                },
+                1 => verts,
+                // a stack of slivers on the same plane, edge on to rays along z
+                _ => verts.map(|mut v| { v.z = 3.0; v }),
+            }));
+        }
+        let elems: Vec<(usize, Renderable)> = spheres.iter().map(|s| s as Renderable)
+            .chain(triangles.iter().map(|t| t as Renderable))
+            .enumerate().collect();
+        let elems_and_aabbs: Vec<_> = elems.iter().map(|(i, e)| (*i, *e, e.give_aabb().unwrap())).collect();
+        let unconditional = vec![];
+        let kdtree = KdTree::build(&elems_and_aabbs, &unconditional);
+        assert!(kdtree.stats().leaves > 1);
+
+        // mostly aimed at some element so most rays hit something, every fourth one axis aligned
+        let axis_dirs = [Vector3::x(), -Vector3::y(), Vector3::z(), -Vector3::z()];
+        for i in 0..3000 {
+            let o = point(30.0);
+            let target = elems_and_aabbs[i * 7 % elems_and_aabbs.len()].2.bounds.map(|b| (b.low + b.high) / 2.0);
+            let d = match i % 4 {
+                3 => axis_dirs[i % axis_dirs.len()],
+                _ => (Vector3::from(target) + point(0.5) - o).normalize(),
+            };
+            let ray = Ray { o, d };
+            let (hrs, idxo) = closest_ray_hit(&ray, elems.iter().copied());
+            let expected = idxo.map(|idx| hrs[idx].1.as_ref().unwrap().l.0);
+            let (hrs, idxo) = kdtree.closest_ray_hit(&ray);
+            let got = idxo.map(|idx| hrs[idx].1.as_ref().unwrap().l.0);
+            assert_eq!(got, expected, "ray {:?} along {:?}", ray.o, ray.d);
         }
     }
-}
\ No newline at end of file
+}
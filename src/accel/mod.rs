@@ -1,6 +1,14 @@
 
 mod aabb;
 mod kdtree;
+mod bvh;
+mod scene;
+mod stats;
+mod wide;
 
 pub use aabb::*;
-pub use kdtree::KdTree;
\ No newline at end of file
+pub use kdtree::{KdTree, auto_depth};
+pub use bvh::Bvh;
+pub use wide::{WideBvh, F4, V4, cross4, dot4, splat3};
+pub use scene::{AccelKind, SceneAccel, take_traced_rays};
+pub use stats::{TreeStats, PARALLEL_BUILD_MIN};
\ No newline at end of file
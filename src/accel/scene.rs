@@ -0,0 +1,145 @@
+use serde::Deserialize;
+use std::cell::Cell;
+use crate::elements::Renderable;
+use crate::ray::{Ray, HitResult, closest_ray_hit, ClosestRayHit};
+use super::{Aabb, WideBvh, KdTree, TreeStats};
+
+// which structure the cpu renderer traces scene rays against
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum AccelKind {
+    #[default]
+    KdTree, // sah planes, stops splitting on its own
+    Bvh, // binned sah, sizes its own leaves
+}
+
+thread_local! {
+    // rays traced on this thread since the last take_traced_rays, a shared atomic would bounce between cores
+    static TRACED: Cell<u64> = const { Cell::new(0) };
+}
+
+// rays traced through any SceneAccel on rayon's threads, resetting the count
+pub fn take_traced_rays() -> u64 {
+    rayon::broadcast(|_| TRACED.take()).into_iter().sum()
+}
+
+pub enum SceneAccel<'a> {
+    KdTree(KdTree<'a>),
+    Bvh(SceneBvh<'a>),
+}
+
+impl<'a> SceneAccel<'a> {
+    pub fn build(kind: AccelKind, elems_and_aabbs: &Vec<(usize, Renderable<'a>, Aabb)>, unconditional: &'a Vec<(usize, Renderable<'a>)>) -> Self {
+        match kind {
+            AccelKind::KdTree => SceneAccel::KdTree(KdTree::build(elems_and_aabbs, unconditional)),
+            AccelKind::Bvh => SceneAccel::Bvh(SceneBvh::build(elems_and_aabbs, unconditional)),
+        }
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        match self {
+            SceneAccel::KdTree(kdtree) => kdtree.stats(),
+            SceneAccel::Bvh(bvh) => bvh.bvh.stats(),
+        }
+    }
+
+    pub fn closest_ray_hit(&self, ray: &Ray) -> ClosestRayHit {
+        TRACED.set(TRACED.get() + 1);
+        match self {
+            SceneAccel::KdTree(kdtree) => kdtree.closest_ray_hit(ray),
+            SceneAccel::Bvh(bvh) => bvh.closest_ray_hit(ray),
+        }
+    }
+
+    // whether anything is in the way closer than max_l, stopping at the first thing found
+    pub fn occluded(&self, ray: &Ray, max_l: f32) -> bool {
+        TRACED.set(TRACED.get() + 1);
+        match self {
+            SceneAccel::KdTree(kdtree) => kdtree.occluded(ray, max_l),
+            SceneAccel::Bvh(bvh) => bvh.occluded(ray, max_l),
+        }
+    }
+}
+
+// the primitive bvh over the scene's bounded elements, unbounded ones (sky, environment) get tested on every ray
+pub struct SceneBvh<'a> {
+    bvh: WideBvh,
+    elems: Vec<(usize, Renderable<'a>)>,
+    unconditional: &'a Vec<(usize, Renderable<'a>)>,
+}
+
+impl<'a> SceneBvh<'a> {
+    pub fn build(elems_and_aabbs: &[(usize, Renderable<'a>, Aabb)], unconditional: &'a Vec<(usize, Renderable<'a>)>) -> Self {
+        let aabbs: Vec<Aabb> = elems_and_aabbs.iter().map(|(_, _, aabb)| *aabb).collect();
+        SceneBvh {
+            bvh: WideBvh::build(&aabbs),
+            elems: elems_and_aabbs.iter().map(|(i, e, _)| (*i, *e)).collect(),
+            unconditional,
+        }
+    }
+
+    // same shape of result as closest_ray_hit, just without the misses from the tree
+    pub fn closest_ray_hit(&self, ray: &Ray) -> ClosestRayHit {
+        let mut closest: Option<(usize, HitResult)> = None;
+        self.bvh.closest_hit(ray, |prim| {
+            let (i, elem) = self.elems[prim];
+            let hit = elem.intersect(ray).filter(|hr| hr.l.0 >= crate::EPS * 20.0)?; // same self hit guard as closest_ray_hit
+            let l = hit.l.0;
+            if closest.as_ref().is_none_or(|(_, c)| l < c.l.0) {
+                closest = Some((i, hit));
+            }
+            Some(l)
+        });
+
+        let (mut hit_results, idxo) = closest_ray_hit(ray, self.unconditional.iter().copied());
+        match closest {
+            Some((i, hit)) if idxo.is_none_or(|u| hit_results[u].1.as_ref().unwrap().l.0 > hit.l.0) => {
+                hit_results.push((i, Some(hit)));
+                let idx = hit_results.len() - 1;
+                (hit_results, Some(idx))
+            },
+            _ => (hit_results, idxo),
+        }
+    }
+
+    pub fn occluded(&self, ray: &Ray, max_l: f32) -> bool {
+        self.unconditional.iter().any(|(_, e)| e.occludes(ray, max_l))
+            || self.bvh.any_hit(ray, max_l, |prim, max_l| self.elems[prim].1.occludes(ray, max_l))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nalgebra::Vector3;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use crate::elements::sphere::{Sphere, Coloring};
+    use crate::material::{UniformDiffuseSpec, DivertRayMethod};
+
+    #[test]
+    fn test_occlusion_agrees_with_the_closest_hit() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let spheres: Vec<Sphere> = (0..300).map(|_| Sphere {
+            c: Vector3::from_fn(|_, _| rng.gen_range(-20.0..20.0)),
+            r: rng.gen_range(0.2..2.0),
+            coloring: Coloring::Solid(Vector3::zeros()),
+            mat: UniformDiffuseSpec { emissive: None, divert_ray: DivertRayMethod::Diff },
+            animation: None,
+            tracks: None,
+        }).collect();
+        let elems: Vec<(usize, Renderable)> = spheres.iter().enumerate().map(|(i, s)| (i, s as Renderable)).collect();
+        let elems_and_aabbs: Vec<_> = elems.iter().map(|(i, e)| (*i, *e, e.give_aabb().unwrap())).collect();
+        let unconditional = vec![];
+
+        for kind in [AccelKind::KdTree, AccelKind::Bvh] {
+            let accel = SceneAccel::build(kind, &elems_and_aabbs, &unconditional);
+            for _ in 0..1000 {
+                let o = Vector3::from_fn(|_, _| rng.gen_range(-25.0..25.0));
+                let ray = Ray { o, d: (Vector3::from_fn(|_, _| rng.gen_range(-5.0..5.0)) - o).normalize() };
+                let max_l = rng.gen_range(1.0..40.0);
+                let (hrs, idxo) = closest_ray_hit(&ray, elems.iter().copied());
+                let expected = idxo.is_some_and(|idx| hrs[idx].1.as_ref().unwrap().l.0 < max_l);
+                assert_eq!(accel.occluded(&ray, max_l), expected, "{:?}", kind);
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+// below this many primitives a node's subtrees get built on the same thread, rayon's overhead isn't worth it
+pub const PARALLEL_BUILD_MIN: usize = 4096;
+
+// shape of a built tree, for the build report
+#[derive(Default, Clone, Copy, Debug)]
+pub struct TreeStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub empty_leaves: usize,
+    pub leaf_prims: usize, // summed over leaves, more than the primitive count when a kd-tree puts one in several
+    pub max_leaf: usize,
+    pub max_depth: usize,
+}
+
+impl TreeStats {
+    pub fn branch(&mut self, depth: usize) {
+        self.nodes += 1;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    pub fn leaf(&mut self, prims: usize, depth: usize) {
+        self.branch(depth);
+        self.leaves += 1;
+        self.empty_leaves += (prims == 0) as usize;
+        self.leaf_prims += prims;
+        self.max_leaf = self.max_leaf.max(prims);
+    }
+
+    // totals over several trees, e.g. the per-mesh bvhs of one model
+    pub fn combined(self, other: TreeStats) -> TreeStats {
+        TreeStats {
+            nodes: self.nodes + other.nodes,
+            leaves: self.leaves + other.leaves,
+            empty_leaves: self.empty_leaves + other.empty_leaves,
+            leaf_prims: self.leaf_prims + other.leaf_prims,
+            max_leaf: self.max_leaf.max(other.max_leaf),
+            max_depth: self.max_depth.max(other.max_depth),
+        }
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let occupied = (self.leaves - self.empty_leaves).max(1);
+        write!(f, "{} nodes, {} leaves ({} empty), {:.1} primitives per occupied leaf (max {}), depth {}",
+            self.nodes, self.leaves, self.empty_leaves, self.leaf_prims as f32 / occupied as f32, self.max_leaf, self.max_depth)
+    }
+}
@@ -0,0 +1,399 @@
+use std::ops::{Add, Sub, Mul};
+use super::{Aabb, Bvh, TreeStats};
+use super::bvh::BvhNodeKind;
+use crate::ray::Ray;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+// four f32 lanes. sse on x86_64 (always there), elsewhere plain loops over the lanes
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[repr(C, align(16))]
+pub struct F4(pub [f32; 4]);
+
+// lanewise op on self and o, given as a sse intrinsic call and the same for one lane
+macro_rules! lanewise {
+    ($name:ident, ($a:ident, $b:ident) => $sse:expr, $lane:expr) => {
+        #[inline(always)]
+        pub fn $name(self, o: F4) -> F4 {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use std::arch::x86_64::*;
+                // F4 is 16 bytes aligned to 16, same as __m128
+                let ($a, $b) = unsafe { (std::mem::transmute::<F4, __m128>(self), std::mem::transmute::<F4, __m128>(o)) };
+                unsafe { std::mem::transmute::<__m128, F4>($sse) }
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            {
+                let f = |$a: f32, $b: f32| $lane;
+                let (a, b) = (self.0, o.0);
+                F4([f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])])
+            }
+        }
+    };
+}
+
+impl F4 {
+    #[inline(always)]
+    pub fn splat(f: f32) -> F4 {
+        F4([f; 4])
+    }
+
+    // sse min/max give back their second operand when either is NaN, so a NaN in o gives self back
+    lanewise!(min, (a, b) => _mm_min_ps(b, a), if b < a { b } else { a });
+    lanewise!(max, (a, b) => _mm_max_ps(b, a), if b > a { b } else { a });
+    lanewise!(plus, (a, b) => _mm_add_ps(a, b), a + b);
+    lanewise!(minus, (a, b) => _mm_sub_ps(a, b), a - b);
+    lanewise!(times, (a, b) => _mm_mul_ps(a, b), a * b);
+    lanewise!(over, (a, b) => _mm_div_ps(a, b), a / b);
+
+    // bit per lane where self <= o
+    #[inline(always)]
+    pub fn le_mask(self, o: F4) -> u32 {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::*;
+            unsafe {
+                let (a, b) = (std::mem::transmute::<F4, __m128>(self), std::mem::transmute::<F4, __m128>(o));
+                _mm_movemask_ps(_mm_cmple_ps(a, b)) as u32
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            (0..4).fold(0, |mask, lane| mask | ((self.0[lane] <= o.0[lane]) as u32) << lane)
+        }
+    }
+
+    #[inline(always)]
+    pub fn abs(self) -> F4 {
+        self.max(F4::splat(0.0).minus(self))
+    }
+
+    #[inline(always)]
+    pub fn recip(self) -> F4 {
+        F4::splat(1.0).over(self)
+    }
+}
+
+impl Add for F4 {
+    type Output = F4;
+    #[inline(always)]
+    fn add(self, o: F4) -> F4 { self.plus(o) }
+}
+
+impl Sub for F4 {
+    type Output = F4;
+    #[inline(always)]
+    fn sub(self, o: F4) -> F4 { self.minus(o) }
+}
+
+impl Mul for F4 {
+    type Output = F4;
+    #[inline(always)]
+    fn mul(self, o: F4) -> F4 { self.times(o) }
+}
+
+// three components of four vectors, structure of arrays
+pub type V4 = [F4; 3];
+
+#[inline(always)]
+pub fn cross4(a: &V4, b: &V4) -> V4 {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+#[inline(always)]
+pub fn dot4(a: &V4, b: &V4) -> F4 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn splat3(v: &Vector3<f32>) -> V4 {
+    [0, 1, 2].map(|a| F4::splat(v[a]))
+}
+
+// bvh with four children per node, collapsed from the binary sah Bvh, so one node visit tests four boxes at once
+// and the tree is about half as deep. leaves keep the binary tree's ranges of primitives
+#[derive(Serialize, Deserialize)]
+pub struct WideBvh {
+    nodes: Vec<WideNode>,
+    leaves: Vec<(usize, usize)>, // first, count in prim_idxs
+    prim_idxs: Vec<usize>,
+    aabb: Aabb,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WideNode {
+    low: V4, // children's bounds, empty slots are inverted boxes that no ray enters
+    high: V4,
+    children: [WideChild; 4],
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum WideChild {
+    Empty,
+    Node(u32),
+    Leaf(u32),
+}
+
+impl WideBvh {
+    pub fn build(aabbs: &[Aabb]) -> Self {
+        let bvh = Bvh::build(aabbs);
+        let mut wide = WideBvh { nodes: vec![], leaves: vec![], prim_idxs: vec![], aabb: bvh.aabb() };
+        if !bvh.nodes.is_empty() {
+            // the root is always a node, even over a single leaf, so traversal has one place to start
+            let children = match bvh.nodes[0].kind {
+                BvhNodeKind::Leaf { .. } => vec![0],
+                BvhNodeKind::Inner { .. } => open_children(&bvh, 0),
+            };
+            wide.collapse(&bvh, &children);
+        }
+        wide.prim_idxs = bvh.prim_idxs;
+        wide
+    }
+
+    // adds a node over children (binary node indices) and everything below them, returning its index
+    fn collapse(&mut self, bvh: &Bvh, children: &[usize]) -> u32 {
+        let idx = self.nodes.len();
+        self.nodes.push(WideNode {
+            low: [F4::splat(f32::INFINITY); 3],
+            high: [F4::splat(f32::NEG_INFINITY); 3],
+            children: [WideChild::Empty; 4],
+        });
+        for (lane, child) in children.iter().enumerate() {
+            let node = &bvh.nodes[*child];
+            let wide_child = match node.kind {
+                BvhNodeKind::Leaf { first, count } => {
+                    self.leaves.push((first, count));
+                    WideChild::Leaf(self.leaves.len() as u32 - 1)
+                },
+                BvhNodeKind::Inner { .. } => WideChild::Node(self.collapse(bvh, &open_children(bvh, *child))),
+            };
+            let wide = &mut self.nodes[idx];
+            wide.children[lane] = wide_child;
+            for a in 0..3 {
+                wide.low[a].0[lane] = node.aabb.bounds[a].low;
+                wide.high[a].0[lane] = node.aabb.bounds[a].high;
+            }
+        }
+        idx as u32
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+
+    pub fn leaf_prims(&self, leaf: usize) -> &[usize] {
+        let (first, count) = self.leaves[leaf];
+        &self.prim_idxs[first..first + count]
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        let mut stack: Vec<(WideChild, usize)> = if self.nodes.is_empty() { vec![] } else { vec![(WideChild::Node(0), 0)] };
+        while let Some((child, depth)) = stack.pop() {
+            match child {
+                WideChild::Empty => {},
+                WideChild::Leaf(leaf) => stats.leaf(self.leaves[leaf as usize].1, depth),
+                WideChild::Node(node) => {
+                    stats.branch(depth);
+                    stack.extend(self.nodes[node as usize].children.map(|c| (c, depth + 1)));
+                },
+            }
+        }
+        stats
+    }
+
+    // closest primitive hit along ray, hit_prim gives the ray length for a primitive index if it gets hit
+    pub fn closest_hit<F: FnMut(usize) -> Option<f32>>(&self, ray: &Ray, mut hit_prim: F) -> Option<(usize, f32)> {
+        self.closest_leaf_hit(ray, |leaf, max_l| {
+            self.leaf_prims(leaf).iter()
+                .filter_map(|prim| hit_prim(*prim).map(|l| (*prim, l)))
+                .filter(|(_, l)| *l < max_l)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+        })
+    }
+
+    // same, a leaf at a time for callers that test a leaf's primitives together. hit_leaf gets the leaf index and
+    // the closest length so far, and only returns hits closer than that
+    pub fn closest_leaf_hit<F: FnMut(usize, f32) -> Option<(usize, f32)>>(&self, ray: &Ray, hit_leaf: F) -> Option<(usize, f32)> {
+        self.search(ray, f32::INFINITY, false, hit_leaf)
+    }
+
+    // whether any primitive gets hit closer than max_l, done at the first one found. for shadow rays
+    pub fn any_hit<F: FnMut(usize, f32) -> bool>(&self, ray: &Ray, max_l: f32, mut hit_prim: F) -> bool {
+        self.any_leaf_hit(ray, max_l, |leaf, max_l| self.leaf_prims(leaf).iter().any(|prim| hit_prim(*prim, max_l)))
+    }
+
+    pub fn any_leaf_hit<F: FnMut(usize, f32) -> bool>(&self, ray: &Ray, max_l: f32, mut hit_leaf: F) -> bool {
+        self.search(ray, max_l, true, |leaf, max_l| hit_leaf(leaf, max_l).then_some((leaf, max_l))).is_some()
+    }
+
+    fn search<F: FnMut(usize, f32) -> Option<(usize, f32)>>(&self, ray: &Ray, max_l: f32, first: bool, mut hit_leaf: F) -> Option<(usize, f32)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let o = splat3(&ray.o);
+        let inv_d = ray.d.map(|d| 1.0 / d);
+        // near planes are the low sides along axes the ray goes up, so no per lane swapping. checked on the inverse
+        // so a -0.0 direction counts as going down, like its infinity does
+        let near_is_low = [0, 1, 2].map(|a| inv_d[a] >= 0.0);
+        let inv_d = splat3(&inv_d);
+        let mut closest: Option<(usize, f32)> = None;
+        let mut max_l = max_l;
+        let mut stack = Stack::new((WideChild::Node(0), 0.0));
+
+        while let Some((child, entry)) = stack.pop() {
+            if entry >= max_l {
+                continue;
+            }
+            match child {
+                WideChild::Empty => {},
+                WideChild::Leaf(leaf) => {
+                    if let Some((prim, l)) = hit_leaf(leaf as usize, max_l) {
+                        if first {
+                            return Some((prim, l));
+                        }
+                        max_l = l;
+                        closest = Some((prim, l));
+                    }
+                },
+                WideChild::Node(node) => {
+                    let node = &self.nodes[node as usize];
+                    let mut near = F4::splat(0.0);
+                    let mut far = F4::splat(max_l);
+                    for a in 0..3 {
+                        let (near_plane, far_plane) = if near_is_low[a] { (node.low[a], node.high[a]) } else { (node.high[a], node.low[a]) };
+                        // max/min drop NaN from 0 * inf, treating that axis as unbounded
+                        near = near.max((near_plane - o[a]) * inv_d[a]);
+                        far = far.min((far_plane - o[a]) * inv_d[a]);
+                    }
+
+                    // farthest pushed first so the nearest gets popped next. empty slots never pass
+                    let mut mask = near.le_mask(far);
+                    let mut hits: [(WideChild, f32); 4] = [(WideChild::Empty, f32::INFINITY); 4];
+                    let mut n = 0;
+                    while mask != 0 {
+                        let lane = mask.trailing_zeros() as usize;
+                        mask &= mask - 1;
+                        hits[n] = (node.children[lane], near.0[lane]);
+                        n += 1;
+                    }
+                    let hits = &mut hits[..n];
+                    if n > 1 {
+                        hits.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+                    }
+                    hits.iter().for_each(|h| stack.push(*h));
+                },
+            }
+        }
+
+        closest
+    }
+}
+
+// traversal stack that lives on the call stack, this runs once per ray per mesh so a heap allocation each time
+// shows. only spills onto the heap for trees deeper than any sane one
+struct Stack<T: Copy> {
+    inline: [T; 64],
+    len: usize,
+    spill: Vec<T>,
+}
+
+impl<T: Copy> Stack<T> {
+    fn new(first: T) -> Self {
+        Stack { inline: [first; 64], len: 1, spill: vec![] }
+    }
+
+    #[inline(always)]
+    fn push(&mut self, t: T) {
+        if self.len < self.inline.len() {
+            self.inline[self.len] = t;
+            self.len += 1;
+        } else {
+            self.spill.push(t);
+        }
+    }
+
+    #[inline(always)]
+    fn pop(&mut self) -> Option<T> {
+        if let Some(t) = self.spill.pop() {
+            return Some(t);
+        }
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.inline[self.len])
+    }
+}
+
+// up to four binary nodes under node, opening the biggest inner one until there are four or only leaves left
+fn open_children(bvh: &Bvh, node: usize) -> Vec<usize> {
+    let mut children = vec![node];
+    while children.len() < 4 {
+        let biggest = children.iter().enumerate()
+            .filter(|(_, c)| matches!(bvh.nodes[**c].kind, BvhNodeKind::Inner { .. }))
+            .max_by(|a, b| bvh.nodes[*a.1].aabb.surface_area().total_cmp(&bvh.nodes[*b.1].aabb.surface_area()))
+            .map(|(i, _)| i);
+        match biggest {
+            Some(i) => {
+                let BvhNodeKind::Inner { low, high } = bvh.nodes[children[i]].kind else { unreachable!() };
+                children.splice(i..=i, [low, high]);
+            },
+            None => break,
+        }
+    }
+    children
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::accel::PlaneBounds;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_wide_tree_finds_the_same_hits_as_the_binary_one() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let boxes: Vec<Aabb> = (0..2000).map(|i| {
+            let spread = if i % 4 == 0 { 60.0 } else { 5.0 };
+            let c = Vector3::<f32>::from_fn(|_, _| rng.gen_range(-spread..spread));
+            let r = rng.gen_range(0.05..1.0);
+            Aabb { bounds: [0, 1, 2].map(|a| PlaneBounds { low: c[a] - r, high: c[a] + r }) }
+        }).collect();
+        let bvh = Bvh::build(&boxes);
+        let wide = WideBvh::build(&boxes);
+        assert_eq!(wide.stats().leaf_prims, boxes.len());
+
+        // boxes themselves as the primitives, entry distance is the hit
+        let hit_box = |aabb: &Aabb, ray: &Ray| {
+            let (mut entry, mut exit) = (0.0f32, f32::INFINITY);
+            for a in 0..3 {
+                let l0 = (aabb.bounds[a].low - ray.o[a]) / ray.d[a];
+                let l1 = (aabb.bounds[a].high - ray.o[a]) / ray.d[a];
+                entry = entry.max(l0.min(l1));
+                exit = exit.min(l0.max(l1));
+            }
+            Some(entry).filter(|_| entry <= exit)
+        };
+        // axis aligned ones too, whose zero components can come out as -0.0
+        let axis_dirs = [Vector3::new(-0.0, 0.0, 1.0), Vector3::new(0.0, -1.0, -0.0), Vector3::new(1.0, -0.0, -0.0)];
+        for i in 0..2000 {
+            let o = Vector3::from_fn(|_, _| rng.gen_range(-80.0..80.0));
+            let d = match i % 4 {
+                3 => axis_dirs[i % 3],
+                _ => (Vector3::from_fn(|_, _| rng.gen_range(-5.0..5.0)) - o).normalize(),
+            };
+            let ray = Ray { o, d };
+            let expected = bvh.closest_hit(&ray, |i| hit_box(&boxes[i], &ray));
+            let got = wide.closest_hit(&ray, |i| hit_box(&boxes[i], &ray));
+            assert_eq!(got.map(|(_, l)| l), expected.map(|(_, l)| l));
+            // stopping at the first hit only has to agree on whether there is one before max_l
+            let max_l = 50.0;
+            let blocked = wide.any_hit(&ray, max_l, |i, max_l| hit_box(&boxes[i], &ray).is_some_and(|l| l < max_l));
+            assert_eq!(blocked, expected.is_some_and(|(_, l)| l < max_l));
+        }
+    }
+}
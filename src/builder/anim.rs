@@ -0,0 +1,135 @@
+// keyframed properties that work the same way for every member. a track names one property of a member and
+// gives its value over time, extract_anim sets it on each frame's copy of the member
+use keyframe::{Keyframe, AnimationSequence};
+use keyframe::mint::Point3;
+use nalgebra::Vector3;
+use serde::Deserialize;
+use crate::elements::sphere::Sphere;
+use super::ease_type;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Track {
+    pub property: String,
+    keyframes: Vec<TrackKeyframe>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TrackKeyframe {
+    value: AnimValue,
+    ease_type: Option<String>, // same names as Keyframe, EaseInOut by default
+    time: f32, // in seconds
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum AnimValue {
+    Scalar(f32),
+    Vector(Vector3<f32>),
+}
+
+impl AnimValue {
+    pub fn scalar(self, property: &str) -> f32 {
+        match self {
+            AnimValue::Scalar(s) => s,
+            AnimValue::Vector(_) => panic!("{} is animated with numbers, not vectors", property),
+        }
+    }
+    pub fn vector(self, property: &str) -> Vector3<f32> {
+        match self {
+            AnimValue::Vector(v) => v,
+            AnimValue::Scalar(_) => panic!("{} is animated with vectors, not numbers", property),
+        }
+    }
+}
+
+impl Track {
+    pub fn end(&self) -> f32 {
+        self.keyframes.iter().map(|k| k.time).fold(0.0, f32::max)
+    }
+
+    // held at the first and last values outside the keyframes
+    pub fn value_at(&self, time: f32) -> AnimValue {
+        let first = self.keyframes.first().unwrap_or_else(|| panic!("track for {} has no keyframes", self.property));
+        let mut sequence = AnimationSequence::<Point3<f32>>::new();
+        for key in &self.keyframes {
+            let v = match (first.value, key.value) {
+                (AnimValue::Scalar(_), AnimValue::Scalar(s)) => Vector3::new(s, 0.0, 0.0),
+                (AnimValue::Vector(_), AnimValue::Vector(v)) => v,
+                _ => panic!("keyframes for {} mix numbers and vectors", self.property),
+            };
+            sequence.insert(Keyframe::new_dynamic(Point3{x: v.x, y: v.y, z: v.z}, key.time, ease_type(key.ease_type.as_deref())))
+                .expect("Something happened while generating keyframe sequence for a track!!");
+        }
+        sequence.advance_to(time as f64);
+        let p = sequence.now_strict().unwrap();
+        match first.value {
+            AnimValue::Scalar(_) => AnimValue::Scalar(p.x),
+            AnimValue::Vector(_) => AnimValue::Vector(Vector3::new(p.x, p.y, p.z)),
+        }
+    }
+}
+
+// a member whose properties tracks can drive
+pub trait Animatable {
+    fn tracks(&self) -> &[Track];
+    fn set_property(&mut self, property: &str, value: AnimValue); // panics on properties it can't animate
+
+    fn tracks_end(&self) -> f32 {
+        self.tracks().iter().map(Track::end).fold(0.0, f32::max)
+    }
+    fn apply_tracks(&mut self, time: f32) {
+        let values: Vec<(String, AnimValue)> = self.tracks().iter().map(|t| (t.property.clone(), t.value_at(time))).collect();
+        values.into_iter().for_each(|(property, value)| self.set_property(&property, value));
+    }
+}
+
+pub fn unknown_property(member: &str, property: &str) -> ! {
+    panic!("{} has no animatable property {}", member, property)
+}
+
+impl Animatable for Sphere {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "c" => self.c = value.vector(property),
+            "r" => self.r = value.scalar(property),
+            _ => unknown_property("Sphere", property),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tracks_hold_their_ends_and_check_types() {
+        let mut sphere: Sphere = serde_yaml::from_str("
+            c: [0, 0, 0]
+            r: 1
+            coloring: !Solid [1, 1, 1]
+            mat:
+              divert_ray: !Diff
+            tracks:
+              - property: r
+                keyframes:
+                  - { value: 1, time: 1, ease_type: Linear }
+                  - { value: 3, time: 2, ease_type: Linear }
+              - property: c
+                keyframes:
+                  - { value: [0, 0, 0], time: 0 }
+                  - { value: [0, 4, 0], time: 2 }
+        ").unwrap();
+        assert_eq!(sphere.tracks_end(), 2.0);
+
+        sphere.apply_tracks(0.0);
+        assert_eq!(sphere.r, 1.0);
+        sphere.apply_tracks(1.5);
+        assert!((sphere.r - 2.0).abs() < 1e-4, "halfway radius was {}", sphere.r);
+        sphere.apply_tracks(5.0);
+        assert_eq!((sphere.r, sphere.c), (3.0, Vector3::new(0.0, 4.0, 0.0)));
+
+        let wrong = std::panic::catch_unwind(move || sphere.set_property("r", AnimValue::Vector(Vector3::zeros())));
+        assert!(wrong.is_err());
+    }
+}
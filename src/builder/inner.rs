@@ -5,8 +5,15 @@ use crate::elements::sphere::Sphere;
 // use crate::elements::Element;
 use crate::scene::Member;
 use crate::elements::distant_cube_map;
+use crate::elements::environment;
+use crate::elements::sky;
 use crate::elements::triangle;
+use crate::elements::light;
+use crate::elements::csg;
+use crate::elements::sdf;
+use crate::elements::heightfield;
 use super::pr;
+use super::anim::Animatable;
 // use super::pr::Cam;
 use keyframe::{Keyframe, AnimationSequence};
 use nalgebra::Vector3;
@@ -31,30 +38,57 @@ impl From<VecInto<MemberTypes>> for Vec<Member<'_>> {
                     members.push(Member::Elem(Box::new(s)));
                 },
                 DistantCubeMap(prcs) => {
-                    members.push(Member::Elem(
-                        Box::new(distant_cube_map::DistantCubeMap {
-                            neg_z: prcs.neg_z.into(),
-                            pos_z: prcs.pos_z.into(),
-                            neg_x: prcs.neg_x.into(),
-                            pos_x: prcs.pos_x.into(),
-                            neg_y: prcs.neg_y.into(),
-                            pos_y: prcs.pos_y.into(),
-                        })));
+                    members.push(Member::Elem(Box::new(distant_cube_map::DistantCubeMap::from(prcs))));
+                },
+                Environment(e) => {
+                    members.push(Member::Elem(Box::new(environment::Environment::from(e))));
+                },
+                Sky(s) => {
+                    let sky = sky::Sky::from(s);
+                    if let Some(sun) = sky.sun_light().filter(|l| l.irradiance.max() > 0.0) {
+                        members.push(Member::Light(Box::new(sun)));
+                    }
+                    members.push(Member::Elem(Box::new(sky)));
                 },
                 FreeTriangle(t) => {
-                    members.push(Member::Elem(
-                        Box::new(
-                            triangle::FreeTriangle {
-                                norm: t.norm.normalize().into(),
-                                verts: t.verts,
-                                rgb: t.rgb,
-                                diverts_ray: t.mat,
-                                type_name: "FreeTriangle".to_string(),
-                            },
-                    )));
+                    members.push(Member::Elem(Box::new(t.to_triangle())));
+                },
+                FreeQuad(q) => {
+                    members.extend(q.to_triangles().into_iter().map(|t| Member::Elem(Box::new(t))));
                 },
                 Model(m) => {
-                    members.extend(m.to_meshes().into_iter().map(|m| Member::Grp(Box::new(m))));
+                    members.extend(m.to_instances().into_iter().map(|inst| Member::Elem(Box::new(inst))));
+                    members.extend(m.to_lights().into_iter().map(Member::Light));
+                },
+                Instances(i) => {
+                    members.extend(i.to_instances().into_iter().map(|inst| Member::Elem(Box::new(inst))));
+                },
+                Csg(c) => {
+                    members.push(Member::Elem(Box::new(csg::Csg::from(c))));
+                },
+                Sdf(s) => {
+                    members.push(Member::Elem(Box::new(sdf::Sdf::from(s))));
+                },
+                Heightfield(h) => {
+                    members.push(Member::Elem(Box::new(heightfield::Heightfield::from(h))));
+                },
+                Curves(c) => {
+                    members.extend(c.to_curves().into_iter().map(|c| Member::Elem(Box::new(c))));
+                },
+                RectLight(r) => {
+                    members.push(Member::Elem(Box::new(light::RectLight::from(r))));
+                },
+                DiskLight(d) => {
+                    members.push(Member::Elem(Box::new(light::DiskLight::from(d))));
+                },
+                PointLight(p) => {
+                    members.push(Member::Light(Box::new(light::PointLight::from(p))));
+                },
+                SpotLight(s) => {
+                    members.push(Member::Light(Box::new(light::SpotLight::from(s))));
+                },
+                DirectionalLight(d) => {
+                    members.push(Member::Light(Box::new(light::DirectionalLight::from(d))));
                 },
             }
         });
@@ -68,6 +102,7 @@ impl VecInto<MemberTypes> {
     pub fn extract_concrete_types(self: VecInto<MemberTypes>) -> GPUElements {
         let mut spheres: Vec<Sphere> = vec![];
         let mut distant_cubemaps: Vec<distant_cube_map::DistantCubeMap> = vec![];
+        let mut environments: Vec<environment::Environment> = vec![];
         let mut free_triangles: Vec<triangle::FreeTriangle> = vec![];
         let mut meshes: Vec<mesh::Mesh> = vec![];
 
@@ -77,35 +112,49 @@ impl VecInto<MemberTypes> {
                     spheres.push(s.clone());
                 },
                 DistantCubeMap(prcs) => {
-                    distant_cubemaps.push(
-                        distant_cube_map::DistantCubeMap {
-                            neg_z: prcs.neg_z.clone().into(),
-                            pos_z: prcs.pos_z.clone().into(),
-                            neg_x: prcs.neg_x.clone().into(),
-                            pos_x: prcs.pos_x.clone().into(),
-                            neg_y: prcs.neg_y.clone().into(),
-                            pos_y: prcs.pos_y.clone().into(),
-                        }
-                    );
+                    distant_cubemaps.push(distant_cube_map::DistantCubeMap::from(prcs.clone()));
+                },
+                Environment(e) => {
+                    environments.push(environment::Environment::from(e.clone()));
+                },
+                Sky(s) => {
+                    environments.push(sky::Sky::from(s.clone()).bake_equirect(1024, 512));
                 },
                 FreeTriangle(t) => {
-                    free_triangles.push(
-                        triangle::FreeTriangle {
-                            norm: t.norm.normalize().into(),
-                            verts: t.verts,
-                            rgb: t.rgb,
-                            diverts_ray: t.mat.clone(),
-                            type_name: "FreeTriangle".to_string(),
-                        },
-                    )
+                    free_triangles.push(t.to_triangle());
+                },
+                FreeQuad(q) => {
+                    free_triangles.extend(q.to_triangles());
                 },
                 Model(model) => {
                     meshes.extend(model.to_meshes().into_iter());
                 },
+                Instances(i) => {
+                    meshes.extend(i.to_meshes());
+                },
+                // no dedicated lights on gpu, closest thing is emissive triangles
+                RectLight(r) => {
+                    free_triangles.extend(light::RectLight::from(r.clone()).to_free_triangles());
+                },
+                DiskLight(d) => {
+                    free_triangles.extend(light::DiskLight::from(d.clone()).to_free_triangles());
+                },
+                PointLight(_) | SpotLight(_) | DirectionalLight(_) => {
+                    static WARN: std::sync::Once = std::sync::Once::new();
+                    WARN.call_once(|| println!("Point, spot and directional lights are not supported on the GPU, skipping them"));
+                },
+                Csg(_) | Sdf(_) | Heightfield(_) | Curves(_) => {
+                    static WARN: std::sync::Once = std::sync::Once::new();
+                    WARN.call_once(|| println!("Csg, Sdf, Heightfield and Curves members are not supported on the GPU, skipping them"));
+                },
             }
         });
 
-        return (spheres, distant_cubemaps, free_triangles, meshes);
+        if free_triangles.iter().any(|t| t.is_shaded()) {
+            println!("Per vertex normals, colours, uvs and maps on free triangles and quads are not supported on the GPU, they render flat");
+        }
+
+        return (spheres, distant_cubemaps, free_triangles, meshes, environments);
     
     }
 
@@ -184,46 +233,76 @@ impl VecInto<MemberTypes> {
                                 frame_to_insert.translation = Vector3::new(x, y, z);
                                 frame_to_insert.euler_angles = [u, v, w];
 
-                                frames[i].0.push(MemberTypes::Model(frame_to_insert));
+                                frames[i].0.push(MemberTypes::Model(frame_to_insert.at_time((i as f64 * time_per_frame) as f32)));
                                 sequence_trans.advance_by(time_per_frame);
                                 sequence_angle.advance_by(time_per_frame);
                             } 
                         }, 
                         None => {
-                            frames.iter_mut().for_each(|frame| {
-                                frame.0.push(MemberTypes::Model(m.clone()));
+                            frames.iter_mut().enumerate().for_each(|(i, frame)| {
+                                frame.0.push(MemberTypes::Model(m.at_time((i as f64 * time_per_frame) as f32)));
                             }); 
                         },
                     }
                 },
 
-                // No animation for SkyBox or Triangles, but need to copy them into each frame's scene
+                // no keyframes for these, they're copied into each frame's scene here and animated by tracks afterwards
                 FreeTriangle(t) => {
                     frames.iter_mut().for_each(|frame| {
                         frame.0.push(MemberTypes::FreeTriangle(t.clone()));
                     });
                 },
 
+                FreeQuad(q) => {
+                    frames.iter_mut().for_each(|frame| {
+                        frame.0.push(MemberTypes::FreeQuad(q.clone()));
+                    });
+                },
+
                 DistantCubeMap(d) => {
                     frames.iter_mut().for_each(|frame| {
                         frame.0.push(MemberTypes::DistantCubeMap(d.clone()));
                     });
                 }, 
+
+                RectLight(r) => {
+                    frames.iter_mut().for_each(|frame| {
+                        frame.0.push(MemberTypes::RectLight(r.clone()));
+                    });
+                },
+
+                DiskLight(d) => {
+                    frames.iter_mut().for_each(|frame| {
+                        frame.0.push(MemberTypes::DiskLight(d.clone()));
+                    });
+                },
+
+                Environment(_) | Sky(_) | Instances(_) | Csg(_) | Sdf(_) | Heightfield(_) | Curves(_) | PointLight(_) | SpotLight(_) | DirectionalLight(_) => {
+                    frames.iter_mut().for_each(|frame| {
+                        frame.0.push(m.clone());
+                    });
+                },
             }
         });
 
+        // tracks go on top of the keyframes above, so they win if both move the same thing
+        frames.iter_mut().enumerate().for_each(|(i, frame)| {
+            let t = (i as f64 * time_per_frame) as f32;
+            frame.0.iter_mut().for_each(|m| m.animatable_mut().apply_tracks(t));
+        });
+
         frames
     }
 
     fn get_last_timestamp(&self) -> f32 {
         let last_timestamp: f32 = self.clone().0.into_iter().map(|m| {
             use MemberTypes::*;
-            let mut final_time: f32 = 0.0;
+            let mut final_time: f32 = m.animatable().tracks_end();
             match m {
                 Sphere(s) => {
                     match s.animation {
                         Some(s) => {
-                            final_time = s.keyframes.last().unwrap().time;
+                            final_time = final_time.max(s.keyframes.last().unwrap().time);
                             // for frame in s.keyframes {
                                 // frame.time 
                             // }
@@ -232,15 +311,17 @@ impl VecInto<MemberTypes> {
                     }
                 }, 
                 Model(m) => {
-                    match m.animation {
-                        Some(m) => {
-                            final_time = m.keyframes.last().unwrap().time;
+                    match &m.animation {
+                        Some(anim) => {
+                            final_time = final_time.max(anim.keyframes.last().unwrap().time);
                         }, 
                         None => {},
                     }
+                    // the file's own animation counts too
+                    final_time = final_time.max(m.gltf_animation_duration());
                 },
 
-                _ => {}, // No animation for SkyBox or Triangles
+                _ => {}, // everything else only animates through tracks
             }
             final_time
         }).reduce(f32::max).unwrap();
@@ -264,8 +345,68 @@ where
 pub enum MemberTypes {
     Sphere(Sphere),
     DistantCubeMap(pr::DistantCubeMap),
+    Environment(pr::Environment),
+    Sky(pr::Sky),
     FreeTriangle(pr::FreeTriangle),
+    FreeQuad(pr::FreeQuad),
 
     Model(pr::Model),
+    Instances(pr::Instances),
+    Csg(pr::Csg),
+    Sdf(pr::Sdf),
+    Heightfield(pr::Heightfield),
+    Curves(pr::Curves),
+
+    RectLight(pr::RectLight),
+    DiskLight(pr::DiskLight),
+    PointLight(pr::PointLight),
+    SpotLight(pr::SpotLight),
+    DirectionalLight(pr::DirectionalLight),
 }
 
+
+impl MemberTypes {
+    fn animatable(&self) -> &dyn Animatable {
+        match self {
+            Sphere(x) => x,
+            DistantCubeMap(x) => x,
+            Environment(x) => x,
+            Sky(x) => x,
+            FreeTriangle(x) => x,
+            FreeQuad(x) => x,
+            Model(x) => x,
+            Instances(x) => x,
+            Csg(x) => x,
+            Sdf(x) => x,
+            Heightfield(x) => x,
+            Curves(x) => x,
+            RectLight(x) => x,
+            DiskLight(x) => x,
+            PointLight(x) => x,
+            SpotLight(x) => x,
+            DirectionalLight(x) => x,
+        }
+    }
+
+    fn animatable_mut(&mut self) -> &mut dyn Animatable {
+        match self {
+            Sphere(x) => x,
+            DistantCubeMap(x) => x,
+            Environment(x) => x,
+            Sky(x) => x,
+            FreeTriangle(x) => x,
+            FreeQuad(x) => x,
+            Model(x) => x,
+            Instances(x) => x,
+            Csg(x) => x,
+            Sdf(x) => x,
+            Heightfield(x) => x,
+            Curves(x) => x,
+            RectLight(x) => x,
+            DiskLight(x) => x,
+            PointLight(x) => x,
+            SpotLight(x) => x,
+            DirectionalLight(x) => x,
+        }
+    }
+}
@@ -9,10 +9,14 @@ use nalgebra::Vector3;
 
 pub mod inner;
 mod pr;
+mod anim;
+
+pub use anim::Track;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Scheme {
     pub render_info: RenderInfo,
+    #[serde(deserialize_with = "pr::deserialize_cam")]
     pub cam: pr::Cam,
     pub scene_members: VecInto<MemberTypes>,
 }
@@ -34,28 +38,32 @@ pub struct Keyframe {
 
 impl Keyframe {
     pub fn get_ease_type (&self) -> Box<dyn EasingFunction + Send + Sync>{
-        // Default option for ease_type is EastInOut. Same as if no easing function is given in the Keyframe crate
-        match self.ease_type.clone().unwrap_or("EaseInOut".to_string()).as_str() {
-            "EaseIn" => {Box::new(keyframe::functions::EaseIn)},
-            "EaseInCubic" => {Box::new(keyframe::functions::EaseInCubic)},
-            "EaseInOut" => {Box::new(keyframe::functions::EaseInOut)},
-            "EaseInOutCubic" => {Box::new(keyframe::functions::EaseInOutCubic)},
-            "EaseInOutQuad" => {Box::new(keyframe::functions::EaseInOutQuad)},
-            "EaseInOutQuart" => {Box::new(keyframe::functions::EaseInOutQuart)},
-            "EaseInOutQuint" => {Box::new(keyframe::functions::EaseInOutQuint)},
-            "EaseInQuad" => {Box::new(keyframe::functions::EaseInQuad)},
-            "EaseInQuart" => {Box::new(keyframe::functions::EaseInQuart)},
-            "EaseInQuint" => {Box::new(keyframe::functions::EaseInQuint)},
-            "EaseOut" => {Box::new(keyframe::functions::EaseOut)},
-            "EaseOutCubic" => {Box::new(keyframe::functions::EaseOutCubic)},
-            "EaseOutQuad" => {Box::new(keyframe::functions::EaseOutQuad)},
-            "EaseOutQuart" => {Box::new(keyframe::functions::EaseOutQuart)},
-            "EaseOutQuint" => {Box::new(keyframe::functions::EaseOutQuint)},
-            "Hold" => {Box::new(keyframe::functions::Hold)},
-            "Linear" => {Box::new(keyframe::functions::Linear)},
-            "Step" => {Box::new(keyframe::functions::Step)},
-            func => {panic!("Unsupported easing function: {func}")}
-        }
+        ease_type(self.ease_type.as_deref())
+    }
+}
+
+pub fn ease_type(name: Option<&str>) -> Box<dyn EasingFunction + Send + Sync> {
+    // Default option for ease_type is EastInOut. Same as if no easing function is given in the Keyframe crate
+    match name.unwrap_or("EaseInOut") {
+        "EaseIn" => {Box::new(keyframe::functions::EaseIn)},
+        "EaseInCubic" => {Box::new(keyframe::functions::EaseInCubic)},
+        "EaseInOut" => {Box::new(keyframe::functions::EaseInOut)},
+        "EaseInOutCubic" => {Box::new(keyframe::functions::EaseInOutCubic)},
+        "EaseInOutQuad" => {Box::new(keyframe::functions::EaseInOutQuad)},
+        "EaseInOutQuart" => {Box::new(keyframe::functions::EaseInOutQuart)},
+        "EaseInOutQuint" => {Box::new(keyframe::functions::EaseInOutQuint)},
+        "EaseInQuad" => {Box::new(keyframe::functions::EaseInQuad)},
+        "EaseInQuart" => {Box::new(keyframe::functions::EaseInQuart)},
+        "EaseInQuint" => {Box::new(keyframe::functions::EaseInQuint)},
+        "EaseOut" => {Box::new(keyframe::functions::EaseOut)},
+        "EaseOutCubic" => {Box::new(keyframe::functions::EaseOutCubic)},
+        "EaseOutQuad" => {Box::new(keyframe::functions::EaseOutQuad)},
+        "EaseOutQuart" => {Box::new(keyframe::functions::EaseOutQuart)},
+        "EaseOutQuint" => {Box::new(keyframe::functions::EaseOutQuint)},
+        "Hold" => {Box::new(keyframe::functions::Hold)},
+        "Linear" => {Box::new(keyframe::functions::Linear)},
+        "Step" => {Box::new(keyframe::functions::Step)},
+        func => {panic!("Unsupported easing function: {func}")}
     }
 }
 
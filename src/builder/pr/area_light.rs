@@ -0,0 +1,67 @@
+use nalgebra::Vector3;
+use serde::Deserialize;
+use crate::elements::light::{self, Emission, LightSampling};
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+// rectangle spanned by full edge vectors u and v around centre c, emits towards u x v
+#[derive(Deserialize, Debug, Clone)]
+pub struct RectLight {
+    pub c: Vector3<f32>,
+    pub u: Vector3<f32>,
+    pub v: Vector3<f32>,
+    pub emission: Emission,
+    pub two_sided: Option<bool>, // default false
+    pub sampling: Option<LightSampling>, // default SolidAngle
+    pub tracks: Option<Vec<Track>>, // c, u and v
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DiskLight {
+    pub c: Vector3<f32>,
+    pub norm: Vector3<f32>, // emitting side
+    pub r: f32,
+    pub emission: Emission,
+    pub two_sided: Option<bool>,
+    pub sampling: Option<LightSampling>,
+    pub tracks: Option<Vec<Track>>, // c, norm and r
+}
+
+impl Animatable for RectLight {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "c" => self.c = value.vector(property),
+            "u" => self.u = value.vector(property),
+            "v" => self.v = value.vector(property),
+            _ => unknown_property("RectLight", property),
+        }
+    }
+}
+
+impl Animatable for DiskLight {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "c" => self.c = value.vector(property),
+            "norm" => self.norm = value.vector(property),
+            "r" => self.r = value.scalar(property),
+            _ => unknown_property("DiskLight", property),
+        }
+    }
+}
+
+impl From<RectLight> for light::RectLight {
+    fn from(r: RectLight) -> Self {
+        let two_sided = r.two_sided.unwrap_or(false);
+        let radiance = r.emission.radiance(r.u.cross(&r.v).norm(), two_sided);
+        light::RectLight::new(r.c, r.u, r.v, radiance, two_sided, r.sampling.unwrap_or_default())
+    }
+}
+
+impl From<DiskLight> for light::DiskLight {
+    fn from(d: DiskLight) -> Self {
+        let two_sided = d.two_sided.unwrap_or(false);
+        let radiance = d.emission.radiance(std::f32::consts::PI * d.r * d.r, two_sided);
+        light::DiskLight { c: d.c, norm: d.norm.normalize(), r: d.r, radiance, two_sided, sampling: d.sampling.unwrap_or_default() }
+    }
+}
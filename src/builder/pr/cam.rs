@@ -1,6 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use nalgebra::{Matrix4, Vector3};
 use crate::scene;
+use super::model::{load_camera, model_transform};
 
 #[derive(Deserialize, Debug, Clone, Copy)]
 pub struct Cam {
@@ -16,6 +17,54 @@ pub struct Cam {
     view_eulers: [f32; 3], // Camera rotation based on up
 }
 
+// a camera set up in a gltf file instead of by hand, placed the same way as a Model of that file would be
+#[derive(Deserialize, Debug, Clone)]
+pub struct GltfCam {
+    from_gltf: String,
+    name: Option<String>, // camera or node name, first camera in the file if not given
+    uniform_scale: Option<f32>,
+    translation: Option<Vector3<f32>>,
+    euler_angles: Option<[f32; 3]>,
+    aspect_ratio: Option<f32>, // width / height, only needed if the file doesn't have one
+    lens_r: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CamSource {
+    Manual(Cam),
+    Gltf(GltfCam),
+}
+
+impl From<GltfCam> for Cam {
+    fn from(g: GltfCam) -> Self {
+        let transform = model_transform(&g.translation.unwrap_or_default(), g.uniform_scale.unwrap_or(1.0), &g.euler_angles.unwrap_or_default());
+        let camera = load_camera(&g.from_gltf, g.name.as_deref(), &transform);
+        let aspect_ratio = g.aspect_ratio.or(camera.aspect_ratio)
+            .unwrap_or_else(|| panic!("gltf camera in {} has no aspect ratio, give the cam one", g.from_gltf));
+
+        // screen one unit in front, sized to the vertical fov
+        let screen_height = 2.0 * (camera.yfov / 2.0).tan();
+        Cam {
+            d: camera.pose.forward,
+            o: camera.pose.o,
+            up: camera.pose.up,
+            screen_width: screen_height * aspect_ratio,
+            screen_height,
+            lens_r: g.lens_r,
+            view_eulers: [0.0; 3],
+        }
+    }
+}
+
+// cam in a scheme is either written out or pulled from a gltf, resolved once here so animation frames dont reload the file
+pub fn deserialize_cam<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Cam, D::Error> {
+    Ok(match CamSource::deserialize(deserializer)? {
+        CamSource::Manual(cam) => cam,
+        CamSource::Gltf(g) => g.into(),
+    })
+}
+
 impl From<Cam> for scene::Cam {
     fn from(c_: Cam) -> Self {
         let Cam {
@@ -0,0 +1,70 @@
+use nalgebra::{Vector3, Rotation3};
+use serde::Deserialize;
+use crate::elements::csg::{self, Leaf, Shape, Tree};
+use crate::elements::sphere::Coloring;
+use crate::material::UniformDiffuseSpec;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Csg {
+    pub tree: CsgNode,
+    pub translation: Option<Vector3<f32>>, // moves every shape in the tree
+    pub tracks: Option<Vec<Track>>, // translation
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum CsgNode {
+    Sphere { c: Vector3<f32>, r: f32, coloring: Coloring, mat: UniformDiffuseSpec },
+    Box { c: Vector3<f32>, half_size: Vector3<f32>, euler_angles: Option<[f32; 3]>, coloring: Coloring, mat: UniformDiffuseSpec },
+    Cylinder { a: Vector3<f32>, b: Vector3<f32>, r: f32, coloring: Coloring, mat: UniformDiffuseSpec }, // capped, a and b are the cap centres
+    Union(Vec<CsgNode>),
+    Intersection(Vec<CsgNode>),
+    Difference(Vec<CsgNode>), // first minus all the rest
+}
+
+impl Animatable for Csg {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "translation" => self.translation = Some(value.vector(property)),
+            _ => unknown_property("Csg", property),
+        }
+    }
+}
+
+impl From<Csg> for csg::Csg {
+    fn from(c: Csg) -> Self {
+        let mut leaves: Vec<Leaf> = vec![];
+        let tree = build(c.tree, c.translation.unwrap_or_else(Vector3::zeros), &mut leaves);
+        csg::Csg { leaves, tree }
+    }
+}
+
+fn build(node: CsgNode, offset: Vector3<f32>, leaves: &mut Vec<Leaf>) -> Tree {
+    let mut leaf = |shape: Shape, coloring: Coloring, mat: UniformDiffuseSpec| {
+        leaves.push(Leaf { shape, coloring, mat });
+        Tree::Leaf(leaves.len() - 1)
+    };
+
+    match node {
+        CsgNode::Sphere { c, r, coloring, mat } => leaf(Shape::Sphere { c: c + offset, r }, coloring, mat),
+        CsgNode::Box { c, half_size, euler_angles, coloring, mat } => {
+            let [r, p, y] = euler_angles.unwrap_or([0.0; 3]);
+            leaf(Shape::Box { c: c + offset, half_size, rot: Rotation3::from_euler_angles(r, p, y).into_inner() }, coloring, mat)
+        },
+        CsgNode::Cylinder { a, b, r, coloring, mat } => {
+            let len = (b - a).norm();
+            leaf(Shape::Cylinder { a: a + offset, axis: (b - a) / len, len, r }, coloring, mat)
+        },
+        CsgNode::Union(children) => Tree::Union(build_all(children, offset, leaves, "Union")),
+        CsgNode::Intersection(children) => Tree::Intersection(build_all(children, offset, leaves, "Intersection")),
+        CsgNode::Difference(children) => Tree::Difference(build_all(children, offset, leaves, "Difference")),
+    }
+}
+
+fn build_all(children: Vec<CsgNode>, offset: Vector3<f32>, leaves: &mut Vec<Leaf>, op: &str) -> Vec<Tree> {
+    if children.is_empty() {
+        panic!("csg {} needs at least one shape in it", op);
+    }
+    children.into_iter().map(|c| build(c, offset, leaves)).collect()
+}
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use nalgebra::{Vector3, Vector4};
+use serde::Deserialize;
+use crate::elements::curve::{Curve, CurveShape, CurveMat};
+use super::model::model_transform;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+// a bunch of strands sharing one material, either listed inline or loaded from a file
+#[derive(Deserialize, Debug, Clone)]
+pub struct Curves {
+    pub path: Option<String>, // .curves text or cem yuksel's .hair binary
+    pub strands: Option<Vec<Strand>>,
+    pub translation: Option<Vector3<f32>>,
+    pub uniform_scale: Option<f32>, // scales widths too
+    pub euler_angles: Option<[f32; 3]>,
+    pub shape: Option<CurveShape>,
+    pub width: Option<f32>, // for .hair files without per point thickness, default 0.01
+    pub mat: CurveMat,
+    pub tracks: Option<Vec<Track>>, // translation, euler_angles and uniform_scale
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Strand {
+    pub cps: Vec<Vector3<f32>>, // 3n+1 bezier control points, consecutive segments share an end
+    pub widths: [f32; 2], // root and tip
+}
+
+impl Animatable for Curves {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "translation" => self.translation = Some(value.vector(property)),
+            "euler_angles" => self.euler_angles = Some(value.vector(property).into()),
+            "uniform_scale" => self.uniform_scale = Some(value.scalar(property)),
+            _ => unknown_property("Curves", property),
+        }
+    }
+}
+
+// one cubic segment ready to become a Curve
+type Segment = ([Vector3<f32>; 4], [f32; 2]);
+
+impl Curves {
+    pub fn to_curves(&self) -> Vec<Curve> {
+        let mut segments: Vec<Segment> = vec![];
+        if let Some(path) = &self.path {
+            let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("couldn't open curves {}: {}", path, e));
+            match path.rsplit('.').next().map(|e| e.to_lowercase()).as_deref() {
+                Some("hair") => segments.extend(parse_hair(&bytes, self.width.unwrap_or(0.01))),
+                _ => {
+                    let text = String::from_utf8(bytes).unwrap_or_else(|_| panic!("{} isn't a text curves file", path));
+                    parse_text(&text).iter().for_each(|s| segments.extend(split_strand(s)));
+                },
+            }
+        }
+        self.strands.iter().flatten().for_each(|s| segments.extend(split_strand(s)));
+
+        let trans = model_transform(
+            &self.translation.unwrap_or(Vector3::zeros()),
+            self.uniform_scale.unwrap_or(1.0),
+            &self.euler_angles.unwrap_or([0.0; 3]),
+        );
+        let scale = self.uniform_scale.unwrap_or(1.0);
+        let shape = self.shape.unwrap_or_default();
+        let mat = Arc::new(self.mat.clone());
+
+        segments.into_iter().map(|(cps, widths)| {
+            let cps = cps.map(|p| (trans * Vector4::new(p.x, p.y, p.z, 1.0)).xyz());
+            Curve::new(cps, widths.map(|w| w * scale), shape, mat.clone())
+        }).collect()
+    }
+}
+
+// a strand of several segments, width tapers along the whole strand
+fn split_strand(s: &Strand) -> Vec<Segment> {
+    if s.cps.len() < 4 || !(s.cps.len() - 1).is_multiple_of(3) {
+        panic!("curve strands need 3n+1 control points, got {}", s.cps.len());
+    }
+    let n = (s.cps.len() - 1) / 3;
+    let width_at = |i: usize| s.widths[0] + (s.widths[1] - s.widths[0]) * i as f32 / n as f32;
+    (0..n).map(|i| {
+        let cps = [s.cps[3 * i], s.cps[3 * i + 1], s.cps[3 * i + 2], s.cps[3 * i + 3]];
+        (cps, [width_at(i), width_at(i + 1)])
+    }).collect()
+}
+
+// each strand starts with "strand <root width> <tip width>" followed by one "x y z" per control point,
+// # starts a comment
+fn parse_text(text: &str) -> Vec<Strand> {
+    let mut strands: Vec<Strand> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let nums = |s: &str| -> Vec<f32> {
+            s.split_whitespace().map(|n| n.parse().unwrap_or_else(|_| panic!("bad number {:?} on curves line {}", n, i + 1))).collect()
+        };
+        match line.strip_prefix("strand") {
+            Some(rest) => {
+                let w = nums(rest);
+                if w.len() != 2 {
+                    panic!("curves line {} should be \"strand <root width> <tip width>\"", i + 1);
+                }
+                strands.push(Strand { cps: vec![], widths: [w[0], w[1]] });
+            },
+            None => {
+                let p = nums(line);
+                match (strands.last_mut(), p.len()) {
+                    (Some(s), 3) => s.cps.push(Vector3::new(p[0], p[1], p[2])),
+                    (None, _) => panic!("curves line {} has a point before any strand", i + 1),
+                    _ => panic!("curves line {} should be \"x y z\"", i + 1),
+                }
+            },
+        }
+    }
+    strands
+}
+
+// cem yuksel's hair format, strands are polylines so they get turned into catmull rom beziers
+fn parse_hair(bytes: &[u8], default_width: f32) -> Vec<Segment> {
+    if bytes.len() < 128 || &bytes[0..4] != b"HAIR" {
+        panic!("not a .hair file");
+    }
+    let u32_at = |o: usize| u32::from_le_bytes(bytes[o..o + 4].try_into().unwrap()) as usize;
+    let f32_at = |o: usize| f32::from_le_bytes(bytes[o..o + 4].try_into().unwrap());
+    let (n_strands, n_points, flags) = (u32_at(4), u32_at(8), u32_at(12));
+    let (default_segments, default_thickness) = (u32_at(16), f32_at(20));
+    // transparency (8) and colour (16) arrays come last, the material decides those here
+    let (has_segments, has_points, has_thickness) = (flags & 1 != 0, flags & 2 != 0, flags & 4 != 0);
+    if !has_points {
+        panic!(".hair file has no points");
+    }
+
+    let mut o = 128;
+    let segment_counts: Vec<usize> = if has_segments {
+        let counts = (0..n_strands).map(|i| u16::from_le_bytes(bytes[o + 2 * i..o + 2 * i + 2].try_into().unwrap()) as usize).collect();
+        o += 2 * n_strands;
+        counts
+    } else {
+        vec![default_segments; n_strands]
+    };
+    let points: Vec<Vector3<f32>> = (0..n_points).map(|i| Vector3::new(f32_at(o + 12 * i), f32_at(o + 12 * i + 4), f32_at(o + 12 * i + 8))).collect();
+    o += 12 * n_points;
+    let widths: Vec<f32> = if has_thickness {
+        (0..n_points).map(|i| f32_at(o + 4 * i)).collect()
+    } else if default_thickness > 0.0 {
+        vec![default_thickness; n_points]
+    } else {
+        vec![default_width; n_points]
+    };
+
+    let mut segments = vec![];
+    let mut start = 0;
+    for count in segment_counts {
+        let p = &points[start..start + count + 1];
+        let w = &widths[start..start + count + 1];
+        for i in 0..count {
+            let prev = p[i.saturating_sub(1)];
+            let next = p[(i + 2).min(count)];
+            let c1 = p[i] + (p[i + 1] - prev) / 6.0;
+            let c2 = p[i + 1] - (next - p[i]) / 6.0;
+            segments.push(([p[i], c1, c2, p[i + 1]], [w[i], w[i + 1]]));
+        }
+        start += count + 1;
+    }
+    segments
+}
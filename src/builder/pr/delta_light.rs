@@ -0,0 +1,120 @@
+use nalgebra::Vector3;
+use serde::Deserialize;
+use crate::elements::light::{self, temperature_to_rgb};
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+// colour of a light, either given directly, from a black body temperature in kelvin, or both (temperature tinted by colour)
+#[derive(Deserialize, Debug, Clone)]
+pub struct LightSpectrum {
+    pub color: Option<Vector3<f32>>,
+    pub temperature: Option<f32>,
+    pub intensity: f32,
+}
+
+impl LightSpectrum {
+    pub fn rgb(&self) -> Vector3<f32> {
+        let color = self.color.unwrap_or(Vector3::repeat(1.0));
+        let temp = self.temperature.map(temperature_to_rgb).unwrap_or(Vector3::repeat(1.0));
+        color.component_mul(&temp) * self.intensity
+    }
+
+    // the properties every delta light shares
+    fn set_property(&mut self, light: &str, property: &str, value: AnimValue) {
+        match property {
+            "intensity" => self.intensity = value.scalar(property),
+            "color" => self.color = Some(value.vector(property)),
+            "temperature" => self.temperature = Some(value.scalar(property)),
+            _ => unknown_property(light, property),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PointLight {
+    pub pos: Vector3<f32>,
+    #[serde(flatten)]
+    pub spectrum: LightSpectrum, // intensity is power per steradian
+    pub tracks: Option<Vec<Track>>, // pos, intensity, color and temperature
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpotLight {
+    pub pos: Vector3<f32>,
+    pub dir: Vector3<f32>,
+    pub cone_angle: f32, // half angle of the cone in degrees, no light outside
+    pub inner_angle: Option<f32>, // half angle in degrees where the falloff starts, defaults to 80% of cone_angle
+    #[serde(flatten)]
+    pub spectrum: LightSpectrum,
+    pub tracks: Option<Vec<Track>>, // pos, dir, cone_angle, intensity, color and temperature
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DirectionalLight {
+    pub dir: Vector3<f32>, // direction the light travels in
+    pub angular_diameter: Option<f32>, // in degrees, the sun is about 0.53. leave out for hard shadows
+    #[serde(flatten)]
+    pub spectrum: LightSpectrum, // intensity is irradiance on a surface facing the light
+    pub tracks: Option<Vec<Track>>, // dir, intensity, color and temperature
+}
+
+impl Animatable for PointLight {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "pos" => self.pos = value.vector(property),
+            _ => self.spectrum.set_property("PointLight", property, value),
+        }
+    }
+}
+
+impl Animatable for SpotLight {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "pos" => self.pos = value.vector(property),
+            "dir" => self.dir = value.vector(property),
+            "cone_angle" => self.cone_angle = value.scalar(property),
+            _ => self.spectrum.set_property("SpotLight", property, value),
+        }
+    }
+}
+
+impl Animatable for DirectionalLight {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "dir" => self.dir = value.vector(property),
+            _ => self.spectrum.set_property("DirectionalLight", property, value),
+        }
+    }
+}
+
+impl From<PointLight> for light::PointLight {
+    fn from(p: PointLight) -> Self {
+        light::PointLight { pos: p.pos, intensity: p.spectrum.rgb() }
+    }
+}
+
+impl From<SpotLight> for light::SpotLight {
+    fn from(s: SpotLight) -> Self {
+        let inner = s.inner_angle.unwrap_or(0.8 * s.cone_angle).min(s.cone_angle);
+        light::SpotLight {
+            pos: s.pos,
+            dir: s.dir.normalize(),
+            intensity: s.spectrum.rgb(),
+            cos_outer: s.cone_angle.to_radians().cos(),
+            cos_inner: inner.to_radians().cos(),
+        }
+    }
+}
+
+impl From<DirectionalLight> for light::DirectionalLight {
+    fn from(d: DirectionalLight) -> Self {
+        let half = 0.5 * d.angular_diameter.unwrap_or(0.0).to_radians();
+        light::DirectionalLight {
+            dir: d.dir.normalize(),
+            irradiance: d.spectrum.rgb(),
+            sin2_half_angle: half.sin().powi(2),
+        }
+    }
+}
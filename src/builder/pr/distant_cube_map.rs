@@ -1,24 +1,151 @@
 // precursor structs for constructing elements
 use serde::Deserialize;
-use crate::elements::distant_cube_map::FaceImagewUVScale;
+use image::{GenericImageView, Rgb32FImage};
+use crate::elements::distant_cube_map::{self, FaceImagewUVScale};
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct PathwUVScale(String, f32, f32);
 
 // for environment mapping, all rays can hit this
+// either six face images, or one image with all of them laid out in a cross or strip
 #[derive(Deserialize, Debug, Clone)]
 pub struct DistantCubeMap {
-    pub neg_z: PathwUVScale,
-    pub pos_z: PathwUVScale,
-    pub neg_x: PathwUVScale,
-    pub pos_x: PathwUVScale,
-    pub neg_y: PathwUVScale,
-    pub pos_y: PathwUVScale,
+    pub neg_z: Option<PathwUVScale>,
+    pub pos_z: Option<PathwUVScale>,
+    pub neg_x: Option<PathwUVScale>,
+    pub pos_x: Option<PathwUVScale>,
+    pub neg_y: Option<PathwUVScale>,
+    pub pos_y: Option<PathwUVScale>,
+    pub path: Option<String>,
+    pub layout: Option<CubeLayout>, // guessed from the image's aspect ratio when left out
+    pub rotation: Option<f32>, // degrees around +y
+    pub tracks: Option<Vec<Track>>, // rotation
+}
+
+// all of these are an unfolded box seen from the inside: -z straight ahead, -x to its left, +x to its right,
+// +y above and -y below, with the top and bottom faces' edges next to -z touching it
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum CubeLayout {
+    HorizontalCross, // 4x3, -x -z +x +z across the middle
+    VerticalCross, // 3x4, +z under -y and upside down so it unfolds from it
+    Strip, // 6x1, +x -x +y -y +z -z, each face oriented as in the horizontal cross
+}
+
+impl Animatable for DistantCubeMap {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "rotation" => self.rotation = Some(value.scalar(property)),
+            _ => unknown_property("DistantCubeMap", property),
+        }
+    }
 }
 
 impl From<PathwUVScale> for FaceImagewUVScale {
-    fn from(pathwuvscale: PathwUVScale) -> Self { 
+    fn from(pathwuvscale: PathwUVScale) -> Self {
         let PathwUVScale (path, us, vs) = pathwuvscale;
         (image::open(path).unwrap().into_rgb32f().into(), us, vs)
     }
-}
\ No newline at end of file
+}
+
+type FaceCell = (u32, u32, f32, f32); // column and row in the layout's grid, then the face's uv scales
+
+impl CubeLayout {
+    fn guess(width: u32, height: u32) -> Self {
+        match (width, height) {
+            (w, h) if 3 * w == 4 * h => CubeLayout::HorizontalCross,
+            (w, h) if 4 * w == 3 * h => CubeLayout::VerticalCross,
+            (w, h) if w == 6 * h => CubeLayout::Strip,
+            (w, h) => panic!("can't tell the cube map layout of a {}x{} image, give it a layout", w, h),
+        }
+    }
+
+    // size of the grid of faces, and where each face sits in it in neg_z, pos_z, neg_x, pos_x, neg_y, pos_y order
+    fn cells(&self) -> ((u32, u32), [FaceCell; 6]) {
+        match self {
+            CubeLayout::HorizontalCross => ((4, 3), [(1, 1, -1.0, 1.0), (3, 1, -1.0, -1.0), (0, 1, 1.0, 1.0), (2, 1, 1.0, -1.0), (1, 2, -1.0, -1.0), (1, 0, 1.0, -1.0)]),
+            CubeLayout::VerticalCross => ((3, 4), [(1, 1, -1.0, 1.0), (1, 3, 1.0, 1.0), (0, 1, 1.0, 1.0), (2, 1, 1.0, -1.0), (1, 2, -1.0, -1.0), (1, 0, 1.0, -1.0)]),
+            CubeLayout::Strip => ((6, 1), [(5, 0, -1.0, 1.0), (4, 0, -1.0, -1.0), (1, 0, 1.0, 1.0), (0, 0, 1.0, -1.0), (3, 0, -1.0, -1.0), (2, 0, 1.0, -1.0)]),
+        }
+    }
+
+    fn split(&self, image: &Rgb32FImage) -> [FaceImagewUVScale; 6] {
+        let ((cols, rows), cells) = self.cells();
+        let (fw, fh) = (image.width() / cols, image.height() / rows);
+        cells.map(|(cx, cy, us, vs)| (image.view(cx * fw, cy * fh, fw, fh).to_image().into(), us, vs))
+    }
+}
+
+impl From<DistantCubeMap> for distant_cube_map::DistantCubeMap {
+    fn from(c: DistantCubeMap) -> Self {
+        let rotation = c.rotation.unwrap_or(0.0).to_radians();
+        let [neg_z, pos_z, neg_x, pos_x, neg_y, pos_y] = match c.path {
+            Some(path) => {
+                let image = image::open(&path).unwrap_or_else(|err| panic!("couldn't open cube map {}: {}", path, err)).into_rgb32f();
+                c.layout.unwrap_or_else(|| CubeLayout::guess(image.width(), image.height())).split(&image)
+            },
+            None => [c.neg_z, c.pos_z, c.neg_x, c.pos_x, c.neg_y, c.pos_y]
+                .map(|f| f.expect("cube map needs either a path or all six faces").into()),
+        };
+        distant_cube_map::DistantCubeMap { neg_z, pos_z, neg_x, pos_x, neg_y, pos_y, rotation }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nalgebra::Vector3;
+
+    fn encode(d: Vector3<f32>) -> image::Rgb<f32> {
+        let c = d.normalize() * 0.5 + Vector3::repeat(0.5);
+        image::Rgb([c.x, c.y, c.z])
+    }
+
+    // direction for a spot on a face, a and b run right and down across it in [-1, 1]
+    type FaceDirs = fn(f32, f32) -> Vector3<f32>;
+
+    // paints every face with its own directions
+    fn painted(layout: CubeLayout, cells: [(u32, u32, FaceDirs); 6]) -> distant_cube_map::DistantCubeMap {
+        let n = 32;
+        let ((cols, rows), _) = layout.cells();
+        let image = Rgb32FImage::from_fn(cols * n, rows * n, |x, y| {
+            let (a, b) = (((x % n) as f32 + 0.5) / n as f32 * 2.0 - 1.0, ((y % n) as f32 + 0.5) / n as f32 * 2.0 - 1.0);
+            match cells.iter().find(|(cx, cy, _)| *cx == x / n && *cy == y / n) {
+                Some((_, _, dir)) => encode(dir(a, b)),
+                None => image::Rgb([0.0; 3]),
+            }
+        });
+        let [neg_z, pos_z, neg_x, pos_x, neg_y, pos_y] = layout.split(&image);
+        distant_cube_map::DistantCubeMap { neg_z, pos_z, neg_x, pos_x, neg_y, pos_y, rotation: 0.0 }
+    }
+
+    #[test]
+    fn test_layouts_unfold_without_seams() {
+        let front: FaceDirs = |a, b| Vector3::new(a, -b, -1.0);
+        let back: FaceDirs = |a, b| Vector3::new(-a, -b, 1.0);
+        let left: FaceDirs = |a, b| Vector3::new(-1.0, -b, -a);
+        let right: FaceDirs = |a, b| Vector3::new(1.0, -b, a);
+        let top: FaceDirs = |a, b| Vector3::new(a, 1.0, -b);
+        let bottom: FaceDirs = |a, b| Vector3::new(a, -1.0, b);
+        let upside_down_back: FaceDirs = |a, b| Vector3::new(a, b, 1.0);
+
+        let maps = [
+            painted(CubeLayout::HorizontalCross, [(1, 1, front), (3, 1, back), (0, 1, left), (2, 1, right), (1, 0, top), (1, 2, bottom)]),
+            painted(CubeLayout::VerticalCross, [(1, 1, front), (1, 3, upside_down_back), (0, 1, left), (2, 1, right), (1, 0, top), (1, 2, bottom)]),
+            painted(CubeLayout::Strip, [(5, 0, front), (4, 0, back), (1, 0, left), (0, 0, right), (2, 0, top), (3, 0, bottom)]),
+        ];
+        // a spiral over the whole sphere, crossing every edge and passing near the corners
+        for map in maps {
+            for i in 0..2000 {
+                let t = (i as f32 + 0.5) / 2000.0;
+                let (y, phi) = (1.0 - 2.0 * t, i as f32 * 2.39996);
+                let r = (1.0 - y * y).sqrt();
+                let d = Vector3::new(r * phi.cos(), y, r * phi.sin());
+                let expected = encode(d).0;
+                let got = map.sample(&d);
+                assert!((got - Vector3::from(expected)).norm() < 0.04, "{:?} gave {:?}, expected {:?}", d, got, expected);
+            }
+        }
+    }
+}
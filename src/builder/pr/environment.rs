@@ -0,0 +1,35 @@
+use serde::Deserialize;
+use crate::elements::environment;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Environment {
+    pub path: String, // equirectangular .hdr, .exr or any ldr image
+    pub rotation: Option<f32>, // degrees around +y
+    pub intensity: Option<f32>,
+    pub background: Option<bool>, // seen directly by the camera, true by default
+    pub tracks: Option<Vec<Track>>, // rotation and intensity
+}
+
+impl From<Environment> for environment::Environment {
+    fn from(e: Environment) -> Self {
+        let image = image::open(&e.path).unwrap_or_else(|err| panic!("couldn't open environment {}: {}", e.path, err));
+        environment::Environment {
+            image: image.into_rgb32f().into(),
+            rotation: e.rotation.unwrap_or(0.0).to_radians(),
+            intensity: e.intensity.unwrap_or(1.0),
+            background: e.background.unwrap_or(true),
+        }
+    }
+}
+
+impl Animatable for Environment {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "rotation" => self.rotation = Some(value.scalar(property)),
+            "intensity" => self.intensity = Some(value.scalar(property)),
+            _ => unknown_property("Environment", property),
+        }
+    }
+}
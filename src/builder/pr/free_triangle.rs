@@ -1,14 +1,150 @@
-use nalgebra::Vector3;
-use crate::material::UniformDiffuseSpec;
+use nalgebra::{Vector3, Vector2};
+use crate::material::{UniformDiffuseSpec, UVRgb32FImage};
+use crate::elements::triangle::{self, FreeNorm, FreeColor, FreeNormalMap};
 use serde::Deserialize;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+use std::sync::Arc;
 
+// uvs have (0, 0) at the top left of the image, like gltf
 #[derive(Deserialize, Debug, Clone)]
 pub struct FreeTriangle {
     pub verts: [Vector3<f32>; 3],
-    pub norm: Vector3<f32>,
+    pub norm: Option<Vector3<f32>>, // face normal, from the winding (right hand) when left out
+    pub norms: Option<[Vector3<f32>; 3]>, // per vertex, for smooth shading
+    pub colors: Option<[Vector3<f32>; 3]>, // per vertex, multiplies rgb
+    pub uvs: Option<[Vector2<f32>; 3]>,
 
     pub rgb: Vector3<f32>,
+    pub texture: Option<String>, // multiplies rgb, needs uvs
+    pub normal_map: Option<String>, // tangent space, needs uvs
     pub mat: UniformDiffuseSpec,
-    // pub animation: Option<Anim>,
+    pub tracks: Option<Vec<Track>>, // v0 to v2, offset (moves all of them) and rgb
 }
 
+// same as FreeTriangle with a fourth corner, split along verts[0] to verts[2]
+#[derive(Deserialize, Debug, Clone)]
+pub struct FreeQuad {
+    pub verts: [Vector3<f32>; 4],
+    pub norm: Option<Vector3<f32>>,
+    pub norms: Option<[Vector3<f32>; 4]>,
+    pub colors: Option<[Vector3<f32>; 4]>,
+    pub uvs: Option<[Vector2<f32>; 4]>,
+
+    pub rgb: Vector3<f32>,
+    pub texture: Option<String>,
+    pub normal_map: Option<String>,
+    pub mat: UniformDiffuseSpec,
+    pub tracks: Option<Vec<Track>>, // v0 to v3, offset and rgb
+}
+
+// what a free triangle or quad carries, per vertex attributes get picked out by corner
+struct Surface<'a> {
+    verts: &'a [Vector3<f32>],
+    norm: Option<Vector3<f32>>,
+    norms: Option<&'a [Vector3<f32>]>,
+    colors: Option<&'a [Vector3<f32>]>,
+    uvs: Option<&'a [Vector2<f32>]>,
+    rgb: Vector3<f32>,
+    texture: Option<Arc<UVRgb32FImage>>,
+    normal_map: Option<Arc<UVRgb32FImage>>,
+    mat: &'a UniformDiffuseSpec,
+}
+
+fn load_map(path: &Option<String>) -> Option<Arc<UVRgb32FImage>> {
+    path.as_ref().map(|path| {
+        let image = image::open(path).unwrap_or_else(|err| panic!("couldn't open {}: {}", path, err));
+        Arc::new(image.into_rgb32f().into())
+    })
+}
+
+impl Surface<'_> {
+    fn triangle(&self, corners: [usize; 3]) -> triangle::FreeTriangle {
+        let pick = |attrs: &[Vector3<f32>]| corners.map(|i| attrs[i]);
+        let verts = pick(self.verts);
+        let face = self.norm.unwrap_or_else(|| (verts[1] - verts[0]).cross(&(verts[2] - verts[0]))).normalize();
+        let uvs = self.uvs.map(|uvs| corners.map(|i| uvs[i]));
+        let with_uvs = |map: &Option<Arc<UVRgb32FImage>>| match (map, uvs) {
+            (Some(map), Some(uvs)) => Some((map.clone(), uvs)),
+            (Some(_), None) => panic!("free triangles and quads need uvs for a texture or normal map"),
+            (None, _) => None,
+        };
+
+        triangle::FreeTriangle {
+            verts,
+            norm: FreeNorm {
+                face,
+                verts: self.norms.map(|norms| pick(norms).map(|n| n.normalize())),
+                normal_map: with_uvs(&self.normal_map).and_then(|(map, uvs)| FreeNormalMap::new(map, &verts, uvs)),
+            },
+            rgb: FreeColor {
+                factor: self.rgb,
+                verts: self.colors.map(pick),
+                texture: with_uvs(&self.texture),
+            },
+            diverts_ray: self.mat.clone(),
+            type_name: "FreeTriangle",
+        }
+    }
+}
+
+impl FreeTriangle {
+    pub fn to_triangle(&self) -> triangle::FreeTriangle {
+        Surface {
+            verts: &self.verts,
+            norm: self.norm,
+            norms: self.norms.as_ref().map(|n| n.as_slice()),
+            colors: self.colors.as_ref().map(|c| c.as_slice()),
+            uvs: self.uvs.as_ref().map(|uv| uv.as_slice()),
+            rgb: self.rgb,
+            texture: load_map(&self.texture),
+            normal_map: load_map(&self.normal_map),
+            mat: &self.mat,
+        }.triangle([0, 1, 2])
+    }
+}
+
+impl FreeQuad {
+    pub fn to_triangles(&self) -> Vec<triangle::FreeTriangle> {
+        let surface = Surface {
+            verts: &self.verts,
+            norm: self.norm,
+            norms: self.norms.as_ref().map(|n| n.as_slice()),
+            colors: self.colors.as_ref().map(|c| c.as_slice()),
+            uvs: self.uvs.as_ref().map(|uv| uv.as_slice()),
+            rgb: self.rgb,
+            texture: load_map(&self.texture),
+            normal_map: load_map(&self.normal_map),
+            mat: &self.mat,
+        };
+        vec![surface.triangle([0, 1, 2]), surface.triangle([0, 2, 3])]
+    }
+}
+
+impl Animatable for FreeTriangle {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "v0" => self.verts[0] = value.vector(property),
+            "v1" => self.verts[1] = value.vector(property),
+            "v2" => self.verts[2] = value.vector(property),
+            "offset" => self.verts.iter_mut().for_each(|v| *v += value.vector(property)),
+            "rgb" => self.rgb = value.vector(property),
+            _ => unknown_property("FreeTriangle", property),
+        }
+    }
+}
+
+impl Animatable for FreeQuad {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "v0" => self.verts[0] = value.vector(property),
+            "v1" => self.verts[1] = value.vector(property),
+            "v2" => self.verts[2] = value.vector(property),
+            "v3" => self.verts[3] = value.vector(property),
+            "offset" => self.verts.iter_mut().for_each(|v| *v += value.vector(property)),
+            "rgb" => self.rgb = value.vector(property),
+            _ => unknown_property("FreeQuad", property),
+        }
+    }
+}
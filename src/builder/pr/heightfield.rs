@@ -0,0 +1,43 @@
+use nalgebra::{Vector3, Vector2};
+use serde::Deserialize;
+use crate::elements::heightfield;
+use crate::elements::sphere::Coloring;
+use crate::material::UniformDiffuseSpec;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Heightfield {
+    pub path: String, // greyscale image, 8 or 16 bit, black is low
+    pub corner: Vector3<f32>, // min x and z of the terrain, y is where black ends up
+    pub extent: [f32; 2], // world size along x (image width) and z (image height)
+    pub height_scale: f32, // white ends up this far above corner.y
+    pub texture: Option<String>, // colour image stretched over the whole extent
+    pub coloring: Coloring, // multiplies the texture if there is one
+    pub mat: UniformDiffuseSpec,
+    pub tracks: Option<Vec<Track>>, // corner and height_scale
+}
+
+impl Animatable for Heightfield {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "corner" => self.corner = value.vector(property),
+            "height_scale" => self.height_scale = value.scalar(property),
+            _ => unknown_property("Heightfield", property),
+        }
+    }
+}
+
+impl From<Heightfield> for heightfield::Heightfield {
+    fn from(h: Heightfield) -> Self {
+        let image = image::open(&h.path).unwrap_or_else(|e| panic!("couldn't open heightfield {}: {}", h.path, e)).to_luma16();
+        let res = (image.width() as usize, image.height() as usize);
+        let heights: Vec<f32> = image.pixels().map(|p| h.corner.y + h.height_scale * p.0[0] as f32 / u16::MAX as f32).collect();
+
+        let texture = h.texture.as_ref().map(|path| {
+            image::open(path).unwrap_or_else(|e| panic!("couldn't open heightfield texture {}: {}", path, e)).to_rgb32f().into()
+        });
+
+        heightfield::Heightfield::new(heights, res, h.corner.xz(), Vector2::from(h.extent), texture, h.coloring, h.mat)
+    }
+}
@@ -0,0 +1,81 @@
+use nalgebra::{Vector3, Matrix4};
+use serde::Deserialize;
+use crate::elements::mesh::{Mesh, MeshInstance};
+use super::model::{load_model, model_transform, shared_blases, cached_meshes};
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+// one model file placed many times, loaded once and shared between the placements
+#[derive(Deserialize, Debug, Clone)]
+pub struct Instances {
+    path: String,
+    uniform_scale: f32, // applied to every instance before its own transform
+    pub instances: Vec<Instance>,
+    pub tracks: Option<Vec<Track>>, // uniform_scale
+    cache: Option<bool>, // same as a Model's
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Instance {
+    pub translation: Vector3<f32>,
+    pub euler_angles: [f32; 3],
+    pub uniform_scale: Option<f32>,
+    pub tint: Option<Vector3<f32>>,
+}
+
+impl Animatable for Instances {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "uniform_scale" => self.uniform_scale = value.scalar(property),
+            _ => unknown_property("Instances", property),
+        }
+    }
+}
+
+impl Instance {
+    fn to_world(&self) -> Matrix4<f32> {
+        model_transform(&self.translation, self.uniform_scale.unwrap_or(1.0), &self.euler_angles)
+    }
+    fn tint(&self) -> Vector3<f32> {
+        self.tint.unwrap_or(Vector3::repeat(1.0))
+    }
+}
+
+impl Instances {
+    // every placement of every mesh points at the same bottom level structure, shared with plain models of the file
+    pub fn to_instances(&self) -> Vec<MeshInstance> {
+        let blases = shared_blases(&self.path, None, self.cache.unwrap_or(true), || load_model(&self.path, &Matrix4::identity()));
+        let scale = Matrix4::new_scaling(self.uniform_scale);
+
+        self.instances.iter()
+            .flat_map(|inst| {
+                let to_world = inst.to_world() * scale;
+                blases.iter().map(move |b| MeshInstance::new(b.clone(), to_world, inst.tint()))
+            })
+            .collect()
+    }
+
+    // gpu has no instancing, every placement gets its own baked copy
+    pub fn to_meshes(&self) -> Vec<Mesh> {
+        let object_meshes = self.object_meshes();
+
+        self.instances.iter()
+            .flat_map(|inst| {
+                let tint = inst.tint();
+                object_meshes.iter().map(move |m| {
+                    let mut mesh = m.transformed(&inst.to_world());
+                    mesh.rgb_info.iter_mut().for_each(|rgb| rgb.factor = rgb.factor.component_mul(&tint));
+                    mesh
+                })
+            })
+            .collect()
+    }
+
+    fn object_meshes(&self) -> Vec<Mesh> {
+        let scale = Matrix4::new_scaling(self.uniform_scale);
+        match self.cache.unwrap_or(true) {
+            true => cached_meshes(&self.path, &scale, None, || load_model(&self.path, &Matrix4::identity())),
+            false => load_model(&self.path, &scale),
+        }
+    }
+}
@@ -1,9 +1,27 @@
 mod distant_cube_map;
+mod environment;
+mod sky;
 mod free_triangle;
 mod model;
 mod cam;
+mod area_light;
+mod delta_light;
+mod instances;
+mod csg;
+mod sdf;
+mod heightfield;
+mod curves;
 
 pub use distant_cube_map::*;
-pub use free_triangle::FreeTriangle;
+pub use environment::Environment;
+pub use sky::Sky;
+pub use free_triangle::{FreeTriangle, FreeQuad};
 pub use model::*;
-pub use cam::Cam;
\ No newline at end of file
+pub use cam::{Cam, deserialize_cam};
+pub use area_light::{RectLight, DiskLight};
+pub use delta_light::{PointLight, SpotLight, DirectionalLight};
+pub use instances::Instances;
+pub use csg::Csg;
+pub use sdf::Sdf;
+pub use heightfield::Heightfield;
+pub use curves::Curves;
\ No newline at end of file
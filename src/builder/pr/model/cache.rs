@@ -0,0 +1,172 @@
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, UNIX_EPOCH};
+use serde::{Serialize, de::DeserializeOwned};
+
+// relative to wherever the renderer runs, like anim_frames
+const CACHE_DIR: &str = "model_cache";
+// bump whenever anything written here changes shape or loading changes what it makes, old entries then just stop
+// matching
+const FORMAT_VERSION: u32 = 3;
+
+type Deps = Vec<(String, Option<u128>)>; // every file a build read and its modification time, None if it was missing
+
+thread_local! {
+    // files opened by the builds running on this thread, innermost last. a rayon thread waiting on one model's build
+    // can pick up another's, so it's a stack
+    static OPENED: RefCell<Vec<Vec<PathBuf>>> = const { RefCell::new(vec![]) };
+}
+
+// loaders call this for every file they read besides the model file itself (buffers, textures, mtls), so entries
+// built from it stop matching once it changes. does nothing outside of a cached build
+pub fn opened(path: &Path) {
+    OPENED.with_borrow_mut(|stack| {
+        if let Some(files) = stack.last_mut() {
+            files.push(path.to_path_buf());
+        }
+    });
+}
+
+// what build makes from the model file at path, kept on disk so the next run with the same file can read it back
+// instead. key holds whatever else changes the result (subdivision, ...), and an entry stops matching once the file
+// or anything the loader opened with it is modified. anything going wrong with the cache only costs the build
+pub fn cached<T: Serialize + DeserializeOwned>(path: &str, what: &str, key: &str, build: impl FnOnce() -> T) -> T {
+    cached_in(Path::new(CACHE_DIR), path, what, key, build)
+}
+
+fn cached_in<T: Serialize + DeserializeOwned>(dir: &Path, path: &str, what: &str, key: &str, build: impl FnOnce() -> T) -> T {
+    let Ok(file) = fs::canonicalize(path) else {
+        return build();
+    };
+    let full_key = format!("{} {} {} {}", FORMAT_VERSION, what, file.display(), key);
+    let entry = entry_path(dir, path, what, &full_key);
+
+    let start = Instant::now();
+    match read(&entry, &full_key) {
+        Ok(Some(t)) => {
+            println!("loaded {} for {} from {} in {:.3?}", what, path, entry.display(), start.elapsed());
+            return t;
+        },
+        Ok(None) => {},
+        Err(e) => println!("ignoring unreadable cache entry {}: {}", entry.display(), e),
+    }
+
+    OPENED.with_borrow_mut(|stack| stack.push(vec![]));
+    let t = build();
+    let mut files = OPENED.with_borrow_mut(|stack| stack.pop().unwrap());
+    files.insert(0, file);
+    files.dedup();
+    let deps: Deps = files.iter().map(|f| (f.display().to_string(), modified(f))).collect();
+
+    if let Err(e) = write(dir, &entry, &full_key, &deps, &t) {
+        println!("couldn't write cache entry {}: {}", entry.display(), e);
+    }
+    t
+}
+
+fn modified(file: &Path) -> Option<u128> {
+    let modified = fs::metadata(file).and_then(|m| m.modified()).ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+// readable enough to find a model's entries by eye, the hash tells the rest apart. editing the model rewrites the
+// same entry rather than adding another
+fn entry_path(dir: &Path, path: &str, what: &str, full_key: &str) -> PathBuf {
+    let stem = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("model");
+    let what = what.replace(' ', "_");
+    dir.join(format!("{}-{}-{:016x}.bin", stem, what, fnv1a(full_key)))
+}
+
+// fnv-1a, std's hasher isn't promised to stay the same between rust versions
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+// None when there's no entry, it belongs to a different key that hashed the same, or a file it was built from changed
+fn read<T: DeserializeOwned>(entry: &Path, full_key: &str) -> io::Result<Option<T>> {
+    let file = match File::open(entry) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut reader = BufReader::new(file);
+    let stored_key: String = bincode::deserialize_from(&mut reader).map_err(io::Error::other)?;
+    if stored_key != full_key {
+        return Ok(None);
+    }
+    let deps: Deps = bincode::deserialize_from(&mut reader).map_err(io::Error::other)?;
+    if let Some((changed, _)) = deps.iter().find(|(f, at)| modified(Path::new(f)) != *at) {
+        println!("{} changed since {} was written, rebuilding it", changed, entry.display());
+        return Ok(None);
+    }
+    bincode::deserialize_from(&mut reader).map(Some).map_err(io::Error::other)
+}
+
+// written next to the entry then renamed over it, so a run stopped halfway never leaves half an entry behind
+fn write<T: Serialize>(dir: &Path, entry: &Path, full_key: &str, deps: &Deps, t: &T) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let partial = entry.with_extension(format!("partial{}", std::process::id()));
+    let written = File::create(&partial).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, full_key).map_err(io::Error::other)?;
+        bincode::serialize_into(&mut writer, deps).map_err(io::Error::other)?;
+        bincode::serialize_into(&mut writer, t).map_err(io::Error::other)?;
+        io::Write::flush(&mut writer)
+    });
+    match written {
+        Ok(()) => fs::rename(&partial, entry),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_entries_rebuild_when_their_files_change() {
+        let dir = std::env::temp_dir().join(format!("model_cache_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let model = dir.join("model.obj");
+        let texture = dir.join("texture.png");
+        fs::write(&model, "v 0 0 0").unwrap();
+        fs::write(&texture, "pixels").unwrap();
+        let model = model.to_str().unwrap();
+        let cache_dir = dir.join("cache");
+
+        let builds = std::cell::Cell::new(0);
+        let get = |key: &str| cached_in(&cache_dir, model, "meshes", key, || {
+            opened(&texture);
+            builds.set(builds.get() + 1);
+            vec![1.5f32, 2.5]
+        });
+
+        assert_eq!(get("a"), vec![1.5, 2.5]);
+        assert_eq!(get("a"), vec![1.5, 2.5]);
+        assert_eq!(builds.get(), 1, "second read should come from the entry");
+
+        get("b");
+        assert_eq!(builds.get(), 2, "a different key is a different entry");
+
+        // a file the build opened, not just the model itself
+        let later = SystemTime::now() + Duration::from_secs(5);
+        File::options().write(true).open(&texture).unwrap().set_modified(later).unwrap();
+        get("a");
+        assert_eq!(builds.get(), 3, "changed texture should force a rebuild");
+        get("a");
+        assert_eq!(builds.get(), 3);
+
+        File::options().write(true).open(model).unwrap().set_modified(later + Duration::from_secs(5)).unwrap();
+        get("a");
+        assert_eq!(builds.get(), 4, "changed model should force a rebuild");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
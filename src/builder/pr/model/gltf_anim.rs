@@ -0,0 +1,153 @@
+// samples gltf animation channels into local node transforms and morph weights at a point in time
+use std::collections::HashMap;
+use nalgebra::{Vector3, Vector4, Matrix4, Quaternion, UnitQuaternion};
+use gltf::animation::{Interpolation, util::ReadOutputs};
+
+// which animation to play and when, None name plays every animation in the file together
+#[derive(Clone, Copy, Debug)]
+pub struct AnimAt<'a> {
+    pub name: Option<&'a str>,
+    pub time: f32,
+    pub looping: bool,
+}
+
+// only the parts a channel actually drives, the rest come from the node itself
+#[derive(Default)]
+pub struct AnimatedNode {
+    translation: Option<Vector3<f32>>,
+    rotation: Option<UnitQuaternion<f32>>,
+    scale: Option<Vector3<f32>>,
+    weights: Option<Vec<f32>>, // morph target weights for the node's mesh
+}
+
+pub type NodePoses = HashMap<usize, AnimatedNode>; // node index -> animated trs and weights
+
+impl AnimatedNode {
+    pub fn local_matrix(&self, node: &gltf::Node) -> Matrix4<f32> {
+        let (t, [x, y, z, w], s) = node.transform().decomposed();
+        let t = self.translation.unwrap_or(Vector3::from(t));
+        let r = self.rotation.unwrap_or(UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)));
+        let s = self.scale.unwrap_or(Vector3::from(s));
+
+        Matrix4::new_translation(&t) * r.to_homogeneous() * Matrix4::new_nonuniform_scaling(&s)
+    }
+
+    pub fn weights(&self) -> Option<&[f32]> {
+        self.weights.as_deref()
+    }
+}
+
+fn chosen<'d>(document: &'d gltf::Document, name: Option<&'d str>) -> impl Iterator<Item = gltf::Animation<'d>> {
+    document.animations().filter(move |a| name.is_none() || a.name() == name)
+}
+
+fn length(anim: &gltf::Animation, buffers: &[gltf::buffer::Data]) -> f32 {
+    anim.channels()
+        .filter_map(|c| c.reader(|b| Some(&buffers[b.index()])).read_inputs())
+        .filter_map(|inputs| inputs.last())
+        .fold(0.0, f32::max)
+}
+
+// longest of the chosen animations, in seconds
+pub fn duration(document: &gltf::Document, buffers: &[gltf::buffer::Data], name: Option<&str>) -> f32 {
+    chosen(document, name).map(|a| length(&a, buffers)).fold(0.0, f32::max)
+}
+
+pub fn sample(document: &gltf::Document, buffers: &[gltf::buffer::Data], at: &AnimAt) -> NodePoses {
+    let mut poses = NodePoses::new();
+    if chosen(document, at.name).next().is_none() {
+        // sampled every frame, once is plenty
+        static WARN: std::sync::Once = std::sync::Once::new();
+        WARN.call_once(|| println!("no gltf animation {} to play, leaving the model still", at.name.unwrap_or("at all")));
+    }
+
+    for anim in chosen(document, at.name) {
+        let length = length(&anim, buffers);
+        // each animation loops over its own length
+        let time = if at.looping && length > 0.0 { at.time.rem_euclid(length) } else { at.time };
+
+        for channel in anim.channels() {
+            let reader = channel.reader(|b| Some(&buffers[b.index()]));
+            let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else { continue };
+            let times: Vec<f32> = inputs.collect();
+            if times.is_empty() {
+                continue;
+            }
+            let interp = channel.sampler().interpolation();
+            let pose = poses.entry(channel.target().node().index()).or_default();
+
+            match outputs {
+                ReadOutputs::Translations(vals) => {
+                    let vals: Vec<Vector4<f32>> = vals.map(|[x, y, z]| Vector4::new(x, y, z, 0.0)).collect();
+                    pose.translation = Some(sample_values(&times, &vals, interp, time, false).xyz());
+                },
+                ReadOutputs::Rotations(vals) => {
+                    let vals: Vec<Vector4<f32>> = vals.into_f32().map(Vector4::from).collect(); // x y z w, same as nalgebra's coords
+                    pose.rotation = Some(UnitQuaternion::from_quaternion(Quaternion::from(sample_values(&times, &vals, interp, time, true))));
+                },
+                ReadOutputs::Scales(vals) => {
+                    let vals: Vec<Vector4<f32>> = vals.map(|[x, y, z]| Vector4::new(x, y, z, 0.0)).collect();
+                    pose.scale = Some(sample_values(&times, &vals, interp, time, false).xyz());
+                },
+                ReadOutputs::MorphTargetWeights(vals) => {
+                    let vals: Vec<f32> = vals.into_f32().collect();
+                    let keys = if interp == Interpolation::CubicSpline { times.len() * 3 } else { times.len() };
+                    let num_targets = vals.len() / keys;
+                    // each key holds one weight per target, sample every target on its own
+                    pose.weights = Some((0..num_targets).map(|target| {
+                        let target_vals: Vec<Vector4<f32>> = vals.chunks(num_targets).map(|c| Vector4::new(c[target], 0.0, 0.0, 0.0)).collect();
+                        sample_values(&times, &target_vals, interp, time, false).x
+                    }).collect());
+                },
+            }
+        }
+    }
+
+    poses
+}
+
+// translations and scales ride along in the first 3 lanes, rotations use all 4, morph weights just the first
+fn sample_values(times: &[f32], vals: &[Vector4<f32>], interp: Interpolation, t: f32, is_rotation: bool) -> Vector4<f32> {
+    let cubic = interp == Interpolation::CubicSpline;
+    // cubic spline keys come as (in tangent, value, out tangent)
+    let value = |k: usize| if cubic { vals[k * 3 + 1] } else { vals[k] };
+
+    // clamp outside the keyed range
+    let k = times.partition_point(|key| *key <= t);
+    if k == 0 {
+        return value(0);
+    }
+    if k == times.len() {
+        return value(times.len() - 1);
+    }
+
+    let (i, j) = (k - 1, k);
+    let dt = times[j] - times[i];
+    let u = if dt > 0.0 { (t - times[i]) / dt } else { 0.0 };
+
+    match interp {
+        Interpolation::Step => value(i),
+        Interpolation::Linear if is_rotation => slerp(value(i), value(j), u),
+        Interpolation::Linear => value(i).lerp(&value(j), u),
+        Interpolation::CubicSpline => {
+            let (u2, u3) = (u * u, u * u * u);
+            let out_tangent = vals[i * 3 + 2] * dt;
+            let in_tangent = vals[j * 3] * dt;
+            let v = value(i) * (2.0 * u3 - 3.0 * u2 + 1.0)
+                + out_tangent * (u3 - 2.0 * u2 + u)
+                + value(j) * (-2.0 * u3 + 3.0 * u2)
+                + in_tangent * (u3 - u2);
+            if is_rotation { v.normalize() } else { v }
+        },
+    }
+}
+
+// shortest way round, falls back to nlerp when the two are nearly the same
+fn slerp(a: Vector4<f32>, b: Vector4<f32>, u: f32) -> Vector4<f32> {
+    let (b, cos) = if a.dot(&b) < 0.0 { (-b, -a.dot(&b)) } else { (b, a.dot(&b)) };
+    if cos > 0.9995 {
+        return a.lerp(&b, u).normalize();
+    }
+    let theta = cos.acos();
+    (a * ((1.0 - u) * theta).sin() + b * (u * theta).sin()) / theta.sin()
+}
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use nalgebra::{Vector3, Matrix3, Matrix4};
+use crate::elements::mesh::{Mesh, PbrMetalRoughInfo, RgbInfo, NormInfo, TransmissionInfo};
+use crate::elements::light::{PointLight, SpotLight, DirectionalLight};
+use crate::elements::Light;
+use image::{DynamicImage, ImageBuffer};
+use nalgebra::Vector2;
+use crate::material::UVRgb32FImage;
+use super::gltf_anim::{self, AnimAt, NodePoses};
+use super::cache;
+
+pub fn load(path: &str, transform: &Matrix4<f32>, anim: Option<&AnimAt>) -> Vec<Mesh> {
+    let mut meshes: Vec<Mesh> = vec![];
+    let (document, buffers, images) = gltf::import(path).unwrap();
+    note_external_files(path, &document);
+    let poses = anim.map(|at| gltf_anim::sample(&document, &buffers, at)).unwrap_or_default();
+
+    // skins can point at joints anywhere in the hierarchy, so get every node's place first
+    let mut world_mats: HashMap<usize, Matrix4<f32>> = HashMap::new();
+    explore_document(&document, transform, &poses, &mut |node, trans_mat| {
+        world_mats.insert(node.index(), *trans_mat);
+    });
+
+    explore_document(&document, transform, &poses, &mut |node, trans_mat| {
+        if let Some(mesh) = node.mesh() {
+            let joint_mats = node.skin().map(|skin| joint_matrices(&skin, &buffers, &world_mats));
+            // animated weights win over the node's, which win over the mesh's defaults
+            let weights = poses.get(&node.index()).and_then(|p| p.weights())
+                .or(node.weights())
+                .or(mesh.weights())
+                .unwrap_or(&[]);
+            meshes.push(generate_mesh(&mesh, &buffers, &images, trans_mat, joint_mats.as_deref(), weights));
+        }
+    });
+
+    // println!("Model loaded from {}:", path);
+    // println!("Total triangle count: {}", meshes.iter().map(|m| m.indices.iter().map(|idxs| idxs.len() as u32)).flatten().sum::<u32>());
+
+    meshes
+}
+
+// KHR_lights_punctual lights, gltf's candela and lux are taken as our intensity and irradiance as is
+pub fn load_lights(path: &str, transform: &Matrix4<f32>, intensity_scale: f32, anim: Option<&AnimAt>) -> Vec<Light> {
+    use gltf::khr_lights_punctual::Kind;
+    let (document, poses) = match anim {
+        Some(at) => {
+            let (document, buffers) = open_with_buffers(path);
+            let poses = gltf_anim::sample(&document, &buffers, at);
+            (document, poses)
+        },
+        None => (open_document(path), NodePoses::new()),
+    };
+    let mut lights: Vec<Light> = vec![];
+
+    explore_document(&document, transform, &poses, &mut |node, trans_mat| {
+        let Some(light) = node.light() else { return };
+        let pose = NodePose::from_transform(trans_mat);
+        let color = Vector3::from(light.color()) * light.intensity() * intensity_scale;
+
+        lights.push(match light.kind() {
+            Kind::Point => Box::new(PointLight { pos: pose.o, intensity: color }),
+            Kind::Spot { inner_cone_angle, outer_cone_angle } => Box::new(SpotLight {
+                pos: pose.o,
+                dir: pose.forward,
+                intensity: color,
+                cos_outer: outer_cone_angle.cos(),
+                cos_inner: inner_cone_angle.cos(),
+            }),
+            Kind::Directional => Box::new(DirectionalLight { dir: pose.forward, irradiance: color, sin2_half_angle: 0.0 }),
+        });
+    });
+
+    lights
+}
+
+pub struct GltfCamera {
+    pub pose: NodePose,
+    pub yfov: f32, // radians, whole vertical angle
+    pub aspect_ratio: Option<f32>,
+}
+
+// camera with the given name, or the first one found
+pub fn load_camera(path: &str, name: Option<&str>, transform: &Matrix4<f32>) -> GltfCamera {
+    use gltf::camera::Projection;
+    let document = open_document(path);
+    let mut found: Option<GltfCamera> = None;
+
+    explore_document(&document, transform, &NodePoses::new(), &mut |node, trans_mat| {
+        let Some(camera) = node.camera() else { return };
+        if found.is_some() || name.is_some_and(|n| camera.name() != Some(n) && node.name() != Some(n)) {
+            return;
+        }
+        match camera.projection() {
+            Projection::Perspective(p) => {
+                found = Some(GltfCamera { pose: NodePose::from_transform(trans_mat), yfov: p.yfov(), aspect_ratio: p.aspect_ratio() });
+            },
+            Projection::Orthographic(_) => panic!("gltf camera {:?} in {} is orthographic, only perspective cameras work", camera.name(), path),
+        }
+    });
+
+    found.unwrap_or_else(|| panic!("no gltf camera {} in {}", name.unwrap_or("at all"), path))
+}
+
+// where a light or camera node ends up, gltf has both looking down their local -z with +y up
+pub struct NodePose {
+    pub o: Vector3<f32>,
+    pub forward: Vector3<f32>,
+    pub up: Vector3<f32>,
+}
+
+impl NodePose {
+    fn from_transform(trans_mat: &Matrix4<f32>) -> Self {
+        NodePose {
+            o: trans_mat.transform_point(&Vector3::zeros().into()).coords,
+            forward: trans_mat.transform_vector(&-Vector3::z()).normalize(),
+            up: trans_mat.transform_vector(&Vector3::y()).normalize(),
+        }
+    }
+}
+
+// buffers and images kept in their own files, so a cached model notices when they change
+fn note_external_files(path: &str, document: &gltf::Document) {
+    let dir = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new(""));
+    let buffers = document.buffers().filter_map(|b| match b.source() {
+        gltf::buffer::Source::Uri(uri) => Some(uri),
+        gltf::buffer::Source::Bin => None,
+    });
+    let images = document.images().filter_map(|i| match i.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    buffers.chain(images)
+        .filter(|uri| !uri.starts_with("data:"))
+        .for_each(|uri| cache::opened(&dir.join(uri)));
+}
+
+// just the json, no need to pull in buffers and images for lights and cameras
+fn open_document(path: &str) -> gltf::Document {
+    gltf::Gltf::open(path).unwrap_or_else(|e| panic!("couldn't open gltf {}: {}", path, e)).document
+}
+
+// longest of the named animation (or all of them) in seconds, 0 if there are none
+pub fn animation_duration(path: &str, name: Option<&str>) -> f32 {
+    let (document, buffers) = open_with_buffers(path);
+    gltf_anim::duration(&document, &buffers, name)
+}
+
+// animation samplers live in the buffers, images still aren't needed
+fn open_with_buffers(path: &str) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path).unwrap_or_else(|e| panic!("couldn't open gltf {}: {}", path, e));
+    let buffers = gltf::import_buffers(&document, std::path::Path::new(path).parent(), blob)
+        .unwrap_or_else(|e| panic!("couldn't load buffers for gltf {}: {}", path, e));
+    (document, buffers)
+}
+
+fn explore_document<F: FnMut(&gltf::Node, &Matrix4<f32>)>(document: &gltf::Document, transform: &Matrix4<f32>, poses: &NodePoses, visit: &mut F) {
+    for scene in document.scenes() {
+        for node in scene.nodes() {
+            explore_node(&node, transform, poses, visit);
+        }
+    }
+}
+
+fn explore_node<F: FnMut(&gltf::Node, &Matrix4<f32>)>(node: &gltf::Node, trans_mat: &Matrix4<f32>, poses: &NodePoses, visit: &mut F) {
+    let local = match poses.get(&node.index()) {
+        Some(pose) => pose.local_matrix(node),
+        None => Matrix4::<f32>::from_iterator(node.transform().matrix().into_iter().flat_map(|e| e.into_iter())),
+    };
+    let trans_mat = (*trans_mat) * local;
+
+    visit(node, &trans_mat);
+
+    for child in node.children() {
+        explore_node(&child, &trans_mat, poses, visit);
+    }
+}
+
+// world space joint transforms times their inverse binds, ready to blend per vertex
+fn joint_matrices(skin: &gltf::Skin, buffers: &[gltf::buffer::Data], world_mats: &HashMap<usize, Matrix4<f32>>) -> Vec<Matrix4<f32>> {
+    let inverse_binds: Vec<Matrix4<f32>> = skin.reader(|b| Some(&buffers[b.index()]))
+        .read_inverse_bind_matrices()
+        .map(|ibms| ibms.map(|m| Matrix4::from_iterator(m.into_iter().flatten())).collect())
+        .unwrap_or_default(); // missing means identities
+
+    skin.joints().enumerate().map(|(i, joint)| {
+        let world = world_mats.get(&joint.index()).unwrap_or_else(|| panic!("skin joint {} isn't in any scene?", joint.index()));
+        world * inverse_binds.get(i).copied().unwrap_or(Matrix4::identity())
+    }).collect()
+}
+
+fn generate_mesh(mesh: &gltf::Mesh, buffers: &Vec<gltf::buffer::Data>, 
+    images: &Vec<gltf::image::Data>, trans_mat: &Matrix4<f32>,
+    joint_mats: Option<&[Matrix4<f32>]>, morph_weights: &[f32],
+) -> Mesh 
+{
+    // skinned vertices land in world space straight from the joints, the mesh node's own transform is ignored
+    let trans_mat = if joint_mats.is_some() { Matrix4::identity() } else { *trans_mat };
+    let mut mesh_ =  Mesh {
+        poses: vec![],
+        norms: vec![],
+        indices: vec![],
+        rgb_info: vec![],
+        norm_info: vec![],
+        tangents: vec![],
+        metal_rough: vec![],
+        transmission: vec![],
+        
+        textures: vec![],
+        normal_maps: vec![],
+        metal_rough_maps: vec![],
+
+        trans_mat,
+    };
+
+    for primitive in mesh.primitives() {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()].0));
+
+        let flat_indices: Vec<usize> = reader.read_indices().unwrap()
+                .into_u32()
+                .map(|v| v.try_into().unwrap())
+                .collect();
+
+        let mut poses: Vec<Vector3<f32>> = reader.read_positions().unwrap().map(|p| p.into()).collect();
+        let mut norms: Vec<Vector3<f32>> = reader.read_normals().unwrap().map(|p| p.into()).collect();
+        let mut tangents: Option<Vec<Vector3<f32>>> = reader.read_tangents().map(|tans| tans.map(|t| Vector3::new(t[0], t[1], t[2])).collect());
+
+        // morph targets first, then skinning, same order as the spec
+        for ((pos_disp, norm_disp, tan_disp), weight) in reader.read_morph_targets().zip(morph_weights) {
+            if *weight == 0.0 {
+                continue;
+            }
+            pos_disp.into_iter().flatten().zip(poses.iter_mut()).for_each(|(d, p)| *p += Vector3::from(d) * *weight);
+            norm_disp.into_iter().flatten().zip(norms.iter_mut()).for_each(|(d, n)| *n += Vector3::from(d) * *weight);
+            if let Some(tangents) = &mut tangents {
+                tan_disp.into_iter().flatten().zip(tangents.iter_mut()).for_each(|(d, t)| *t += Vector3::from(d) * *weight);
+            }
+        }
+
+        if let Some(joint_mats) = joint_mats {
+            let joints: Vec<[u16; 4]> = reader.read_joints(0).expect("skinned primitive without JOINTS_0?").into_u16().collect();
+            let weights: Vec<[f32; 4]> = reader.read_weights(0).expect("skinned primitive without WEIGHTS_0?").into_f32().collect();
+
+            for (i, (js, ws)) in joints.iter().zip(&weights).enumerate() {
+                let skin_mat: Matrix4<f32> = js.iter().zip(ws)
+                    .map(|(j, w)| joint_mats[*j as usize] * *w)
+                    .sum();
+                let skin_mat3: Matrix3<f32> = skin_mat.fixed_view::<3, 3>(0, 0).into();
+                let norm_mat3 = skin_mat3.try_inverse().unwrap_or(skin_mat3).transpose();
+
+                poses[i] = skin_mat.transform_point(&poses[i].into()).coords;
+                norms[i] = (norm_mat3 * norms[i]).normalize();
+                if let Some(tangents) = &mut tangents {
+                    tangents[i] = skin_mat3 * tangents[i];
+                }
+            }
+        }
+
+        let poses: Vec<Vector3<f32>> = poses.iter()
+            .map(|v| v.fixed_resize::<4, 1>(1.0)) // vec4 with 1 last entry for transform
+            .map(|v| trans_mat * v)
+            .map(|v| v.fixed_resize::<3, 1>(0.0))
+            .collect();
+
+        let material = primitive.material();
+        let pbr_met_rough = material.pbr_metallic_roughness();
+        
+        let (textures, tex_coords) = texinfo_to_uvtex_and_coords(&pbr_met_rough.base_color_texture(), &reader, &images);
+        let base_color_factor: [f32; 3] = pbr_met_rough.base_color_factor()[..3].try_into().unwrap();
+        let rgb_info = RgbInfo {
+            factor: base_color_factor.into(),
+            coords: tex_coords,
+            vert_colors: None,
+        };
+        let (normal_maps, norm_info) = match material.normal_texture() {
+                Some(n_info) => {
+                    let (normal_maps, norm_coords) = get_uvtex_and_coords(&n_info.texture(), n_info.tex_coord(), &reader, &images);
+                    (normal_maps, Some(NormInfo { scale: n_info.scale(), coords: norm_coords.unwrap() }))
+                },
+                None => {
+                    (None, None)
+                },
+            };
+
+        let (metal_rough_maps, mr_coords) = texinfo_to_uvtex_and_coords(&pbr_met_rough.metallic_roughness_texture(), &reader, &images);
+        let metal_rough = PbrMetalRoughInfo {
+            metal: pbr_met_rough.metallic_factor(),
+            rough: pbr_met_rough.roughness_factor(),
+            coords: mr_coords,
+        };
+
+        mesh_.poses.push(poses);
+        mesh_.norms.push(norms);
+        mesh_.indices.push(flat_indices.chunks(3).map(|c| c.try_into().unwrap()).collect());
+        mesh_.rgb_info.push(rgb_info);
+        mesh_.norm_info.push(norm_info);
+        mesh_.tangents.push(tangents);
+        mesh_.metal_rough.push(metal_rough);
+        mesh_.transmission.push(TransmissionInfo::default());
+        mesh_.textures.push(textures);
+        mesh_.normal_maps.push(normal_maps);
+        mesh_.metal_rough_maps.push(metal_rough_maps);
+
+    };
+
+    mesh_
+}
+
+use gltf::texture::Info;
+use gltf::mesh::Reader;
+use gltf::image::Data;
+use gltf::{Buffer, Texture};
+
+fn texinfo_to_uvtex_and_coords<'a, 's, F>(tex_info: &Option<Info>, reader: &Reader<'a, 's, F>, images: &Vec<Data>) -> (Option<UVRgb32FImage>, Option<Vec<Vector2<f32>>>) 
+where
+    F: Clone + Fn(Buffer<'a>) -> Option<&'s [u8]>,
+{
+    match tex_info {
+        Some(info) => get_uvtex_and_coords(&info.texture(), info.tex_coord(), reader, images),
+        None => (None, None),
+    }
+}
+
+fn get_uvtex_and_coords<'a, 's, F>(texture: &Texture, tex_coord: u32, reader: &Reader<'a, 's, F>, images: &Vec<Data>) -> (Option<UVRgb32FImage>, Option<Vec<Vector2<f32>>>)
+where
+    F: Clone + Fn(Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let coords: Vec<Vector2<f32>> = reader.read_tex_coords(tex_coord).expect("no metal roughness map coordinates?").into_f32().map(|p| p.into()).collect();
+
+    let image_data = &images[texture.index()];
+    use gltf::image::Format::*;
+    // println!("format!!!! : {:?}", image_data.format);
+    let dyn_image = match image_data.format {
+        R8 => DynamicImage::ImageLuma8(
+            ImageBuffer::from_raw(image_data.width, image_data.height, image_data.pixels.clone()).expect("doesn't fit??")
+        ),
+        R8G8B8 => DynamicImage::ImageRgb8(
+            ImageBuffer::from_raw(image_data.width, image_data.height, image_data.pixels.clone()).expect("doesn't fit??")
+        ),
+        R8G8B8A8 => DynamicImage::ImageRgba8(
+            ImageBuffer::from_raw(image_data.width, image_data.height, image_data.pixels.clone()).expect("doesn't fit??")
+        ),
+        R16G16B16 => DynamicImage::ImageRgb16(
+            ImageBuffer::from_raw(image_data.width, image_data.height, 
+                image_data.pixels.clone()
+                .chunks(2)
+                .map(|c| unsafe { std::mem::transmute::<[u8; 2], u16>(c.try_into().unwrap()) })
+                .collect())
+                .expect("doesn't fit??")
+        ),
+        R16G16B16A16 => DynamicImage::ImageRgba16(
+            ImageBuffer::from_raw(image_data.width, image_data.height, 
+                image_data.pixels.clone()
+                .chunks(2)
+                .map(|c| unsafe { std::mem::transmute::<[u8; 2], u16>(c.try_into().unwrap()) })
+                .collect())
+                .expect("doesn't fit??")
+        ),
+        R32G32B32FLOAT => DynamicImage::ImageRgb32F(
+            ImageBuffer::from_raw(image_data.width, image_data.height, 
+                image_data.pixels.clone()
+                .chunks(4)
+                .map(|c| unsafe { std::mem::transmute::<[u8; 4], f32>(c.try_into().unwrap()) })
+                .collect())
+                .expect("doesn't fit??")
+        ),
+        R32G32B32A32FLOAT => DynamicImage::ImageRgba32F(
+            ImageBuffer::from_raw(image_data.width, image_data.height, 
+                image_data.pixels.clone()
+                .chunks(4)
+                .map(|c| unsafe { std::mem::transmute::<[u8; 4], f32>(c.try_into().unwrap()) })
+                .collect())
+                .expect("doesn't fit??")
+        ),
+        _ => { panic!("different image format??"); },
+    };
+    let image = dyn_image.to_rgb32f();
+
+    (Some(image.into()), Some(coords))
+}
\ No newline at end of file
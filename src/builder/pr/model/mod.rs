@@ -0,0 +1,246 @@
+use nalgebra::{Vector3, Matrix4};
+use serde::Deserialize;
+use std::path::Path;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use rayon::prelude::*;
+use crate::elements::mesh::{Mesh, PbrMetalRoughInfo, RgbInfo, TransmissionInfo, SubdivScheme, InstancedModel, MeshInstance};
+use crate::elements::Light;
+use crate::accel::TreeStats;
+use crate::builder::Anim;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+use gltf_anim::AnimAt;
+
+pub use gltf_import::load_camera;
+
+mod cache;
+mod gltf_anim;
+mod gltf_import;
+mod obj;
+mod ply;
+mod stl;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Model {
+    path: String,
+    uniform_scale: f32,
+    pub translation: Vector3<f32>,
+    pub euler_angles: [f32; 3],
+    pub animation: Option<Anim>,
+    pub tracks: Option<Vec<Track>>, // translation, euler_angles and uniform_scale, applied after animation
+    import_lights: Option<bool>, // KHR_lights_punctual lights from a gltf, off by default
+    light_scale: Option<f32>, // multiplies imported light intensities, exporters disagree a lot on units
+    gltf_animation: Option<GltfAnimation>, // plays the file's own node animations
+    subdivision_level: Option<u32>, // each level splits every face into four, 0 by default
+    subdivision_scheme: Option<SubdivScheme>, // loop by default
+    crease_angle: Option<f32>, // degrees, edges folded sharper than this stay sharp when subdividing
+    cache: Option<bool>, // keeps what gets loaded and built from the file in model_cache for next time, on by default
+    #[serde(skip)]
+    anim_time: Option<f32>, // set per frame by extract_anim
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GltfAnimation {
+    name: Option<String>, // plays every animation in the file if not given
+    looping: Option<bool>,
+}
+
+impl Model {
+    // world space, for the gpu which has no instancing
+    pub fn to_meshes(&self) -> Vec<Mesh> {
+        let transform = self.transform();
+        match self.anim_at() {
+            None if self.cache.unwrap_or(true) => {
+                cached_meshes(&self.path, &transform, self.subdivision(), || self.load(&Matrix4::identity()))
+            },
+            _ => self.load(&transform),
+        }
+    }
+
+    // one instance per mesh, each over that mesh's own bvh in object space so moving the model doesn't rebuild it
+    pub fn to_instances(&self) -> Vec<MeshInstance> {
+        let blases = match self.anim_at() {
+            // posed differently every frame, no point keeping these around
+            Some(_) => build_blases(self.load(&Matrix4::identity())).into_iter().map(Arc::new).collect(),
+            None => shared_blases(&self.path, self.subdivision(), self.cache.unwrap_or(true), || self.load(&Matrix4::identity())),
+        };
+        let to_world = self.transform();
+        blases.into_iter().map(|b| MeshInstance::new(b, to_world, Vector3::repeat(1.0))).collect()
+    }
+
+    fn subdivision(&self) -> Option<Subdivision> {
+        self.subdivision_level.filter(|level| *level > 0).map(|level| (level, self.subdivision_scheme.unwrap_or_default(), self.crease_angle))
+    }
+
+    fn load(&self, transform: &Matrix4<f32>) -> Vec<Mesh> {
+        let meshes = match self.anim_at() {
+            Some(at) => gltf_import::load(&self.path, transform, Some(&at)),
+            None => load_model(&self.path, transform),
+        };
+        match self.subdivision() {
+            Some((level, scheme, crease_angle)) => meshes.iter().map(|m| m.subdivided(scheme, level, crease_angle)).collect(),
+            None => meshes,
+        }
+    }
+
+    pub fn to_lights(&self) -> Vec<Light> {
+        if !self.import_lights.unwrap_or(false) {
+            return vec![];
+        }
+        if !is_gltf(&self.path) {
+            println!("import_lights only works for gltf models, {} has no lights to give", self.path);
+            return vec![];
+        }
+        gltf_import::load_lights(&self.path, &self.transform(), self.light_scale.unwrap_or(1.0), self.anim_at().as_ref())
+    }
+
+    // this model posed at time t, on top of whatever translation and angles it already has
+    pub fn at_time(&self, t: f32) -> Model {
+        Model { anim_time: Some(t), ..self.clone() }
+    }
+
+    // how long the gltf animation runs, so the scene's timeline covers it
+    pub fn gltf_animation_duration(&self) -> f32 {
+        match &self.gltf_animation {
+            Some(anim) if is_gltf(&self.path) => gltf_import::animation_duration(&self.path, anim.name.as_deref()),
+            _ => 0.0,
+        }
+    }
+
+    fn anim_at(&self) -> Option<AnimAt<'_>> {
+        let anim = self.gltf_animation.as_ref()?;
+        if !is_gltf(&self.path) {
+            println!("gltf_animation only works for gltf models, {} stays still", self.path);
+            return None;
+        }
+        Some(AnimAt {
+            name: anim.name.as_deref(),
+            time: self.anim_time.unwrap_or(0.0),
+            looping: anim.looping.unwrap_or(false),
+        })
+    }
+
+    fn transform(&self) -> Matrix4<f32> {
+        model_transform(&self.translation, self.uniform_scale, &self.euler_angles)
+    }
+}
+
+impl Animatable for Model {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "translation" => self.translation = value.vector(property),
+            "euler_angles" => self.euler_angles = value.vector(property).into(),
+            "uniform_scale" => self.uniform_scale = value.scalar(property),
+            _ => unknown_property("Model", property),
+        }
+    }
+}
+
+type Subdivision = (u32, SubdivScheme, Option<f32>); // level, scheme, crease angle
+type BlasKey = (String, String); // path, then the subdivision printed since crease angles don't hash
+
+// bottom level structures for every mesh of a file, built once and reused by every model and instance placing it,
+// and by every frame of an animation. they live as long as the program, and on disk too when use_cache
+pub fn shared_blases<F: FnOnce() -> Vec<Mesh>>(path: &str, subdivision: Option<Subdivision>, use_cache: bool, load: F) -> Vec<Arc<InstancedModel>> {
+    static BLASES: OnceLock<Mutex<HashMap<BlasKey, Vec<Arc<InstancedModel>>>>> = OnceLock::new();
+    let key = (path.to_string(), format!("{:?}", subdivision));
+    if let Some(blases) = BLASES.get_or_init(Default::default).lock().unwrap().get(&key) {
+        return blases.clone();
+    }
+    // built outside the lock so other members can load at the same time
+    let build = || {
+        let meshes = load();
+        let start = Instant::now();
+        let blases = build_blases(meshes);
+        let stats = blases.iter().fold(TreeStats::default(), |acc, b| acc.combined(b.stats()));
+        println!("built {} mesh bvhs for {} in {:.3?}: {}", blases.len(), path, start.elapsed(), stats);
+        blases
+    };
+    let blases = match use_cache {
+        true => cache::cached(path, "mesh bvhs", &key.1, build),
+        false => build(),
+    };
+    let blases = blases.into_iter().map(Arc::new).collect();
+    BLASES.get().unwrap().lock().unwrap().entry(key).or_insert(blases).clone()
+}
+
+// the file's meshes moved by transform. load gives them in object space, that's what goes in model_cache so a model
+// that's placed differently (or moves between frames) reuses the same entry
+pub fn cached_meshes<F: FnOnce() -> Vec<Mesh>>(path: &str, transform: &Matrix4<f32>, subdivision: Option<Subdivision>, load: F) -> Vec<Mesh> {
+    let meshes = cache::cached(path, "meshes", &format!("{:?}", subdivision), load);
+    meshes.iter().map(|m| m.transformed(transform)).collect()
+}
+
+// one per mesh, each mesh's tree on its own thread
+fn build_blases(meshes: Vec<Mesh>) -> Vec<InstancedModel> {
+    meshes.into_par_iter().map(|m| InstancedModel::new(vec![m])).collect()
+}
+
+// same placement convention for anything that positions a model in the scene
+pub fn model_transform(translation: &Vector3<f32>, uniform_scale: f32, euler_angles: &[f32; 3]) -> Matrix4<f32> {
+    let [r, p, y] = *euler_angles;
+
+    Matrix4::new_translation(translation)
+    * Matrix4::new_scaling(uniform_scale)
+    * Matrix4::from_euler_angles(r, p, y)
+}
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+pub fn is_gltf(path: &str) -> bool {
+    matches!(extension(path).as_deref(), Some("gltf") | Some("glb"))
+}
+
+// picks the loader from the file extension
+pub fn load_model(path: &str, transform: &Matrix4<f32>) -> Vec<Mesh> {
+    match extension(path).as_deref() {
+        Some("gltf") | Some("glb") => gltf_import::load(path, transform, None),
+        Some("obj") => obj::load(path, transform),
+        Some("ply") => vec![ply::load(path, transform)],
+        Some("stl") => vec![stl::load(path, transform)],
+        _ => panic!("don't know how to load model {}, expected .gltf, .glb, .obj, .ply or .stl", path),
+    }
+}
+
+// single primitive, matte and untextured, for the formats that only carry geometry
+fn plain_mesh(obj_poses: &[Vector3<f32>], norms: Vec<Vector3<f32>>, indices: Vec<[usize; 3]>,
+    vert_colors: Option<Vec<Vector3<f32>>>, trans_mat: &Matrix4<f32>
+) -> Mesh
+{
+    let factor = if vert_colors.is_some() { Vector3::repeat(1.0) } else { Vector3::repeat(0.8) };
+    let mesh = Mesh {
+        poses: vec![obj_poses.iter().map(|p| trans_mat.transform_point(&(*p).into()).coords).collect()],
+        norms: vec![norms],
+        indices: vec![indices],
+        rgb_info: vec![RgbInfo { factor, coords: None, vert_colors }],
+        norm_info: vec![None],
+        tangents: vec![None],
+        metal_rough: vec![PbrMetalRoughInfo { metal: 0.0, rough: 1.0, coords: None }],
+        transmission: vec![TransmissionInfo::default()],
+
+        textures: vec![None],
+        normal_maps: vec![None],
+        metal_rough_maps: vec![None],
+
+        trans_mat: *trans_mat,
+    };
+    mesh.check_num_primitives();
+    mesh
+}
+
+// area weighted average of face normals around each vertex, for files that dont give any
+fn smooth_normals(poses: &[Vector3<f32>], indices: &[[usize; 3]]) -> Vec<Vector3<f32>> {
+    let mut accum = vec![Vector3::zeros(); poses.len()];
+    for idxs in indices {
+        let [a, b, c] = idxs.map(|i| poses[i]);
+        let face = (b - a).cross(&(c - a));
+        idxs.iter().for_each(|i| accum[*i] += face);
+    }
+    accum.iter().map(|n| n.try_normalize(1e-12).unwrap_or(Vector3::y())).collect()
+}
@@ -0,0 +1,299 @@
+// wavefront obj + mtl, parsed by hand since both are just lines of whitespace separated tokens
+// each group (g/o) becomes a Mesh, with a primitive per material used in that group
+use nalgebra::{Vector3, Vector2, Matrix4};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::elements::mesh::{Mesh, PbrMetalRoughInfo, RgbInfo, NormInfo, TransmissionInfo};
+use crate::material::UVRgb32FImage;
+use super::{cache, smooth_normals};
+
+type Corner = (usize, Option<usize>, Option<usize>); // position, tex coord, normal
+
+struct Group {
+    prims: Vec<(Option<String>, Vec<[Corner; 3]>)>, // material name and its triangles
+}
+
+#[derive(Clone)]
+struct Material {
+    kd: Vector3<f32>,
+    ks: Vector3<f32>,
+    ns: f32,
+    ni: f32,
+    d: f32,
+    map_kd: Option<UVRgb32FImage>,
+    map_bump: Option<(UVRgb32FImage, f32)>, // treated as a tangent space normal map, with its -bm scale
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material { kd: Vector3::repeat(0.8), ks: Vector3::zeros(), ns: 0.0, ni: 1.0, d: 1.0, map_kd: None, map_bump: None }
+    }
+}
+
+pub fn load(path: &str, transform: &Matrix4<f32>) -> Vec<Mesh> {
+    let src = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("couldn't read obj {}: {}", path, e));
+    let dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut positions: Vec<Vector3<f32>> = vec![];
+    let mut tex_coords: Vec<Vector2<f32>> = vec![];
+    let mut normals: Vec<Vector3<f32>> = vec![];
+    let mut materials: HashMap<String, Material> = HashMap::new();
+
+    let mut groups: Vec<Group> = vec![Group { prims: vec![] }];
+    let mut curr_mat: Option<String> = None;
+
+    for (line_no, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else { continue };
+        let rest: Vec<&str> = tokens.collect();
+        let bad_line = || -> ! { panic!("bad obj line {} in {}: {}", line_no + 1, path, line) };
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&rest).unwrap_or_else(|| bad_line())),
+            "vn" => normals.push(parse_vec3(&rest).unwrap_or_else(|| bad_line())),
+            "vt" => {
+                let uv = parse_floats(&rest);
+                if uv.is_empty() { bad_line(); }
+                // obj has v going up from the bottom, images go down from the top
+                tex_coords.push(Vector2::new(uv[0], 1.0 - uv.get(1).copied().unwrap_or(0.0)));
+            },
+            "f" => {
+                let corners: Vec<Corner> = rest.iter()
+                    .map(|c| parse_corner(c, positions.len(), tex_coords.len(), normals.len()).unwrap_or_else(|| bad_line()))
+                    .collect();
+                if corners.len() < 3 { bad_line(); }
+
+                let group = groups.last_mut().unwrap();
+                if group.prims.last().is_none_or(|(mat, _)| *mat != curr_mat) {
+                    group.prims.push((curr_mat.clone(), vec![]));
+                }
+                let tris = &mut group.prims.last_mut().unwrap().1;
+                // fan out polygons, fine for the convex faces exporters give
+                tris.extend((1..corners.len() - 1).map(|i| [corners[0], corners[i], corners[i + 1]]));
+            },
+            "g" | "o" if !groups.last().unwrap().prims.is_empty() => groups.push(Group { prims: vec![] }),
+            "usemtl" => curr_mat = Some(rest.join(" ")),
+            "mtllib" => {
+                // file names with spaces in them break this, but so do most other obj tools
+                for lib in rest {
+                    materials.extend(load_mtl(&dir.join(lib), &dir));
+                }
+            },
+            _ => {}, // smoothing groups, lines, points etc. dont mean anything here
+        }
+    }
+
+    groups.iter()
+        .filter(|g| !g.prims.is_empty())
+        .map(|g| group_to_mesh(g, &positions, &tex_coords, &normals, &materials, transform))
+        .collect()
+}
+
+fn group_to_mesh(group: &Group, positions: &[Vector3<f32>], tex_coords: &[Vector2<f32>], normals: &[Vector3<f32>],
+    materials: &HashMap<String, Material>, trans_mat: &Matrix4<f32>
+) -> Mesh
+{
+    let mut mesh = Mesh {
+        poses: vec![],
+        norms: vec![],
+        indices: vec![],
+        rgb_info: vec![],
+        norm_info: vec![],
+        tangents: vec![],
+        metal_rough: vec![],
+        transmission: vec![],
+
+        textures: vec![],
+        normal_maps: vec![],
+        metal_rough_maps: vec![],
+
+        trans_mat: *trans_mat,
+    };
+
+    for (mat_name, tris) in &group.prims {
+        let mat = match mat_name {
+            Some(name) => materials.get(name).cloned().unwrap_or_else(|| {
+                println!("obj material {} not found, using default", name);
+                Material::default()
+            }),
+            None => Material::default(),
+        };
+
+        // every distinct position/uv/normal combo becomes its own vertex
+        let mut vert_of: HashMap<Corner, usize> = HashMap::new();
+        let mut corners: Vec<Corner> = vec![];
+        let indices: Vec<[usize; 3]> = tris.iter()
+            .map(|tri| tri.map(|c| *vert_of.entry(c).or_insert_with(|| { corners.push(c); corners.len() - 1 })))
+            .collect();
+
+        let obj_poses: Vec<Vector3<f32>> = corners.iter().map(|c| positions[c.0]).collect();
+        let norms: Vec<Vector3<f32>> = if corners.iter().all(|c| c.2.is_some()) {
+            corners.iter().map(|c| normals[c.2.unwrap()]).collect()
+        } else {
+            // smooth over positions, so uv seams dont split the shading
+            let pos_norms = smooth_normals(positions, &tris.iter().map(|tri| tri.map(|c| c.0)).collect::<Vec<_>>());
+            corners.iter().map(|c| pos_norms[c.0]).collect()
+        };
+        let coords: Option<Vec<Vector2<f32>>> = corners.iter()
+            .map(|c| c.1.map(|t| tex_coords[t]))
+            .collect();
+
+        // maps without uvs to look them up with are useless
+        let (texture, rgb_coords) = match (mat.map_kd, &coords) {
+            (Some(tex), Some(coords)) => (Some(tex), Some(coords.clone())),
+            _ => (None, None),
+        };
+        let (normal_map, norm_info, tangents) = match (mat.map_bump, &coords) {
+            (Some((map, scale)), Some(coords)) => {
+                let tangents = vertex_tangents(&obj_poses, coords, &indices);
+                (Some(map), Some(NormInfo { scale, coords: coords.clone() }), Some(tangents))
+            },
+            _ => (None, None, None),
+        };
+
+        mesh.poses.push(obj_poses.iter().map(|p| trans_mat.transform_point(&(*p).into()).coords).collect());
+        mesh.norms.push(norms);
+        mesh.indices.push(indices);
+        mesh.rgb_info.push(RgbInfo { factor: mat.kd, coords: rgb_coords, vert_colors: None });
+        mesh.norm_info.push(norm_info);
+        mesh.tangents.push(tangents);
+        mesh.metal_rough.push(PbrMetalRoughInfo {
+            // Ks read as reflectance at normal incidence, which the metalness fresnel blends from 0.04 up to 1
+            metal: ((mat.ks.max() - 0.04) / 0.96).clamp(0.0, 1.0),
+            // phong exponent to a roughness, Ns 0 is fully rough
+            rough: (2.0 / (mat.ns.max(0.0) + 2.0)).sqrt(),
+            coords: None,
+        });
+        mesh.transmission.push(TransmissionInfo { amount: (1.0 - mat.d).clamp(0.0, 1.0), ior: mat.ni });
+        mesh.textures.push(texture);
+        mesh.normal_maps.push(normal_map);
+        mesh.metal_rough_maps.push(None);
+    }
+
+    mesh
+}
+
+fn load_mtl(path: &Path, dir: &Path) -> HashMap<String, Material> {
+    cache::opened(path); // even when it's missing, so adding it later rebuilds
+    let mut materials: HashMap<String, Material> = HashMap::new();
+    let Ok(src) = std::fs::read_to_string(path) else {
+        println!("couldn't read mtl {}, using default materials", path.display());
+        return materials;
+    };
+    let mut curr: Option<(String, Material)> = None;
+
+    for (line_no, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else { continue };
+        let rest: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            materials.extend(curr.take());
+            curr = Some((rest.join(" "), Material::default()));
+            continue;
+        }
+        let Some((_, mat)) = curr.as_mut() else { continue };
+        let bad_line = || -> ! { panic!("bad mtl line {} in {}: {}", line_no + 1, path.display(), line) };
+        let scalar = || parse_floats(&rest).first().copied().unwrap_or_else(|| bad_line());
+
+        match keyword {
+            "Kd" => mat.kd = parse_vec3(&rest).unwrap_or_else(|| bad_line()),
+            "Ks" => mat.ks = parse_vec3(&rest).unwrap_or_else(|| bad_line()),
+            "Ns" => mat.ns = scalar(),
+            "Ni" => mat.ni = scalar(),
+            "d" => mat.d = scalar(),
+            "Tr" => mat.d = 1.0 - scalar(),
+            "map_Kd" => {
+                let (file, _) = map_file_and_bump_scale(&rest);
+                mat.map_kd = Some(load_image(&dir.join(file)));
+            },
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                let (file, scale) = map_file_and_bump_scale(&rest);
+                mat.map_bump = Some((load_image(&dir.join(file)), scale));
+            },
+            _ => {},
+        }
+    }
+    materials.extend(curr);
+
+    materials
+}
+
+// map statements can have options before the file name, like "map_Bump -bm 0.5 -s 2 2 normal.png"
+fn map_file_and_bump_scale(tokens: &[&str]) -> (String, f32) {
+    let mut bump_scale = 1.0;
+    let mut i = 0;
+    // always leave at least one token for the file name
+    while i + 1 < tokens.len() && tokens[i].starts_with('-') {
+        let opt = tokens[i];
+        i += 1;
+        if opt == "-bm" {
+            bump_scale = tokens[i].parse().unwrap_or(1.0);
+        }
+        match opt {
+            "-o" | "-s" | "-t" => { // 1 to 3 numbers
+                let nums = tokens[i..tokens.len() - 1].iter().take(3).take_while(|t| t.parse::<f32>().is_ok()).count();
+                i += nums;
+            },
+            "-mm" => i += 2,
+            _ => i += 1,
+        }
+    }
+    (tokens[i.min(tokens.len().saturating_sub(1))..].join(" "), bump_scale)
+}
+
+fn load_image(path: &PathBuf) -> UVRgb32FImage {
+    cache::opened(path);
+    image::open(path)
+        .unwrap_or_else(|e| panic!("couldn't open texture {}: {}", path.display(), e))
+        .to_rgb32f()
+        .into()
+}
+
+fn parse_floats(tokens: &[&str]) -> Vec<f32> {
+    tokens.iter().map_while(|t| t.parse().ok()).collect()
+}
+
+fn parse_vec3(tokens: &[&str]) -> Option<Vector3<f32>> {
+    let f = parse_floats(tokens);
+    (f.len() >= 3).then(|| Vector3::new(f[0], f[1], f[2]))
+}
+
+// "v", "v/vt", "v//vn" or "v/vt/vn", 1 based, negative counts back from the latest
+fn parse_corner(token: &str, num_v: usize, num_vt: usize, num_vn: usize) -> Option<Corner> {
+    let resolve = |s: Option<&str>, count: usize| -> Option<Option<usize>> {
+        match s {
+            None | Some("") => Some(None),
+            Some(s) => {
+                let i: i64 = s.parse().ok()?;
+                let idx = if i < 0 { count as i64 + i } else { i - 1 };
+                (0..count as i64).contains(&idx).then_some(Some(idx as usize))
+            },
+        }
+    };
+    let mut parts = token.split('/');
+    let v = resolve(parts.next(), num_v)??;
+    let vt = resolve(parts.next(), num_vt)?;
+    let vn = resolve(parts.next(), num_vn)?;
+    Some((v, vt, vn))
+}
+
+// per vertex tangents from the uv layout, so normal maps dont need a base colour texture to orient themselves
+fn vertex_tangents(poses: &[Vector3<f32>], coords: &[Vector2<f32>], indices: &[[usize; 3]]) -> Vec<Vector3<f32>> {
+    let mut tangents = vec![Vector3::zeros(); poses.len()];
+    for idxs in indices {
+        let e1 = poses[idxs[1]] - poses[idxs[0]];
+        let e2 = poses[idxs[2]] - poses[idxs[0]];
+        let t1 = coords[idxs[1]] - coords[idxs[0]];
+        let t2 = coords[idxs[2]] - coords[idxs[0]];
+        let det = t1.x * t2.y - t2.x * t1.y;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let tan = (e1 * t2.y - e2 * t1.y) / det;
+        idxs.iter().for_each(|i| tangents[*i] += tan);
+    }
+    tangents.iter().map(|t| t.try_normalize(1e-12).unwrap_or(Vector3::x())).collect()
+}
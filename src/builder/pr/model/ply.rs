@@ -0,0 +1,209 @@
+// stanford ply, ascii or binary, only the vertex and face elements mean anything here
+use nalgebra::{Vector3, Matrix4};
+use crate::elements::mesh::Mesh;
+use super::{plain_mesh, smooth_normals};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLe,
+    BinaryBe,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar { I8, U8, I16, U16, I32, U32, F32, F64 }
+
+enum Property {
+    Scalar { name: String, ty: Scalar },
+    List { name: String, count_ty: Scalar, item_ty: Scalar },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    props: Vec<Property>,
+}
+
+impl Scalar {
+    fn parse(s: &str) -> Option<Scalar> {
+        use Scalar::*;
+        Some(match s {
+            "char" | "int8" => I8,
+            "uchar" | "uint8" => U8,
+            "short" | "int16" => I16,
+            "ushort" | "uint16" => U16,
+            "int" | "int32" => I32,
+            "uint" | "uint32" => U32,
+            "float" | "float32" => F32,
+            "double" | "float64" => F64,
+            _ => return None,
+        })
+    }
+    fn size(&self) -> usize {
+        use Scalar::*;
+        match self {
+            I8 | U8 => 1,
+            I16 | U16 => 2,
+            I32 | U32 | F32 => 4,
+            F64 => 8,
+        }
+    }
+    // integer colour channels go 0 - max, floats are already 0 - 1
+    fn color_scale(&self) -> f64 {
+        use Scalar::*;
+        match self {
+            U8 | I8 => 255.0,
+            U16 | I16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+// walks the body one scalar at a time, whatever the format
+struct Body<'b> {
+    format: Format,
+    bytes: &'b [u8],
+    at: usize,
+    tokens: std::str::SplitAsciiWhitespace<'b>,
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> f64 {
+        if self.format == Format::Ascii {
+            return self.tokens.next().and_then(|t| t.parse().ok()).expect("ply body ended early or has a bad number?");
+        }
+        let size = ty.size();
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(self.bytes.get(self.at..self.at + size).expect("ply body ended early?"));
+        self.at += size;
+        if self.format == Format::BinaryBe {
+            raw[..size].reverse();
+        }
+
+        use Scalar::*;
+        match ty {
+            I8 => raw[0] as i8 as f64,
+            U8 => raw[0] as f64,
+            I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+            U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+            I32 => i32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+            U32 => u32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+            F32 => f32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+            F64 => f64::from_le_bytes(raw),
+        }
+    }
+}
+
+pub fn load(path: &str, transform: &Matrix4<f32>) -> Mesh {
+    let data = std::fs::read(path).unwrap_or_else(|e| panic!("couldn't read ply {}: {}", path, e));
+    let (format, elements, body_start) = parse_header(&data, path);
+
+    let body_text = if format == Format::Ascii {
+        std::str::from_utf8(&data[body_start..]).expect("ascii ply with non utf8 body?")
+    } else {
+        ""
+    };
+    let mut body = Body { format, bytes: &data[body_start..], at: 0, tokens: body_text.split_ascii_whitespace() };
+
+    let mut poses: Vec<Vector3<f32>> = vec![];
+    let mut norms: Vec<Vector3<f32>> = vec![];
+    let mut colors: Vec<Vector3<f32>> = vec![];
+    let mut indices: Vec<[usize; 3]> = vec![];
+
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut pos = Vector3::zeros();
+            let mut norm = Vector3::zeros();
+            let mut color = Vector3::zeros();
+            let (mut has_norm, mut has_color) = (false, false);
+
+            for prop in &element.props {
+                match prop {
+                    Property::Scalar { name, ty } => {
+                        let val = body.read(*ty);
+                        if element.name != "vertex" {
+                            continue;
+                        }
+                        match name.as_str() {
+                            "x" => pos.x = val as f32,
+                            "y" => pos.y = val as f32,
+                            "z" => pos.z = val as f32,
+                            "nx" => { norm.x = val as f32; has_norm = true; },
+                            "ny" => norm.y = val as f32,
+                            "nz" => norm.z = val as f32,
+                            "red" | "r" => { color.x = (val / ty.color_scale()) as f32; has_color = true; },
+                            "green" | "g" => color.y = (val / ty.color_scale()) as f32,
+                            "blue" | "b" => color.z = (val / ty.color_scale()) as f32,
+                            _ => {},
+                        }
+                    },
+                    Property::List { name, count_ty, item_ty } => {
+                        let count = body.read(*count_ty) as usize;
+                        let items: Vec<usize> = (0..count).map(|_| body.read(*item_ty) as usize).collect();
+                        if element.name == "face" && (name == "vertex_indices" || name == "vertex_index") && count >= 3 {
+                            indices.extend((1..count - 1).map(|i| [items[0], items[i], items[i + 1]]));
+                        }
+                    },
+                }
+            }
+
+            if element.name == "vertex" {
+                poses.push(pos);
+                if has_norm { norms.push(norm); }
+                if has_color { colors.push(color); }
+            }
+        }
+    }
+
+    if let Some(bad) = indices.iter().flatten().find(|i| **i >= poses.len()) {
+        panic!("ply {} has a face pointing at vertex {} but only {} vertices", path, bad, poses.len());
+    }
+    let norms = if norms.len() == poses.len() { norms } else { smooth_normals(&poses, &indices) };
+    let colors = (colors.len() == poses.len() && !colors.is_empty()).then_some(colors);
+
+    plain_mesh(&poses, norms, indices, colors, transform)
+}
+
+fn parse_header(data: &[u8], path: &str) -> (Format, Vec<Element>, usize) {
+    const END: &[u8] = b"end_header";
+    let end = data.windows(END.len()).position(|w| w == END).unwrap_or_else(|| panic!("ply {} has no end_header?", path));
+    let body_start = end + END.len() + data[end + END.len()..].iter().position(|b| *b == b'\n').map_or(0, |p| p + 1);
+    let header = std::str::from_utf8(&data[..end]).expect("ply header isn't text?");
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        panic!("{} isn't a ply file?", path);
+    }
+
+    let mut format: Option<Format> = None;
+    let mut elements: Vec<Element> = vec![];
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let bad_line = || -> ! { panic!("bad ply header line in {}: {}", path, line) };
+        match tokens.as_slice() {
+            ["format", f, ..] => format = Some(match *f {
+                "ascii" => Format::Ascii,
+                "binary_little_endian" => Format::BinaryLe,
+                "binary_big_endian" => Format::BinaryBe,
+                _ => bad_line(),
+            }),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().unwrap_or_else(|_| bad_line()),
+                props: vec![],
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements.last_mut().unwrap_or_else(|| bad_line()).props.push(Property::List {
+                name: name.to_string(),
+                count_ty: Scalar::parse(count_ty).unwrap_or_else(|| bad_line()),
+                item_ty: Scalar::parse(item_ty).unwrap_or_else(|| bad_line()),
+            }),
+            ["property", ty, name] => elements.last_mut().unwrap_or_else(|| bad_line()).props.push(Property::Scalar {
+                name: name.to_string(),
+                ty: Scalar::parse(ty).unwrap_or_else(|| bad_line()),
+            }),
+            _ => {}, // comments and obj_info
+        }
+    }
+
+    (format.unwrap_or_else(|| panic!("ply {} doesn't say its format?", path)), elements, body_start)
+}
@@ -0,0 +1,78 @@
+// stereolithography, binary or ascii. triangles dont share vertices in stl so everything gets flat shaded
+use nalgebra::{Vector3, Matrix4};
+use crate::elements::mesh::Mesh;
+use super::plain_mesh;
+
+pub fn load(path: &str, transform: &Matrix4<f32>) -> Mesh {
+    let data = std::fs::read(path).unwrap_or_else(|e| panic!("couldn't read stl {}: {}", path, e));
+
+    let facets = if is_binary(&data) { read_binary(&data) } else { read_ascii(&data, path) };
+
+    let mut poses: Vec<Vector3<f32>> = Vec::with_capacity(facets.len() * 3);
+    let mut norms: Vec<Vector3<f32>> = Vec::with_capacity(facets.len() * 3);
+    let mut indices: Vec<[usize; 3]> = Vec::with_capacity(facets.len());
+    for (norm, verts) in facets {
+        // plenty of exporters leave the facet normal as zeros
+        let winding_norm = (verts[1] - verts[0]).cross(&(verts[2] - verts[0]));
+        let norm = norm.try_normalize(1e-12)
+            .or(winding_norm.try_normalize(1e-12))
+            .unwrap_or(Vector3::y());
+
+        let first = poses.len();
+        poses.extend(verts);
+        norms.extend([norm; 3]);
+        indices.push([first, first + 1, first + 2]);
+    }
+
+    plain_mesh(&poses, norms, indices, None, transform)
+}
+
+type Facet = (Vector3<f32>, [Vector3<f32>; 3]);
+
+// binary files can start with "solid" too, so go by whether the size matches the triangle count
+fn is_binary(data: &[u8]) -> bool {
+    data.len() >= 84 && {
+        let count = u32::from_le_bytes(data[80..84].try_into().unwrap()) as usize;
+        data.len() == 84 + count * 50
+    }
+}
+
+fn read_binary(data: &[u8]) -> Vec<Facet> {
+    let vec3 = |b: &[u8]| -> Vector3<f32> {
+        let f = |i: usize| f32::from_le_bytes(b[i * 4..i * 4 + 4].try_into().unwrap());
+        Vector3::new(f(0), f(1), f(2))
+    };
+
+    data[84..].chunks_exact(50)
+        .map(|rec| (vec3(&rec[0..12]), [vec3(&rec[12..24]), vec3(&rec[24..36]), vec3(&rec[36..48])])) // last 2 bytes are attributes nobody uses
+        .collect()
+}
+
+fn read_ascii(data: &[u8], path: &str) -> Vec<Facet> {
+    let src = std::str::from_utf8(data).unwrap_or_else(|_| panic!("stl {} is neither binary nor text?", path));
+    let mut facets: Vec<Facet> = vec![];
+    let mut norm = Vector3::zeros();
+    let mut verts: Vec<Vector3<f32>> = vec![];
+
+    for line in src.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let vec3_at = |start: usize| -> Vector3<f32> {
+            let f = |i: usize| tokens.get(start + i).and_then(|t| t.parse().ok()).unwrap_or_else(|| panic!("bad stl line in {}: {}", path, line));
+            Vector3::new(f(0), f(1), f(2))
+        };
+        match tokens.first().copied() {
+            Some("facet") => {
+                norm = vec3_at(2); // facet normal nx ny nz
+                verts.clear();
+            },
+            Some("vertex") => verts.push(vec3_at(1)),
+            Some("endfacet") => {
+                // fan out in case something wrote more than 3 vertices in a loop
+                facets.extend((1..verts.len().saturating_sub(1)).map(|i| (norm, [verts[0], verts[i], verts[i + 1]])));
+            },
+            _ => {},
+        }
+    }
+
+    facets
+}
@@ -0,0 +1,62 @@
+use nalgebra::Vector3;
+use serde::Deserialize;
+use crate::accel::{Aabb, PlaneBounds};
+use crate::elements::sdf::{self, SdfNode};
+use crate::elements::sphere::Coloring;
+use crate::material::UniformDiffuseSpec;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Sdf {
+    pub tree: SdfNode,
+    pub bounds: [Vector3<f32>; 2], // min and max corners, everything outside is never looked at
+    pub coloring: Coloring,
+    pub mat: UniformDiffuseSpec,
+    pub max_steps: Option<u32>, // default 256
+    pub step_scale: Option<f32>, // default 1, lower it if twists or fractals show holes
+    pub translation: Option<Vector3<f32>>, // moves the tree and its bounds
+    pub tracks: Option<Vec<Track>>, // translation
+}
+
+impl Animatable for Sdf {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "translation" => self.translation = Some(value.vector(property)),
+            _ => unknown_property("Sdf", property),
+        }
+    }
+}
+
+impl From<Sdf> for sdf::Sdf {
+    fn from(s: Sdf) -> Self {
+        check_children(&s.tree);
+        let offset = s.translation.unwrap_or_else(Vector3::zeros);
+        let [low, high] = s.bounds.map(|b| b + offset);
+        sdf::Sdf {
+            tree: match s.translation {
+                Some(offset) => SdfNode::Translate { offset, child: Box::new(s.tree) },
+                None => s.tree,
+            },
+            bounds: Aabb { bounds: [0, 1, 2].map(|a| PlaneBounds { low: low[a], high: high[a] }) },
+            coloring: s.coloring,
+            mat: s.mat,
+            max_steps: s.max_steps.unwrap_or(256),
+            step_scale: s.step_scale.unwrap_or(1.0),
+        }
+    }
+}
+
+fn check_children(node: &SdfNode) {
+    use SdfNode::*;
+    match node {
+        Union(children) | Intersection(children) | Difference(children) | SmoothUnion { children, .. } => {
+            if children.is_empty() {
+                panic!("sdf {:?} needs at least one child", node);
+            }
+            children.iter().for_each(check_children);
+        },
+        Round { child, .. } | Twist { child, .. } | Repeat { child, .. } | Translate { child, .. } => check_children(child),
+        Sphere { .. } | Box { .. } | Torus { .. } | Mandelbulb { .. } => {},
+    }
+}
@@ -0,0 +1,67 @@
+use nalgebra::Vector3;
+use serde::Deserialize;
+use crate::elements::sky;
+use crate::builder::anim::{Animatable, AnimValue, Track, unknown_property};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Sky {
+    pub sun_dir: Option<Vector3<f32>>, // direction towards the sun, takes priority over time
+    pub time: Option<SkyTime>,
+    pub turbidity: Option<f32>, // haziness, 2 is a very clear day and 10 is thick haze. 3 by default
+    pub ground_albedo: Option<Vector3<f32>>, // colour of the ground below the horizon
+    pub intensity: Option<f32>, // scales the sky and sun together
+    pub sun_intensity: Option<f32>, // scales just the sun
+    pub sun: Option<bool>, // false leaves out the sun disk and its light, true by default
+    pub tracks: Option<Vec<Track>>, // sun_dir, hour, day_of_year, turbidity, ground_albedo, intensity and sun_intensity
+}
+
+// north is -z and east is +x
+#[derive(Deserialize, Debug, Clone)]
+pub struct SkyTime {
+    pub day_of_year: f32, // 1 is the 1st of january
+    pub hour: f32, // local solar time, 12 is noon
+    pub latitude: f32, // degrees, negative for the southern hemisphere
+}
+
+impl Sky {
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        match (&self.sun_dir, &self.time) {
+            (Some(d), _) => d.normalize(),
+            (None, Some(t)) => sky::sun_direction(t.day_of_year, t.hour, t.latitude),
+            (None, None) => panic!("sky needs either sun_dir or time"),
+        }
+    }
+
+    fn time_mut(&mut self, property: &str) -> &mut SkyTime {
+        self.time.as_mut().unwrap_or_else(|| panic!("Sky needs a time to animate {}", property))
+    }
+}
+
+impl Animatable for Sky {
+    fn tracks(&self) -> &[Track] { self.tracks.as_deref().unwrap_or(&[]) }
+    fn set_property(&mut self, property: &str, value: AnimValue) {
+        match property {
+            "sun_dir" => self.sun_dir = Some(value.vector(property)),
+            "hour" => self.time_mut(property).hour = value.scalar(property),
+            "day_of_year" => self.time_mut(property).day_of_year = value.scalar(property),
+            "turbidity" => self.turbidity = Some(value.scalar(property)),
+            "ground_albedo" => self.ground_albedo = Some(value.vector(property)),
+            "intensity" => self.intensity = Some(value.scalar(property)),
+            "sun_intensity" => self.sun_intensity = Some(value.scalar(property)),
+            _ => unknown_property("Sky", property),
+        }
+    }
+}
+
+impl From<Sky> for sky::Sky {
+    fn from(s: Sky) -> Self {
+        let sun_intensity = if s.sun.unwrap_or(true) { s.sun_intensity.unwrap_or(1.0) } else { 0.0 };
+        sky::Sky::new(
+            s.sun_direction(),
+            s.turbidity.unwrap_or(3.0).clamp(1.7, 10.0), // range the fit was made for
+            s.ground_albedo.unwrap_or(Vector3::repeat(0.3)),
+            s.intensity.unwrap_or(1.0),
+            sun_intensity,
+        )
+    }
+}
@@ -0,0 +1,294 @@
+// constructive solid geometry, boolean combinations of closed shapes worked out from where the ray is inside each of them
+use nalgebra::{Vector3, Matrix3};
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::material::*;
+use crate::elements::IsCompleteElement;
+use crate::elements::sphere::Coloring;
+use crate::accel::{Aabb, PlaneBounds};
+
+pub enum Shape {
+    Sphere { c: Vector3<f32>, r: f32 },
+    Box { c: Vector3<f32>, half_size: Vector3<f32>, rot: Matrix3<f32> }, // rot takes box space to world
+    Cylinder { a: Vector3<f32>, axis: Vector3<f32>, len: f32, r: f32 }, // capped, axis is unit from a
+}
+
+// every leaf keeps its own material, surfaces cut by a difference show the material of what did the cutting
+pub struct Leaf {
+    pub shape: Shape,
+    pub coloring: Coloring,
+    pub mat: UniformDiffuseSpec,
+}
+
+pub enum Tree {
+    Leaf(usize), // index into leaves
+    Union(Vec<Tree>),
+    Intersection(Vec<Tree>),
+    Difference(Vec<Tree>), // first minus all the rest
+}
+
+pub struct Csg {
+    pub leaves: Vec<Leaf>,
+    pub tree: Tree,
+}
+
+// where the ray crosses a surface, norm points out of whatever solid the span belongs to
+#[derive(Clone, Copy)]
+struct Boundary {
+    t: f32,
+    norm: Vector3<f32>,
+    leaf: usize,
+}
+
+type Span = [Boundary; 2]; // entry, exit
+
+const NO_LEAF: usize = usize::MAX; // ends of open spans from complements, never actually hit
+
+impl Boundary {
+    fn at_infinity(t: f32) -> Self {
+        Boundary { t, norm: Vector3::zeros(), leaf: NO_LEAF }
+    }
+    fn flipped(self) -> Self {
+        Boundary { norm: -self.norm, ..self }
+    }
+}
+
+struct BounceInfo {
+    seeding: SeedingRay,
+    leaf: usize,
+    surface_pos: Vector3<f32>, // exactly on the surface, before the outward offset
+}
+
+impl Shape {
+    fn span(&self, ray: &Ray, leaf: usize) -> Option<Span> {
+        match self {
+            Shape::Sphere { c, r } => {
+                let oc = ray.o - c;
+                let b = ray.d.dot(&oc);
+                let disc = b * b - (oc.dot(&oc) - r * r);
+                if disc <= 0.0 {
+                    return None;
+                }
+                let s = disc.sqrt();
+                let at = |t: f32| Boundary { t, norm: (ray.o + ray.d * t - c) / *r, leaf };
+                Some([at(-b - s), at(-b + s)])
+            },
+            Shape::Box { c, half_size, rot } => {
+                let o = rot.transpose() * (ray.o - c);
+                let d = rot.transpose() * ray.d;
+                let mut span = [Boundary::at_infinity(f32::NEG_INFINITY), Boundary::at_infinity(f32::INFINITY)];
+                for a in 0..3 {
+                    let axis: Vector3<f32> = rot.column(a).into();
+                    span = clip_slab(span, o[a], d[a], -half_size[a], half_size[a], &axis, leaf)?;
+                }
+                Some(span)
+            },
+            Shape::Cylinder { a, axis, len, r } => {
+                let oc = ray.o - a;
+                let (d_along, o_along) = (ray.d.dot(axis), oc.dot(axis));
+                let d_perp = ray.d - axis * d_along;
+                let o_perp = oc - axis * o_along;
+
+                // infinite tube first, then the two caps as a slab along the axis
+                let qa = d_perp.norm_squared();
+                let qb = d_perp.dot(&o_perp);
+                let qc = o_perp.norm_squared() - r * r;
+                let mut span = if qa < 1e-12 {
+                    if qc > 0.0 {
+                        return None;
+                    }
+                    [Boundary::at_infinity(f32::NEG_INFINITY), Boundary::at_infinity(f32::INFINITY)]
+                } else {
+                    let disc = qb * qb - qa * qc;
+                    if disc <= 0.0 {
+                        return None;
+                    }
+                    let s = disc.sqrt();
+                    let at = |t: f32| Boundary { t, norm: (o_perp + d_perp * t) / *r, leaf };
+                    [at((-qb - s) / qa), at((-qb + s) / qa)]
+                };
+                span = clip_slab(span, o_along, d_along, 0.0, *len, axis, leaf)?;
+                Some(span)
+            },
+        }
+    }
+
+    fn aabb(&self) -> Aabb {
+        match self {
+            Shape::Sphere { c, r } => Aabb {
+                bounds: [0, 1, 2].map(|a| PlaneBounds { low: c[a] - r, high: c[a] + r }),
+            },
+            Shape::Box { c, half_size, rot } => {
+                let local = Aabb { bounds: [0, 1, 2].map(|a| PlaneBounds { low: -half_size[a], high: half_size[a] }) };
+                Aabb::from_points(&local.corners().map(|p| rot * p + c))
+            },
+            Shape::Cylinder { a, axis, len, r } => {
+                let b = a + axis * *len;
+                // a cap disk reaches r * sin(angle between axis and that world axis)
+                Aabb {
+                    bounds: [0, 1, 2].map(|i| {
+                        let reach = r * (1.0 - axis[i] * axis[i]).max(0.0).sqrt();
+                        PlaneBounds { low: a[i].min(b[i]) - reach, high: a[i].max(b[i]) + reach }
+                    }),
+                }
+            },
+        }
+    }
+}
+
+// narrows a convex span to the slab low <= o + d t <= high along axis
+fn clip_slab(span: Span, o: f32, d: f32, low: f32, high: f32, axis: &Vector3<f32>, leaf: usize) -> Option<Span> {
+    let [mut entry, mut exit] = span;
+    if d.abs() < 1e-12 {
+        return (low..=high).contains(&o).then_some(span);
+    }
+    let (t_low, t_high) = ((low - o) / d, (high - o) / d);
+    let (near, far) = if d > 0.0 {
+        (Boundary { t: t_low, norm: -axis, leaf }, Boundary { t: t_high, norm: *axis, leaf })
+    } else {
+        (Boundary { t: t_high, norm: *axis, leaf }, Boundary { t: t_low, norm: -axis, leaf })
+    };
+    if near.t > entry.t {
+        entry = near;
+    }
+    if far.t < exit.t {
+        exit = far;
+    }
+    (entry.t < exit.t).then_some([entry, exit])
+}
+
+// the span lists below are always sorted and disjoint
+fn union(a: Vec<Span>, b: Vec<Span>) -> Vec<Span> {
+    let mut all: Vec<Span> = a.into_iter().chain(b).collect();
+    all.sort_by(|x, y| x[0].t.total_cmp(&y[0].t));
+
+    let mut merged: Vec<Span> = Vec::with_capacity(all.len());
+    for span in all {
+        match merged.last_mut() {
+            Some(last) if span[0].t <= last[1].t => {
+                if span[1].t > last[1].t {
+                    last[1] = span[1];
+                }
+            },
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+fn intersection(a: Vec<Span>, b: Vec<Span>) -> Vec<Span> {
+    let mut out: Vec<Span> = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let entry = if a[i][0].t > b[j][0].t { a[i][0] } else { b[j][0] };
+        let exit = if a[i][1].t < b[j][1].t { a[i][1] } else { b[j][1] };
+        if entry.t < exit.t {
+            out.push([entry, exit]);
+        }
+        if a[i][1].t < b[j][1].t { i += 1 } else { j += 1 }
+    }
+    out
+}
+
+// everywhere outside the spans, the surfaces face the other way
+fn complement(spans: Vec<Span>) -> Vec<Span> {
+    let mut out: Vec<Span> = Vec::with_capacity(spans.len() + 1);
+    let mut prev = Boundary::at_infinity(f32::NEG_INFINITY);
+    for [entry, exit] in spans {
+        if prev.t < entry.t {
+            out.push([prev, entry.flipped()]);
+        }
+        prev = exit.flipped();
+    }
+    out.push([prev, Boundary::at_infinity(f32::INFINITY)]);
+    out
+}
+
+impl Tree {
+    fn spans(&self, leaves: &[Leaf], ray: &Ray) -> Vec<Span> {
+        match self {
+            Tree::Leaf(i) => leaves[*i].shape.span(ray, *i).into_iter().collect(),
+            Tree::Union(children) => children.iter()
+                .map(|c| c.spans(leaves, ray))
+                .reduce(union)
+                .unwrap_or_default(),
+            Tree::Intersection(children) => {
+                let mut spans = children[0].spans(leaves, ray);
+                for c in &children[1..] {
+                    if spans.is_empty() {
+                        break;
+                    }
+                    spans = intersection(spans, c.spans(leaves, ray));
+                }
+                spans
+            },
+            Tree::Difference(children) => {
+                let mut spans = children[0].spans(leaves, ray);
+                for c in &children[1..] {
+                    if spans.is_empty() {
+                        break;
+                    }
+                    spans = intersection(spans, complement(c.spans(leaves, ray)));
+                }
+                spans
+            },
+        }
+    }
+
+    fn aabb(&self, leaves: &[Leaf]) -> Aabb {
+        match self {
+            Tree::Leaf(i) => leaves[*i].shape.aabb(),
+            Tree::Union(children) => children.iter().fold(Aabb::empty(), |acc, c| acc.union(&c.aabb(leaves))),
+            Tree::Intersection(children) => children[1..].iter().fold(children[0].aabb(leaves), |acc, c| acc.intersection(&c.aabb(leaves))),
+            Tree::Difference(children) => children[0].aabb(leaves), // cutting only ever shrinks the first
+        }
+    }
+}
+
+impl IsCompleteElement for Csg {}
+
+impl Hitable for Csg {
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        let hit = self.tree.spans(&self.leaves, ray).into_iter()
+            .flatten()
+            .find(|b| b.t > 0.0)?;
+        let pos = ray.o + ray.d * hit.t;
+        Some(HitResult { l: hit.t.into(), intermed: Some(Box::new((pos, hit.norm, hit.leaf))) })
+    }
+    fn give_aabb(&self) -> Option<Aabb> {
+        Some(self.tree.aabb(&self.leaves))
+    }
+}
+
+impl HasHitInfo for Csg {
+    fn hit_info(&self, info: &HitResult, _ray: &Ray) -> HitInfo {
+        let (surface_pos, norm, leaf) = *info.intermed.as_ref().unwrap().downcast_ref::<(Vector3<f32>, Vector3<f32>, usize)>().unwrap();
+        let mat = &self.leaves[leaf].mat;
+        let continue_info = BounceInfo { seeding: mat.generate_seed(), leaf, surface_pos };
+
+        HitInfo {
+            emissive: mat.emissive.unwrap_or(Vector3::zeros()),
+            pos: surface_pos + norm * crate::EPS,
+            norm,
+            dls: mat.should_dls(&continue_info.seeding),
+            continue_info: Some(Box::new(continue_info)),
+        }
+    }
+}
+
+impl InteractsWithRay for Csg {
+    fn continue_ray(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> {
+        let bounce = hit_info.continue_info.as_ref().unwrap().downcast_ref::<BounceInfo>().unwrap();
+        let leaf = &self.leaves[bounce.leaf];
+        let Coloring::Solid(rgb) = leaf.coloring;
+        let (mut new_ray, p) = leaf.mat.gen_new_ray(ray, &hit_info.norm, &hit_info.pos, &bounce.seeding);
+        // refracted rays start just inside instead, or they'd hit the surface they just went through
+        if new_ray.d.dot(&hit_info.norm) < 0.0 {
+            new_ray.o = bounce.surface_pos - hit_info.norm * crate::EPS;
+        }
+
+        Some((rgb * p, new_ray))
+    }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> {
+        None // emissive leaves still glow when hit, they just aren't sampled directly
+    }
+}
@@ -0,0 +1,182 @@
+// cubic bezier curves with a width running from root to tip, for hair, fur, wires and the like
+// each one is its own element so the kd-tree can cull them like anything else
+use std::sync::Arc;
+use nalgebra::Vector3;
+use serde::Deserialize;
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::material::*;
+use crate::elements::IsCompleteElement;
+use crate::accel::Aabb;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum CurveShape {
+    #[default]
+    Cylinder, // round tube, shaded like one
+    Ribbon, // flat strip that always faces the ray, cheap and fine for fur seen from afar
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum CurveMat {
+    Hair(HairSpec),
+    Surface { color: Vector3<f32>, mat: UniformDiffuseSpec }, // cables and anything else that's just a thin solid
+}
+
+const SEGMENTS: usize = 8; // straight pieces the bezier gets flattened into for intersecting
+
+pub struct Curve {
+    points: [Vector3<f32>; SEGMENTS + 1],
+    radii: [f32; SEGMENTS + 1],
+    bounds: Aabb,
+    shape: CurveShape,
+    mat: Arc<CurveMat>, // usually thousands of strands share one
+}
+
+struct CurveHit {
+    pos: Vector3<f32>,
+    norm: Vector3<f32>,
+    tangent: Vector3<f32>,
+    r: f32,
+}
+
+struct BounceInfo {
+    seeding: SeedingRay,
+    tangent: Vector3<f32>,
+    surface_pos: Vector3<f32>,
+    r: f32,
+}
+
+impl Curve {
+    // widths are full widths at the root and tip, linear in between
+    pub fn new(cps: [Vector3<f32>; 4], widths: [f32; 2], shape: CurveShape, mat: Arc<CurveMat>) -> Self {
+        let points = core::array::from_fn(|i| bezier(&cps, i as f32 / SEGMENTS as f32));
+        let radii = core::array::from_fn(|i| 0.5 * (widths[0] + (widths[1] - widths[0]) * i as f32 / SEGMENTS as f32));
+
+        // the curve never leaves the hull of its control points
+        let r_max = radii.iter().copied().fold(0.0, f32::max);
+        let mut bounds = Aabb::from_points(&cps);
+        bounds.bounds.iter_mut().for_each(|b| { b.low -= r_max; b.high += r_max; });
+
+        Curve { points, radii, bounds, shape, mat }
+    }
+
+    fn hit_segment(&self, i: usize, ray: &Ray) -> Option<(f32, CurveHit)> {
+        let (a, b) = (self.points[i], self.points[i + 1]);
+        let len = (b - a).norm();
+        if len < 1e-9 {
+            return None;
+        }
+        let axis = (b - a) / len;
+        let (r0, r1) = (self.radii[i], self.radii[i + 1]);
+        let oa = ray.o - a;
+        let (d_along, o_along) = (ray.d.dot(&axis), oa.dot(&axis));
+        let d_perp = ray.d - axis * d_along;
+        let o_perp = oa - axis * o_along;
+        let qa = d_perp.norm_squared();
+        if qa < 1e-12 {
+            return None; // looking straight down the segment, the neighbours will catch it
+        }
+
+        // closest approach to the axis, which decides how wide the curve is here
+        let t_close = -d_perp.dot(&o_perp) / qa;
+        let s_close = ((o_along + d_along * t_close) / len).clamp(0.0, 1.0);
+        let r = r0 + (r1 - r0) * s_close;
+
+        match self.shape {
+            CurveShape::Cylinder => {
+                let qb = d_perp.dot(&o_perp);
+                let disc = qb * qb - qa * (o_perp.norm_squared() - r * r);
+                if disc < 0.0 {
+                    return None;
+                }
+                let s = disc.sqrt();
+                let t = [(-qb - s) / qa, (-qb + s) / qa].into_iter().find(|t| *t > 0.0)?;
+                let along = o_along + d_along * t;
+                if !(0.0..=len).contains(&along) {
+                    return None;
+                }
+                let pos = ray.o + ray.d * t;
+                let norm = (o_perp + d_perp * t).normalize();
+                Some((t, CurveHit { pos, norm, tangent: axis, r }))
+            },
+            CurveShape::Ribbon => {
+                if t_close <= 0.0 {
+                    return None;
+                }
+                let along = o_along + d_along * t_close;
+                let dist = (o_perp + d_perp * t_close).norm();
+                if !(0.0..=len).contains(&along) || dist > r {
+                    return None;
+                }
+                let pos = ray.o + ray.d * t_close;
+                let norm = (-ray.d + axis * d_along).try_normalize(1e-9)?;
+                Some((t_close, CurveHit { pos, norm, tangent: axis, r }))
+            },
+        }
+    }
+}
+
+fn bezier(cps: &[Vector3<f32>; 4], t: f32) -> Vector3<f32> {
+    let s = 1.0 - t;
+    cps[0] * (s * s * s) + cps[1] * (3.0 * s * s * t) + cps[2] * (3.0 * s * t * t) + cps[3] * (t * t * t)
+}
+
+impl IsCompleteElement for Curve {}
+
+impl Hitable for Curve {
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        self.bounds.get_entry_exit(ray)?;
+        let (t, hit) = (0..SEGMENTS)
+            .filter_map(|i| self.hit_segment(i, ray))
+            .reduce(|a, b| if a.0 < b.0 { a } else { b })?;
+        Some(HitResult { l: t.into(), intermed: Some(Box::new(hit)) })
+    }
+    fn give_aabb(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl HasHitInfo for Curve {
+    fn hit_info(&self, info: &HitResult, _ray: &Ray) -> HitInfo {
+        let hit = info.intermed.as_ref().unwrap().downcast_ref::<CurveHit>().unwrap();
+        let (emissive, seeding, dls) = match self.mat.as_ref() {
+            CurveMat::Hair(_) => (Vector3::zeros(), SeedingRay::NoSeed, false), // far from lambertian, no direct light sampling
+            CurveMat::Surface { mat, .. } => {
+                let seeding = mat.generate_seed();
+                let dls = mat.should_dls(&seeding);
+                (mat.emissive.unwrap_or(Vector3::zeros()), seeding, dls)
+            },
+        };
+
+        HitInfo {
+            emissive,
+            pos: hit.pos + hit.norm * crate::EPS,
+            norm: hit.norm,
+            dls,
+            continue_info: Some(Box::new(BounceInfo { seeding, tangent: hit.tangent, surface_pos: hit.pos, r: hit.r })),
+        }
+    }
+}
+
+impl InteractsWithRay for Curve {
+    fn continue_ray(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> {
+        let bounce = hit_info.continue_info.as_ref().unwrap().downcast_ref::<BounceInfo>().unwrap();
+        match self.mat.as_ref() {
+            CurveMat::Hair(hair) => {
+                let (d, throughput, lobe) = hair.sample(&ray.d, &bounce.tangent, &hit_info.norm);
+                let o = match lobe {
+                    // went through the fibre, so come out the far side of it
+                    HairLobe::Tt => bounce.surface_pos + d * (2.0 * bounce.r + crate::EPS),
+                    HairLobe::R | HairLobe::Trt => hit_info.pos,
+                };
+                Some((throughput, Ray { d, o }))
+            },
+            CurveMat::Surface { color, mat } => {
+                let (ray, p) = mat.gen_new_ray(ray, &hit_info.norm, &hit_info.pos, &bounce.seeding);
+                Some((color * p, ray))
+            },
+        }
+    }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> {
+        None
+    }
+}
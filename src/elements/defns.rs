@@ -1,4 +1,5 @@
 use crate::ray::{Hitable, HasHitInfo, InteractsWithRay};
+use super::light::DeltaLight;
 
 pub trait IsCompleteElement : Hitable + HasHitInfo + InteractsWithRay {}
 
@@ -9,6 +10,10 @@ pub type Element<'a> = Box<dyn IsCompleteElement + Send + Sync + 'a>;
 
 pub type Group = Box<dyn Decomposable + Send + Sync>; // what meshes do as part of the render list
 
+pub type Light = Box<dyn DeltaLight + Send + Sync>; // lights with no geometry, only used for direct light sampling
+
+pub type Illuminator<'l> = &'l (dyn DeltaLight + Send + Sync);
+
 pub trait Decomposable {
     fn decompose_to_elems<'e, 's>(&'s self, mesh_index: u32) -> Box<dyn Iterator<Item = Element<'e>> + 's> 
     where
@@ -6,6 +6,9 @@ use crate::accel::Aabb;
 
 pub type FaceImagewUVScale = (UVRgb32FImage, f32, f32);
 
+// which axis each face looks down and from which side, in the order the gpu stores them
+const FACE_AXES: [(usize, f32); 6] = [(2, -1.0), (2, 1.0), (0, -1.0), (0, 1.0), (1, -1.0), (1, 1.0)];
+
 // for environment mapping, all rays can hit this
 pub struct DistantCubeMap {
     pub neg_z: FaceImagewUVScale,
@@ -14,43 +17,80 @@ pub struct DistantCubeMap {
     pub pos_x: FaceImagewUVScale,
     pub neg_y: FaceImagewUVScale,
     pub pos_y: FaceImagewUVScale,
+    pub rotation: f32, // radians around +y, right handed like Environment
 }
 
-impl IsCompleteElement for DistantCubeMap {}
-
-impl InteractsWithRay for DistantCubeMap {
-    fn continue_ray(&self, _ray: &Ray, _hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> { None } // cant shoot new ray silly
-    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> { None } // maybe ill do this? for a skybox it seems almost unnecessary since all rays can hit
+// components of the direction that run along a face's u and v
+fn face_uv_axes(axis: usize) -> (usize, usize) {
+    match axis {
+        0 => (2, 1),
+        1 => (0, 2),
+        _ => (0, 1),
+    }
 }
 
-impl HasHitInfo for DistantCubeMap {
-    fn hit_info(&self, _info: &HitResult, ray: &Ray) -> HitInfo {
-        let comps: &[f32] = (&ray.d).into();
-        let (max_idx, max_c) = comps.iter().enumerate()
-            .reduce(|(prev_i, prev_c), (i, c)| if c.abs() > prev_c.abs() {(i, c)} else {(prev_i, prev_c)})
-            .unwrap();
-        
-        let d = ray.d.normalize();
-        use std::cmp::Ordering;
-        let (u, v, fact, face) = 
-            match (max_idx, max_c.partial_cmp(&0.0).expect(&format!("wtf {}", max_c))) {
-                (0, Ordering::Less) => (d.z, d.y, d.x, &self.neg_x),
+impl DistantCubeMap {
+    fn faces(&self) -> [&FaceImagewUVScale; 6] {
+        [&self.neg_z, &self.pos_z, &self.neg_x, &self.pos_x, &self.neg_y, &self.pos_y]
+    }
 
-                (0, Ordering::Greater) => (d.z, d.y, d.x, &self.pos_x),
+    // face and uv a direction (in the map's own unrotated frame) lands on, the uv scales flip faces into place
+    fn locate(&self, d: &Vector3<f32>) -> (usize, f32, f32) {
+        let axis = d.iamax();
+        let face = [2, 4, 0][axis] + (d[axis] > 0.0) as usize;
+        let (_, us, vs) = *self.faces()[face];
+        let (ua, va) = face_uv_axes(axis);
+        (face, 0.5 + 0.5 * d[ua] * us / d[axis], 0.5 + 0.5 * d[va] * vs / d[axis])
+    }
 
-                (1, Ordering::Less) => (d.x, d.z, d.y, &self.neg_y),
+    // inverse of locate, uv can run off the face to reach into its neighbours
+    fn direction(&self, face: usize, u: f32, v: f32) -> Vector3<f32> {
+        let (axis, sign) = FACE_AXES[face];
+        let (_, us, vs) = *self.faces()[face];
+        let (ua, va) = face_uv_axes(axis);
+        let mut d = Vector3::zeros();
+        d[axis] = sign;
+        d[ua] = (2.0 * u - 1.0) * sign / us;
+        d[va] = (2.0 * v - 1.0) * sign / vs;
+        d
+    }
 
-                (1, Ordering::Greater) => (d.x, d.z, d.y, &self.pos_y),
+    // bilinear, texels past a face's edge are fetched from whichever face they fold onto so there are no seams
+    pub fn sample(&self, d: &Vector3<f32>) -> Vector3<f32> {
+        let (s, c) = self.rotation.sin_cos();
+        let d = Vector3::new(c * d.x - s * d.z, d.y, s * d.x + c * d.z);
+        let (face, u, v) = self.locate(&d);
+        let image = &self.faces()[face].0;
+        let (w, h) = (image.get_width() as f32, image.get_height() as f32);
+        let (x, y) = (u * w - 0.5, v * h - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
 
-                (2, Ordering::Less) => (d.x, d.y, d.z, &self.neg_z),
+        let texel = |tx: f32, ty: f32| {
+            if tx >= 0.0 && ty >= 0.0 && tx < w && ty < h {
+                image.get_texel(tx as u32, ty as u32)
+            } else {
+                let (f, u, v) = self.locate(&self.direction(face, (tx + 0.5) / w, (ty + 0.5) / h));
+                self.faces()[f].0.get_pixel(u, v)
+            }
+        };
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
 
-                (2, Ordering::Greater) => (d.x, d.y, d.z, &self.pos_z),
+impl IsCompleteElement for DistantCubeMap {}
 
-                _ => { panic!("this should be impossible!!") },
-        };
+impl InteractsWithRay for DistantCubeMap {
+    fn continue_ray(&self, _ray: &Ray, _hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> { None } // cant shoot new ray silly
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> { None } // maybe ill do this? for a skybox it seems almost unnecessary since all rays can hit
+}
 
+impl HasHitInfo for DistantCubeMap {
+    fn hit_info(&self, _info: &HitResult, ray: &Ray) -> HitInfo {
         HitInfo {
-            emissive: sample_face(u, v, fact, face), //: vector![0.7,0.7,1.0] * atten + red_comp,
+            emissive: self.sample(&ray.d),
             pos: ray.d * f32::INFINITY,
             norm: -ray.d,
             dls: false,
@@ -59,15 +99,6 @@ impl HasHitInfo for DistantCubeMap {
     }
 }
 
-fn sample_face(u: f32, v: f32, fact: f32, facewscale: &FaceImagewUVScale) -> Vector3<f32> {
-    let (_, us, vs) = *facewscale;
-    let face = &facewscale.0;
-    let (u, v) = (u * us / fact, v * vs / fact);
-    let (u, v) = (0.5 * u + 0.5, 0.5 * v + 0.5);
-    
-    face.get_pixel(u, v)
-}
-
 impl Hitable for DistantCubeMap {
     fn intersect(&self, _ray: &Ray) -> Option<HitResult> { // always hits since distant and covers all
         Some(HitResult{l: f32::INFINITY.into(), intermed: None})
@@ -0,0 +1,53 @@
+// a single equirectangular (latitude longitude) image wrapped around everything, usually an hdr probe
+use nalgebra::Vector3;
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::elements::IsCompleteElement;
+use crate::material::UVRgb32FImage;
+use crate::accel::Aabb;
+
+pub struct Environment {
+    pub image: UVRgb32FImage,
+    pub rotation: f32, // radians around +y, turns the probe without editing the image
+    pub intensity: f32,
+    pub background: bool, // false keeps the lighting but camera rays that miss everything see black
+}
+
+impl Environment {
+    // centre of the image is straight down -z, top row is straight up
+    pub fn uv(&self, d: &Vector3<f32>) -> (f32, f32) {
+        let d = d.normalize();
+        let (s, c) = self.rotation.sin_cos();
+        let (x, z) = (c * d.x - s * d.z, s * d.x + c * d.z); // undo the rotation to find where d came from
+        let u = 0.5 + x.atan2(-z) / std::f32::consts::TAU;
+        let v = d.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+        (u, v)
+    }
+}
+
+impl IsCompleteElement for Environment {}
+
+impl InteractsWithRay for Environment {
+    fn continue_ray(&self, _ray: &Ray, _hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> { None }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> { None }
+    fn seen_by_camera(&self) -> bool { self.background }
+}
+
+impl HasHitInfo for Environment {
+    fn hit_info(&self, _info: &HitResult, ray: &Ray) -> HitInfo {
+        let (u, v) = self.uv(&ray.d);
+        HitInfo {
+            emissive: self.image.get_pixel(u, v) * self.intensity,
+            pos: ray.d * f32::INFINITY,
+            norm: -ray.d,
+            dls: false,
+            continue_info: None,
+        }
+    }
+}
+
+impl Hitable for Environment {
+    fn intersect(&self, _ray: &Ray) -> Option<HitResult> { // infinitely far away, so only ever hit when nothing else is
+        Some(HitResult{l: f32::INFINITY.into(), intermed: None})
+    }
+    fn give_aabb(&self) -> Option<Aabb> { None }
+}
@@ -0,0 +1,209 @@
+// terrain straight from a grid of heights, rays walk the grid cell by cell instead of going through triangles in the accel
+use nalgebra::{Vector3, Vector2};
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::material::*;
+use crate::elements::IsCompleteElement;
+use crate::elements::sphere::Coloring;
+use crate::accel::{Aabb, PlaneBounds};
+
+pub struct Heightfield {
+    heights: Vec<f32>, // world y per sample, rows run along +x and go down the image towards +z
+    res: (usize, usize), // samples along x and z
+    corner: Vector2<f32>, // min x and z
+    cell: Vector2<f32>, // world size of one cell along x and z
+    norms: Vec<Vector3<f32>>,
+    bounds: Aabb,
+    texture: Option<UVRgb32FImage>, // draped over the whole extent
+    coloring: Coloring,
+    mat: UniformDiffuseSpec,
+}
+
+struct BounceInfo {
+    seeding: SeedingRay,
+}
+
+struct CellHit {
+    t: f32,
+    verts: [usize; 3],
+    bary: (f32, f32),
+}
+
+impl Heightfield {
+    // heights are world y, row major from the image, corner is the min x and z
+    pub fn new(heights: Vec<f32>, res: (usize, usize), corner: Vector2<f32>, extent: Vector2<f32>,
+        texture: Option<UVRgb32FImage>, coloring: Coloring, mat: UniformDiffuseSpec,
+    ) -> Self
+    {
+        let (w, h) = res;
+        if w < 2 || h < 2 || heights.len() != w * h {
+            panic!("heightfield needs at least a 2x2 grid of heights, got {}x{}", w, h);
+        }
+        let cell = Vector2::new(extent.x / (w - 1) as f32, extent.y / (h - 1) as f32);
+
+        // central differences, one sided at the edges
+        let at = |x: usize, z: usize| heights[z * w + x];
+        let norms = (0..h).flat_map(|z| (0..w).map(move |x| (x, z))).map(|(x, z)| {
+            let (x0, x1) = (x.saturating_sub(1), (x + 1).min(w - 1));
+            let (z0, z1) = (z.saturating_sub(1), (z + 1).min(h - 1));
+            let dx = (at(x1, z) - at(x0, z)) / ((x1 - x0) as f32 * cell.x);
+            let dz = (at(x, z1) - at(x, z0)) / ((z1 - z0) as f32 * cell.y);
+            Vector3::new(-dx, 1.0, -dz).normalize()
+        }).collect();
+
+        let (low, high) = heights.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(l, h), y| (l.min(*y), h.max(*y)));
+        let bounds = Aabb {
+            bounds: [
+                PlaneBounds { low: corner.x, high: corner.x + extent.x },
+                // flat terrain would give a zero thickness box, which rays parallel to it can slip through
+                PlaneBounds { low: low - crate::EPS, high: high + crate::EPS },
+                PlaneBounds { low: corner.y, high: corner.y + extent.y },
+            ],
+        };
+
+        Heightfield { heights, res, corner, cell, norms, bounds, texture, coloring, mat }
+    }
+
+    fn idx(&self, x: usize, z: usize) -> usize {
+        z * self.res.0 + x
+    }
+
+    fn vert(&self, i: usize) -> Vector3<f32> {
+        let (x, z) = (i % self.res.0, i / self.res.0);
+        Vector3::new(self.corner.x + x as f32 * self.cell.x, self.heights[i], self.corner.y + z as f32 * self.cell.y)
+    }
+
+    // both triangles of a cell, keeping the nearer hit
+    fn hit_cell(&self, x: usize, z: usize, ray: &Ray, t_range: (f32, f32)) -> Option<CellHit> {
+        let [v00, v10, v01, v11] = [self.idx(x, z), self.idx(x + 1, z), self.idx(x, z + 1), self.idx(x + 1, z + 1)];
+
+        // skip cells the ray passes wholly above or below
+        let ys = [v00, v10, v01, v11].map(|i| self.heights[i]);
+        let (low, high) = (ys.iter().copied().fold(f32::INFINITY, f32::min), ys.iter().copied().fold(f32::NEG_INFINITY, f32::max));
+        let (y_in, y_out) = (ray.o.y + ray.d.y * t_range.0, ray.o.y + ray.d.y * t_range.1);
+        if (y_in > high && y_out > high) || (y_in < low && y_out < low) {
+            return None;
+        }
+
+        [[v00, v01, v11], [v00, v11, v10]].into_iter()
+            .filter_map(|verts| {
+                let (t, bary) = intersect_triangle(ray, &verts.map(|i| self.vert(i)))?;
+                Some(CellHit { t, verts, bary })
+            })
+            .reduce(|a, b| if a.t < b.t { a } else { b })
+    }
+}
+
+// moller trumbore, no culling since terrain can be seen from underneath too
+fn intersect_triangle(ray: &Ray, [a, b, c]: &[Vector3<f32>; 3]) -> Option<(f32, (f32, f32))> {
+    let (e1, e2) = (b - a, c - a);
+    let p = ray.d.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv = 1.0 / det;
+    let s = ray.o - a;
+    let u = s.dot(&p) * inv;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&e1);
+    let v = ray.d.dot(&q) * inv;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(&q) * inv;
+    (t > 0.0).then_some((t, (u, v)))
+}
+
+impl IsCompleteElement for Heightfield {}
+
+impl Hitable for Heightfield {
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        let ((_, entry), (_, exit)) = self.bounds.get_entry_exit(ray)?;
+        let entry = entry.max(0.0);
+        let (cells_x, cells_z) = (self.res.0 - 1, self.res.1 - 1);
+
+        // 2d dda over the cells in xz, starting wherever the ray enters the box
+        let start = ray.o + ray.d * entry;
+        let cell_of = |p: f32, lo: f32, size: f32, n: usize| (((p - lo) / size).floor().max(0.0) as usize).min(n - 1);
+        let mut x = cell_of(start.x, self.corner.x, self.cell.x, cells_x);
+        let mut z = cell_of(start.z, self.corner.y, self.cell.y, cells_z);
+
+        // t at the next cell wall along an axis, and how far apart walls are in t
+        let walls = |d: f32, o: f32, lo: f32, size: f32, i: usize| -> (f32, f32) {
+            if d.abs() < 1e-12 {
+                return (f32::INFINITY, f32::INFINITY);
+            }
+            let next = lo + size * if d > 0.0 { (i + 1) as f32 } else { i as f32 };
+            ((next - o) / d, size / d.abs())
+        };
+        let (mut next_x, delta_x) = walls(ray.d.x, ray.o.x, self.corner.x, self.cell.x, x);
+        let (mut next_z, delta_z) = walls(ray.d.z, ray.o.z, self.corner.y, self.cell.y, z);
+
+        let mut t_in = entry;
+        loop {
+            let t_out = next_x.min(next_z).min(exit);
+            if let Some(hit) = self.hit_cell(x, z, ray, (t_in, t_out)) {
+                let pos = ray.o + ray.d * hit.t;
+                return Some(HitResult { l: hit.t.into(), intermed: Some(Box::new((pos, hit.verts, hit.bary))) });
+            }
+            if t_out >= exit {
+                return None;
+            }
+            t_in = t_out;
+            if next_x < next_z {
+                match (ray.d.x > 0.0, x) {
+                    (true, x_) if x_ + 1 < cells_x => x += 1,
+                    (false, x_) if x_ > 0 => x -= 1,
+                    _ => return None,
+                }
+                next_x += delta_x;
+            } else {
+                match (ray.d.z > 0.0, z) {
+                    (true, z_) if z_ + 1 < cells_z => z += 1,
+                    (false, z_) if z_ > 0 => z -= 1,
+                    _ => return None,
+                }
+                next_z += delta_z;
+            }
+        }
+    }
+    fn give_aabb(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl HasHitInfo for Heightfield {
+    fn hit_info(&self, info: &HitResult, _ray: &Ray) -> HitInfo {
+        let (perfect_pos, [a, b, c], (u, v)) = *info.intermed.as_ref().unwrap().downcast_ref::<(Vector3<f32>, [usize; 3], (f32, f32))>().unwrap();
+        let norm = (self.norms[a] * (1.0 - u - v) + self.norms[b] * u + self.norms[c] * v).normalize();
+        let continue_info = BounceInfo { seeding: self.mat.generate_seed() };
+
+        HitInfo {
+            emissive: self.mat.emissive.unwrap_or(Vector3::zeros()),
+            pos: perfect_pos + norm * crate::EPS,
+            norm,
+            dls: self.mat.should_dls(&continue_info.seeding),
+            continue_info: Some(Box::new(continue_info)),
+        }
+    }
+}
+
+impl InteractsWithRay for Heightfield {
+    fn continue_ray(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> {
+        let seeding = &hit_info.continue_info.as_ref().unwrap().downcast_ref::<BounceInfo>().unwrap().seeding;
+        let Coloring::Solid(mut rgb) = self.coloring;
+        if let Some(texture) = &self.texture {
+            let extent = self.cell.component_mul(&Vector2::new((self.res.0 - 1) as f32, (self.res.1 - 1) as f32));
+            let (u, v) = ((hit_info.pos.x - self.corner.x) / extent.x, (hit_info.pos.z - self.corner.y) / extent.y);
+            rgb = rgb.component_mul(&texture.get_pixel(u, v));
+        }
+        let (ray, p) = self.mat.gen_new_ray(ray, &hit_info.norm, &hit_info.pos, seeding);
+
+        Some((rgb * p, ray))
+    }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> {
+        None
+    }
+}
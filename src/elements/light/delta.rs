@@ -0,0 +1,90 @@
+// lights with no surface to hit, they only ever show up through direct light sampling
+use nalgebra::Vector3;
+use super::sample_cone;
+
+pub trait DeltaLight {
+    fn illuminate(&self, pos: &Vector3<f32>) -> Option<DeltaSample>; // None if pos gets nothing from this light
+}
+
+pub struct DeltaSample {
+    pub d: Vector3<f32>, // unit vector from pos towards the light
+    pub dist: f32, // distance to the light along d, infinite for directional
+    pub irradiance: Vector3<f32>, // arriving at pos on a surface facing d, cosine term not included
+}
+
+pub struct PointLight {
+    pub pos: Vector3<f32>,
+    pub intensity: Vector3<f32>, // radiant intensity, power per steradian
+}
+
+pub struct SpotLight {
+    pub pos: Vector3<f32>,
+    pub dir: Vector3<f32>, // unit vector the cone points along
+    pub intensity: Vector3<f32>,
+    pub cos_outer: f32, // nothing outside this
+    pub cos_inner: f32, // full intensity inside this
+}
+
+pub struct DirectionalLight {
+    pub dir: Vector3<f32>, // unit vector of the direction light travels
+    pub irradiance: Vector3<f32>,
+    pub sin2_half_angle: f32, // 0 for a hard sun, otherwise directions get picked from the disk the sun covers
+}
+
+fn towards(from: &Vector3<f32>, to: &Vector3<f32>) -> Option<(Vector3<f32>, f32)> {
+    let diff = to - from;
+    let dist = diff.norm();
+    if dist < crate::EPS { None } else { Some((diff / dist, dist)) }
+}
+
+impl DeltaLight for PointLight {
+    fn illuminate(&self, pos: &Vector3<f32>) -> Option<DeltaSample> {
+        let (d, dist) = towards(pos, &self.pos)?;
+        Some(DeltaSample { d, dist, irradiance: self.intensity / (dist * dist) })
+    }
+}
+
+impl DeltaLight for SpotLight {
+    fn illuminate(&self, pos: &Vector3<f32>) -> Option<DeltaSample> {
+        let (d, dist) = towards(pos, &self.pos)?;
+        let cos_t = -d.dot(&self.dir);
+        if cos_t <= self.cos_outer {
+            return None;
+        }
+        // smoothstep between the two cones so the edge of the spot isnt a hard line
+        let t = ((cos_t - self.cos_outer) / (self.cos_inner - self.cos_outer).max(1e-6)).min(1.0);
+        let falloff = t * t * (3.0 - 2.0 * t);
+        Some(DeltaSample { d, dist, irradiance: self.intensity * falloff / (dist * dist) })
+    }
+}
+
+impl DeltaLight for DirectionalLight {
+    fn illuminate(&self, _pos: &Vector3<f32>) -> Option<DeltaSample> {
+        let d = if self.sin2_half_angle > 0.0 {
+            // uniform radiance over the sun's disk, radiance / pdf works out to the total irradiance
+            sample_cone(&(-self.dir), self.sin2_half_angle).d
+        } else {
+            -self.dir
+        };
+        Some(DeltaSample { d, dist: f32::INFINITY, irradiance: self.irradiance })
+    }
+}
+
+// rgb for a black body at given kelvin, Tanner Helland's fit: https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html
+pub fn temperature_to_rgb(kelvin: f32) -> Vector3<f32> {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.69873 * (t - 60.0).powf(-0.13320476) };
+    let g = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.075514849)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+    Vector3::new(r, g, b).map(|c| c.clamp(0.0, 255.0) / 255.0)
+}
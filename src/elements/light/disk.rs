@@ -0,0 +1,110 @@
+use nalgebra::Vector3;
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter, DLSSample};
+use crate::elements::IsCompleteElement;
+use crate::accel::{Aabb, PlaneBounds};
+use crate::elements::triangle::FreeTriangle;
+use super::{LightSampling, emissive_triangle, rand_pair, orthonormal_basis, sample_cone, area_sample_to_dir, plane_hit_l, facing_emission};
+
+pub struct DiskLight {
+    pub c: Vector3<f32>,
+    pub norm: Vector3<f32>, // emitting side, should be unit vector
+    pub r: f32,
+
+    pub radiance: Vector3<f32>,
+    pub two_sided: bool,
+    pub sampling: LightSampling,
+}
+
+impl DiskLight {
+    pub fn area(&self) -> f32 {
+        std::f32::consts::PI * self.r * self.r
+    }
+
+    pub fn to_free_triangles(&self) -> Vec<FreeTriangle> {
+        const SEGMENTS: usize = 32;
+        let (x, y) = orthonormal_basis(&self.norm);
+        let rim = |i: usize| {
+            let phi = 2.0 * std::f32::consts::PI * (i % SEGMENTS) as f32 / SEGMENTS as f32;
+            self.c + (x * phi.cos() + y * phi.sin()) * self.r
+        };
+        (0..SEGMENTS)
+            .map(|i| emissive_triangle([self.c, rim(i), rim(i + 1)], &self.norm, &self.radiance))
+            .collect()
+    }
+
+    fn sample_area(&self, pos: &Vector3<f32>) -> Option<DLSSample> {
+        let (u, v) = rand_pair();
+        let (x, y) = orthonormal_basis(&self.norm);
+        let rad = self.r * u.sqrt();
+        let phi = 2.0 * std::f32::consts::PI * v;
+        let p = self.c + x * (rad * phi.cos()) + y * (rad * phi.sin());
+        area_sample_to_dir(pos, &p, &self.norm, self.area())
+    }
+
+    fn sample_solid_angle(&self, pos: &Vector3<f32>) -> Option<DLSSample> {
+        // sample the cone around the disk's bounding sphere, directions that miss the disk just dont contribute
+        let to_c = self.c - pos;
+        let dist2 = to_c.norm_squared();
+        let r2 = self.r * self.r;
+        if dist2 <= r2 * 1.0001 {
+            return self.sample_area(pos);
+        }
+        let samp = sample_cone(&to_c.normalize(), r2 / dist2);
+        self.intersect(&Ray { d: samp.d, o: *pos }).map(|_| samp)
+    }
+}
+
+impl IsCompleteElement for DiskLight {}
+
+impl InteractsWithRay for DiskLight {
+    fn continue_ray(&self, _ray: &Ray, _hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> { None }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> {
+        Some(Box::new(DLSEmitter_{disk: self}))
+    }
+}
+
+struct DLSEmitter_<'a> {
+    disk: &'a DiskLight,
+}
+impl DLSEmitter for DLSEmitter_<'_> {
+    fn dls_ray(&self, pos: &Vector3<f32>, _norm: &Vector3<f32>) -> Option<DLSSample> {
+        let disk = self.disk;
+        if !disk.two_sided && (pos - disk.c).dot(&disk.norm) <= 0.0 { // behind a one sided light
+            return None;
+        }
+        match disk.sampling {
+            LightSampling::Area => disk.sample_area(pos),
+            LightSampling::SolidAngle => disk.sample_solid_angle(pos),
+        }
+    }
+}
+
+impl HasHitInfo for DiskLight {
+    fn hit_info(&self, info: &HitResult, ray: &Ray) -> HitInfo {
+        let perfect_pos: &Vector3<f32> = info.intermed.as_ref().unwrap().downcast_ref().unwrap();
+        let (emissive, norm) = facing_emission(&ray.d, &self.norm, &self.radiance, self.two_sided);
+
+        HitInfo {emissive, pos: perfect_pos + norm * crate::EPS, norm, dls: false, continue_info: None}
+    }
+}
+
+impl Hitable for DiskLight {
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        let l = plane_hit_l(&ray.o, &ray.d, &self.c, &self.norm)?;
+        let pos = ray.o + ray.d * l;
+
+        if (pos - self.c).norm_squared() <= self.r * self.r {
+            Some(HitResult{l: l.into(), intermed: Some(Box::new(pos))})
+        } else {
+            None
+        }
+    }
+    fn give_aabb(&self) -> Option<Aabb> {
+        // extent of a disk along axis i is r * sqrt(1 - n_i^2)
+        let bounds: [PlaneBounds; 3] = core::array::from_fn(|i| {
+            let ext = self.r * (1.0 - self.norm[i] * self.norm[i]).max(0.0).sqrt();
+            PlaneBounds {low: self.c[i] - ext, high: self.c[i] + ext}
+        });
+        Some(Aabb { bounds })
+    }
+}
@@ -0,0 +1,120 @@
+// dedicated light sources, these end any path that hits them and only exist to emit
+// sampling here is all about giving direct light sampling a good direction + its solid angle pdf
+use nalgebra::{Vector3, vector};
+use serde::Deserialize;
+use rand::Rng;
+use crate::ray::DLSSample;
+use crate::elements::triangle::FreeTriangle;
+use crate::material::{UniformDiffuseSpec, DivertRayMethod};
+
+mod rect;
+mod disk;
+mod delta;
+
+pub use rect::RectLight;
+pub use disk::DiskLight;
+pub use delta::*;
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum Emission {
+    Radiance(Vector3<f32>), // emitted radiance per rgb channel, what HitInfo.emissive ends up being
+    Power(Vector3<f32>), // total emitted power (flux) per rgb channel, spread over the area of the light
+}
+
+impl Emission {
+    pub fn radiance(&self, area: f32, two_sided: bool) -> Vector3<f32> {
+        use Emission::*;
+        match self {
+            Radiance(l) => *l,
+            Power(p) => {
+                // lambertian emitter gives out pi * L * A per side
+                let sides = if two_sided { 2.0 } else { 1.0 };
+                p / (sides * std::f32::consts::PI * area)
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum LightSampling {
+    Area, // uniform over the surface, cheap but noisy when close to the light
+    #[default]
+    SolidAngle, // uniform over the directions the light covers from the shading point
+}
+
+pub(crate) fn rand_pair() -> (f32, f32) {
+    let u: f32 = crate::RNG.with_borrow_mut(|r| r.gen());
+    let v: f32 = crate::RNG.with_borrow_mut(|r| r.gen());
+    (u, v)
+}
+
+// two unit vectors that form a right handed frame together with unit vector w
+pub fn orthonormal_basis(w: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let helper = if w.x.abs() > 0.9 { vector![0.0, 1.0, 0.0] } else { vector![1.0, 0.0, 0.0] };
+    let x = helper.cross(w).normalize();
+    let y = w.cross(&x);
+    (x, y)
+}
+
+// uniformly sample directions in a cone around unit vector w, with sin^2 of half angle given
+pub fn sample_cone(w: &Vector3<f32>, sin2_max: f32) -> DLSSample {
+    // 1 - cos(theta_max), series expansion for tiny cones so distant lights dont collapse to 0
+    let one_minus_cos_max = if sin2_max < 1e-4 {
+        0.5 * sin2_max
+    } else {
+        1.0 - (1.0 - sin2_max.min(1.0)).sqrt()
+    };
+    let (u, v) = rand_pair();
+    let one_minus_cos = u * one_minus_cos_max;
+    let cos_t = 1.0 - one_minus_cos;
+    let sin_t = (one_minus_cos * (2.0 - one_minus_cos)).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * v;
+
+    let (x, y) = orthonormal_basis(w);
+    let d = (x * (sin_t * phi.cos()) + y * (sin_t * phi.sin()) + w * cos_t).normalize();
+
+    DLSSample { d, pdf: 1.0 / (2.0 * std::f32::consts::PI * one_minus_cos_max) }
+}
+
+// convert a point sampled uniformly on a surface of given area to a direction + solid angle pdf
+pub fn area_sample_to_dir(pos: &Vector3<f32>, p: &Vector3<f32>, light_norm: &Vector3<f32>, area: f32) -> Option<DLSSample> {
+    let to_p = p - pos;
+    let dist2 = to_p.norm_squared();
+    let d = to_p / dist2.sqrt();
+    let cos_l = d.dot(light_norm).abs();
+
+    if cos_l < crate::EPS || dist2 < crate::EPS * crate::EPS {
+        None
+    } else {
+        Some(DLSSample { d, pdf: dist2 / (cos_l * area) })
+    }
+}
+
+// shared planar light bits: which side got hit and with what
+pub(crate) fn plane_hit_l(ray_o: &Vector3<f32>, ray_d: &Vector3<f32>, on_plane: &Vector3<f32>, norm: &Vector3<f32>) -> Option<f32> {
+    let denom = ray_d.dot(norm);
+    if denom.abs() < crate::EPS * crate::EPS {
+        None
+    } else {
+        let l = (on_plane - ray_o).dot(norm) / denom;
+        if l < crate::EPS { None } else { Some(l) }
+    }
+}
+
+pub(crate) fn facing_emission(ray_d: &Vector3<f32>, norm: &Vector3<f32>, radiance: &Vector3<f32>, two_sided: bool) -> (Vector3<f32>, Vector3<f32>) { // (emissive, norm facing the ray)
+    let front = ray_d.dot(norm) < 0.0;
+    let facing_norm = if front { *norm } else { -norm };
+    let emissive = if front || two_sided { *radiance } else { Vector3::zeros() };
+    (emissive, facing_norm)
+}
+
+// the gpu path has no dedicated lights, so they get approximated as black emissive triangles
+pub(crate) fn emissive_triangle(verts: [Vector3<f32>; 3], norm: &Vector3<f32>, radiance: &Vector3<f32>) -> FreeTriangle {
+    FreeTriangle {
+        verts,
+        norm: (*norm).into(),
+        rgb: Vector3::zeros().into(),
+        diverts_ray: UniformDiffuseSpec { emissive: Some(*radiance), divert_ray: DivertRayMethod::Diff },
+        type_name: "FreeTriangle",
+    }
+}
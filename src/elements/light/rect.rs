@@ -0,0 +1,159 @@
+use nalgebra::{Vector3, vector};
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter, DLSSample};
+use crate::elements::IsCompleteElement;
+use crate::accel::{Aabb, PlaneBounds};
+use crate::elements::triangle::FreeTriangle;
+use super::{LightSampling, emissive_triangle, rand_pair, area_sample_to_dir, plane_hit_l, facing_emission};
+
+pub struct RectLight {
+    pub c: Vector3<f32>, // centre
+    pub u: Vector3<f32>, // full edge vectors, perpendicular to each other
+    pub v: Vector3<f32>,
+    pub norm: Vector3<f32>, // emitting side, u x v normalized
+
+    pub radiance: Vector3<f32>,
+    pub two_sided: bool,
+    pub sampling: LightSampling,
+}
+
+impl RectLight {
+    pub fn new(c: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32>, radiance: Vector3<f32>, two_sided: bool, sampling: LightSampling) -> Self {
+        if u.normalize().dot(&v.normalize()).abs() > 1e-3 {
+            panic!("RectLight edges u and v must be perpendicular!");
+        }
+        RectLight { c, u, v, norm: u.cross(&v).normalize(), radiance, two_sided, sampling }
+    }
+
+    pub fn area(&self) -> f32 {
+        self.u.cross(&self.v).norm()
+    }
+
+    pub fn corner(&self) -> Vector3<f32> {
+        self.c - self.u * 0.5 - self.v * 0.5
+    }
+
+    pub fn to_free_triangles(&self) -> Vec<FreeTriangle> {
+        let corner = self.corner();
+        let far = corner + self.u + self.v;
+        vec![
+            emissive_triangle([corner, corner + self.u, far], &self.norm, &self.radiance),
+            emissive_triangle([corner, far, corner + self.v], &self.norm, &self.radiance),
+        ]
+    }
+
+    fn sample_area(&self, pos: &Vector3<f32>) -> Option<DLSSample> {
+        let (s, t) = rand_pair();
+        let p = self.corner() + self.u * s + self.v * t;
+        area_sample_to_dir(pos, &p, &self.norm, self.area())
+    }
+
+    fn sample_solid_angle(&self, pos: &Vector3<f32>) -> Option<DLSSample> {
+        // spherical rectangle sampling from Urena et al. 2013, "An Area-Preserving Parametrization for Spherical Rectangles"
+        use std::f32::consts::PI;
+        let (ex, ey) = (self.u.norm(), self.v.norm());
+        let x = self.u / ex;
+        let y = self.v / ey;
+        let mut z = x.cross(&y);
+
+        let d = self.corner() - pos;
+        let (x0, y0, mut z0) = (d.dot(&x), d.dot(&y), d.dot(&z));
+        if z0 > 0.0 {
+            z0 = -z0;
+            z = -z;
+        }
+        if z0.abs() < crate::EPS { // sitting in the plane of the light
+            return None;
+        }
+        let (x1, y1) = (x0 + ex, y0 + ey);
+
+        // normals of the planes through pos and each edge
+        let n0 = vector![0.0, z0, -y0].normalize();
+        let n1 = vector![-z0, 0.0, x1].normalize();
+        let n2 = vector![0.0, -z0, y1].normalize();
+        let n3 = vector![z0, 0.0, -x0].normalize();
+        let angle = |a: &Vector3<f32>, b: &Vector3<f32>| (-a.dot(b)).clamp(-1.0, 1.0).acos();
+        let (g0, g1, g2, g3) = (angle(&n0, &n1), angle(&n1, &n2), angle(&n2, &n3), angle(&n3, &n0));
+
+        let (b0, b1) = (n0.z, n2.z);
+        let k = 2.0 * PI - g2 - g3;
+        let solid_angle = g0 + g1 - k;
+        if solid_angle < 1e-5 { // too far to parametrize stably, area sampling is just as good here
+            return self.sample_area(pos);
+        }
+
+        let (u, v) = rand_pair();
+        let au = u * solid_angle + k;
+        let fu = (au.cos() * b0 - b1) / au.sin();
+        let cu = (1.0 / (fu * fu + b0 * b0).sqrt()).copysign(fu).clamp(-1.0, 1.0);
+        let xu = (-(cu * z0) / (1.0 - cu * cu).max(1e-12).sqrt()).clamp(x0, x1);
+
+        let dist = (xu * xu + z0 * z0).sqrt();
+        let h0 = y0 / (dist * dist + y0 * y0).sqrt();
+        let h1 = y1 / (dist * dist + y1 * y1).sqrt();
+        let hv = h0 + v * (h1 - h0);
+        let hv2 = hv * hv;
+        let yv = if hv2 < 1.0 - 1e-6 { (hv * dist) / (1.0 - hv2).sqrt() } else { y1 };
+
+        let to_p = x * xu + y * yv + z * z0;
+        Some(DLSSample { d: to_p.normalize(), pdf: 1.0 / solid_angle })
+    }
+}
+
+impl IsCompleteElement for RectLight {}
+
+impl InteractsWithRay for RectLight {
+    fn continue_ray(&self, _ray: &Ray, _hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> { None }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> {
+        Some(Box::new(DLSEmitter_{rect: self}))
+    }
+}
+
+struct DLSEmitter_<'a> {
+    rect: &'a RectLight,
+}
+impl DLSEmitter for DLSEmitter_<'_> {
+    fn dls_ray(&self, pos: &Vector3<f32>, _norm: &Vector3<f32>) -> Option<DLSSample> {
+        let rect = self.rect;
+        if !rect.two_sided && (pos - rect.c).dot(&rect.norm) <= 0.0 { // behind a one sided light
+            return None;
+        }
+        match rect.sampling {
+            LightSampling::Area => rect.sample_area(pos),
+            LightSampling::SolidAngle => rect.sample_solid_angle(pos),
+        }
+    }
+}
+
+impl HasHitInfo for RectLight {
+    fn hit_info(&self, info: &HitResult, ray: &Ray) -> HitInfo {
+        let perfect_pos: &Vector3<f32> = info.intermed.as_ref().unwrap().downcast_ref().unwrap();
+        let (emissive, norm) = facing_emission(&ray.d, &self.norm, &self.radiance, self.two_sided);
+
+        HitInfo {emissive, pos: perfect_pos + norm * crate::EPS, norm, dls: false, continue_info: None}
+    }
+}
+
+impl Hitable for RectLight {
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        let l = plane_hit_l(&ray.o, &ray.d, &self.c, &self.norm)?;
+        let pos = ray.o + ray.d * l;
+        let local = pos - self.corner();
+        let s = local.dot(&self.u) / self.u.norm_squared();
+        let t = local.dot(&self.v) / self.v.norm_squared();
+
+        if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
+            Some(HitResult{l: l.into(), intermed: Some(Box::new(pos))})
+        } else {
+            None
+        }
+    }
+    fn give_aabb(&self) -> Option<Aabb> {
+        let corner = self.corner();
+        let corners = [corner, corner + self.u, corner + self.v, corner + self.u + self.v];
+        let bounds: [PlaneBounds; 3] = core::array::from_fn(|i| PlaneBounds {
+            low: corners.iter().map(|c| c[i]).fold(f32::INFINITY, f32::min),
+            high: corners.iter().map(|c| c[i]).fold(f32::NEG_INFINITY, f32::max),
+        });
+        Some(Aabb { bounds })
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+use std::any::Any;
+use nalgebra::{Vector3, Matrix3, Matrix4};
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::elements::IsCompleteElement;
+use crate::elements::triangle::{Barycentric, hit_result_at};
+use crate::accel::{Aabb, WideBvh, TreeStats, F4, V4, cross4, dot4, splat3};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use super::*;
+
+type TriRef = (usize, usize, usize); // mesh, primitive, triangle within primitive
+
+// bottom level structure: meshes kept in object space along with their own bvh, loaded once and shared by every
+// instance placing them. models get one per mesh
+#[derive(Serialize, Deserialize)]
+pub struct InstancedModel {
+    pub meshes: Vec<Mesh>,
+    tris: Vec<TriRef>,
+    bvh: WideBvh,
+    packs: Vec<TriPack>, // each leaf's triangles four at a time
+    leaf_packs: Vec<(usize, usize)>, // first, count in packs for each bvh leaf
+}
+
+// four triangles laid out for testing against a ray together, unused lanes are degenerate and never hit
+#[derive(Serialize, Deserialize)]
+struct TriPack {
+    v0: V4,
+    e1: V4,
+    e2: V4,
+    tri_idxs: [usize; 4],
+}
+
+impl InstancedModel {
+    pub fn new(meshes: Vec<Mesh>) -> Self {
+        let tris: Vec<TriRef> = meshes.iter().enumerate()
+            .flat_map(|(m, mesh)| mesh.indices.iter().enumerate()
+                .flat_map(move |(p, idxs)| (0..idxs.len()).map(move |t| (m, p, t))))
+            .collect();
+        let aabbs: Vec<Aabb> = tris.par_iter().map(|tri| Aabb::from_points(tri_verts(&meshes, tri))).collect();
+        let bvh = WideBvh::build(&aabbs);
+
+        let mut packs = vec![];
+        let leaf_packs = (0..bvh.num_leaves()).map(|leaf| {
+            let first = packs.len();
+            packs.extend(bvh.leaf_prims(leaf).chunks(4).map(|chunk| TriPack::new(&meshes, &tris, chunk)));
+            (first, packs.len() - first)
+        }).collect();
+
+        InstancedModel { meshes, tris, bvh, packs, leaf_packs }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        self.bvh.aabb()
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        self.bvh.stats()
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.tris.len()
+    }
+
+    // closest triangle, its ray length and barycentric coords, ignoring anything closer than min_l
+    pub fn closest_hit(&self, ray: &Ray, min_l: f32) -> Option<(usize, f32, Barycentric)> {
+        let (o, d) = (splat3(&ray.o), splat3(&ray.d));
+        let min_l = F4::splat(min_l.max(crate::EPS));
+        let mut baryc = (0.0, 0.0);
+        let hit = self.bvh.closest_leaf_hit(ray, |leaf, max_l| {
+            let (first, count) = self.leaf_packs[leaf];
+            let mut closest = None;
+            let mut max_l = max_l;
+            for pack in &self.packs[first..first + count] {
+                if let Some((tri_idx, l, uv)) = pack.closest_hit(&o, &d, min_l, max_l) {
+                    (max_l, baryc) = (l, uv);
+                    closest = Some((tri_idx, l));
+                }
+            }
+            closest
+        });
+        hit.map(|(tri_idx, l)| (tri_idx, l, baryc))
+    }
+
+    // whether any triangle is hit between min_l and max_l, done at the first one found
+    pub fn any_hit(&self, ray: &Ray, min_l: f32, max_l: f32) -> bool {
+        let (o, d) = (splat3(&ray.o), splat3(&ray.d));
+        let min_l = F4::splat(min_l.max(crate::EPS));
+        self.bvh.any_leaf_hit(ray, max_l, |leaf, max_l| {
+            let (first, count) = self.leaf_packs[leaf];
+            self.packs[first..first + count].iter().any(|pack| pack.closest_hit(&o, &d, min_l, max_l).is_some())
+        })
+    }
+
+    pub fn triangle(&self, tri_idx: usize) -> MeshTriangle<'_> {
+        let (m, p, t) = self.tris[tri_idx];
+        let norm = NormFromMesh::from_mesh_and_inner_idx(&self.meshes[m], m as u32, (p, t));
+        self.triangle_with_norm(tri_idx, norm.normal_transform)
+    }
+
+    // same triangle for a normal transform worked out already, that's the costly part of making one
+    pub fn triangle_with_norm(&self, tri_idx: usize, normal_transform: Matrix3<f32>) -> MeshTriangle<'_> {
+        let (m, p, t) = self.tris[tri_idx];
+        let mesh = &self.meshes[m];
+        let mesh_index = m as u32;
+        MeshTriangle {
+            verts: VertexFromMesh { index: (p, t), mesh_index, mesh },
+            norm: NormFromMesh { index: (p, t), normal_transform, mesh_index, mesh },
+            diverts_ray: DivertsRayFromMesh { index: (p, t), mesh_index, mesh },
+            rgb: RgbFromMesh { index: (p, t), mesh_index, mesh },
+            type_name: "MeshTriangle",
+        }
+    }
+}
+
+impl TriPack {
+    fn new(meshes: &[Mesh], tris: &[TriRef], tri_idxs: &[usize]) -> Self {
+        let mut pack = TriPack { v0: [F4::default(); 3], e1: [F4::default(); 3], e2: [F4::default(); 3], tri_idxs: [0; 4] };
+        for (lane, tri_idx) in tri_idxs.iter().enumerate() {
+            let [a, b, c] = tri_verts(meshes, &tris[*tri_idx]);
+            let (e1, e2) = (b - a, c - a);
+            for axis in 0..3 {
+                pack.v0[axis].0[lane] = a[axis];
+                pack.e1[axis].0[lane] = e1[axis];
+                pack.e2[axis].0[lane] = e2[axis];
+            }
+            pack.tri_idxs[lane] = *tri_idx;
+        }
+        pack
+    }
+
+    // moller trumbore on all four lanes at once, same tests as moller_trumbore. o and d are the ray splatted
+    #[inline(always)]
+    fn closest_hit(&self, o: &V4, d: &V4, min_l: F4, max_l: f32) -> Option<(usize, f32, Barycentric)> {
+        let ray_x_e2 = cross4(d, &self.e2);
+        let det = dot4(&self.e1, &ray_x_e2);
+        let inv_det = det.recip();
+        let rhs = [o[0] - self.v0[0], o[1] - self.v0[1], o[2] - self.v0[2]];
+        let u = inv_det * dot4(&rhs, &ray_x_e2);
+        let rhs_x_e1 = cross4(&rhs, &self.e1);
+        let v = inv_det * dot4(d, &rhs_x_e1);
+        let l = inv_det * dot4(&self.e2, &rhs_x_e1);
+        let parallel = det.abs();
+
+        let mut closest = None;
+        let mut max_l = max_l;
+        for lane in 0..4 {
+            let (u, v, l) = (u.0[lane], v.0[lane], l.0[lane]);
+            let hit = parallel.0[lane] >= crate::EPS && u >= 0.0 && v >= 0.0 && u + v <= 1.0 && l >= min_l.0[lane] && l < max_l;
+            if hit {
+                max_l = l;
+                closest = Some((self.tri_idxs[lane], l, (u, v)));
+            }
+        }
+        closest
+    }
+}
+
+fn tri_verts<'m>(meshes: &'m [Mesh], tri: &TriRef) -> [&'m Vector3<f32>; 3] {
+    let (m, p, t) = *tri;
+    let idxs = &meshes[m].indices[p][t];
+    idxs.map(|i| &meshes[m].poses[p][i])
+}
+
+// one placement of an InstancedModel, rays get taken into object space instead of baking the transform into vertices.
+// the scene's kd-tree or bvh is the top level over these, so moving one only changes its transform and box
+pub struct MeshInstance {
+    pub model: Arc<InstancedModel>,
+    pub tint: Vector3<f32>, // multiplies the albedo of the whole model
+    to_object: Matrix4<f32>,
+    norm_to_world: Matrix3<f32>,
+    aabb: Aabb,
+}
+
+struct InstanceIntermed {
+    tri_idx: usize,
+    obj_ray: Ray,
+    obj_l: f32,
+    baryc: Barycentric,
+}
+
+struct InstanceContinueInfo {
+    tri_idx: usize,
+    normal_transform: Matrix3<f32>, // the triangle's, kept from hit_info so continue_ray doesn't redo it
+    inner: Box<dyn Any>, // continue_info of the object space triangle
+}
+
+impl MeshInstance {
+    pub fn new(model: Arc<InstancedModel>, to_world: Matrix4<f32>, tint: Vector3<f32>) -> Self {
+        let to_object = to_world.try_inverse().expect("non invertible instance transform?");
+        let norm_to_world = to_object.fixed_resize::<3, 3>(0.0).transpose();
+        let aabb = Aabb::from_points(&model.aabb().corners().map(|c| to_world.transform_point(&c.into()).coords));
+
+        MeshInstance { model, tint, to_object, norm_to_world, aabb }
+    }
+
+    fn to_object_ray(&self, ray: &Ray) -> (Ray, f32) { // ray in object space + how much longer a unit of world length is there
+        let d = self.to_object.transform_vector(&ray.d);
+        let scale = d.norm();
+        (Ray { d: d / scale, o: self.to_object.transform_point(&ray.o.into()).coords }, scale)
+    }
+}
+
+impl IsCompleteElement for MeshInstance {}
+
+impl InteractsWithRay for MeshInstance {
+    fn continue_ray(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> {
+        let cont_info: &InstanceContinueInfo = hit_info.continue_info.as_ref().unwrap().downcast_ref().unwrap();
+        let tri = self.model.triangle_with_norm(cont_info.tri_idx, cont_info.normal_transform);
+
+        tri.continue_ray_with(ray, &hit_info.norm, &hit_info.pos, cont_info.inner.as_ref())
+            .map(|(rgb, ray)| (rgb.component_mul(&self.tint), ray))
+    }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> { None }
+}
+
+impl HasHitInfo for MeshInstance {
+    fn hit_info(&self, info: &HitResult, ray: &Ray) -> HitInfo {
+        let intermed: &InstanceIntermed = info.intermed.as_ref().unwrap().downcast_ref().unwrap();
+        let tri = self.model.triangle(intermed.tri_idx);
+        let obj_hit = hit_result_at(intermed.obj_l, intermed.baryc);
+        let obj_info = tri.hit_info(&obj_hit, &intermed.obj_ray);
+
+        let norm = (self.norm_to_world * obj_info.norm).normalize();
+        let pos = ray.d * info.l.0 + ray.o + norm * crate::EPS; // create offset from surface to prevent errors
+
+        HitInfo {
+            emissive: obj_info.emissive,
+            pos,
+            norm,
+            dls: obj_info.dls,
+            continue_info: Some(Box::new(InstanceContinueInfo {
+                tri_idx: intermed.tri_idx,
+                normal_transform: tri.norm.normal_transform,
+                inner: obj_info.continue_info.unwrap(),
+            })),
+        }
+    }
+}
+
+impl Hitable for MeshInstance {
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        let (obj_ray, scale) = self.to_object_ray(ray);
+        // skip self intersections here, closest_ray_hit would otherwise throw away the whole model for a ray leaving its surface
+        let min_l = crate::EPS * 20.0 * scale;
+
+        self.model.closest_hit(&obj_ray, min_l).map(|(tri_idx, l, baryc)| {
+            HitResult { l: (l / scale).into(), intermed: Some(Box::new(InstanceIntermed { tri_idx, obj_ray, obj_l: l, baryc })) }
+        })
+    }
+    fn occludes(&self, ray: &Ray, max_l: f32) -> bool {
+        let (obj_ray, scale) = self.to_object_ray(ray);
+        self.model.any_hit(&obj_ray, crate::EPS * 20.0 * scale, max_l * scale)
+    }
+    fn give_aabb(&self) -> Option<Aabb> {
+        Some(self.aabb)
+    }
+}
@@ -3,10 +3,12 @@ use crate::elements::Decomposable;
 use crate::elements::Element;
 use super::*;
 use crate::material::*;
+use serde::{Deserialize, Serialize};
 
 // so it begins .....
 
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Mesh {
     // top layer of vec has each position as a single primitive
     pub poses: Vec<Vec<Vector3<f32>>>,
@@ -16,6 +18,7 @@ pub struct Mesh {
     pub norm_info: Vec<Option<NormInfo>>,
     pub tangents: Vec<Option<Vec<Vector3<f32>>>>,
     pub metal_rough: Vec<PbrMetalRoughInfo>,
+    pub transmission: Vec<TransmissionInfo>,
 
     pub textures: Vec<Option<UVRgb32FImage>>,
     pub normal_maps: Vec<Option<UVRgb32FImage>>,
@@ -32,23 +35,48 @@ impl Mesh {
         assert_eq!(num_primitives, self.rgb_info.len());
         assert_eq!(num_primitives, self.norm_info.len());
         assert_eq!(num_primitives, self.metal_rough.len());
+        assert_eq!(num_primitives, self.transmission.len());
         assert_eq!(num_primitives, self.textures.len());
         assert_eq!(num_primitives, self.normal_maps.len());
         assert_eq!(num_primitives, self.metal_rough_maps.len());
     }
+
+    // copy with positions baked through an extra transform on top of what's already there
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Mesh {
+        let mut mesh = self.clone();
+        mesh.poses.iter_mut().flatten().for_each(|p| *p = transform.transform_point(&(*p).into()).coords);
+        mesh.trans_mat = transform * mesh.trans_mat;
+        mesh
+    }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PbrMetalRoughInfo {
     pub metal: f32,
     pub rough: f32,
     pub coords: Option<Vec<Vector2<f32>>>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TransmissionInfo {
+    pub amount: f32, // chance of the ray going into the surface instead of bouncing off it, 0 for opaque
+    pub ior: f32,
+}
+
+impl Default for TransmissionInfo {
+    fn default() -> Self {
+        TransmissionInfo { amount: 0.0, ior: 1.5 } // gltf defaults
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RgbInfo {
     pub factor: Vector3<f32>,
     pub coords: Option<Vec<Vector2<f32>>>,
+    pub vert_colors: Option<Vec<Vector3<f32>>>, // per vertex, multiplies with the rest
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct NormInfo {
     pub scale: f32,
     pub coords: Vec<Vector2<f32>>,
@@ -85,7 +113,7 @@ impl Decomposable for Mesh {
                                 mesh_index,
                                 mesh: self,
                             },
-                            type_name: "MeshTriangle".to_string(),
+                            type_name: "MeshTriangle",
                         })} as Element<'e>)
             })
             .flatten())
@@ -1,5 +1,9 @@
 mod mesh;
 mod triangle;
+mod instance;
+mod subdivide;
 
 pub use mesh::*;
-pub use triangle::*;
\ No newline at end of file
+pub use triangle::*;
+pub use instance::*;
+pub use subdivide::SubdivScheme;
\ No newline at end of file
@@ -0,0 +1,475 @@
+// subdivision surfaces, done once at load time so the accel only ever sees the fine triangles
+// positions are welded before smoothing so uv and normal seams don't tear open, everything else
+// (uvs, colours, tangents) is interpolated linearly on its own side of the seam
+use std::collections::{HashMap, HashSet};
+use nalgebra::{Vector3, Vector2};
+use serde::Deserialize;
+use super::Mesh;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum SubdivScheme {
+    #[default]
+    Loop,
+    CatmullClark, // triangle pairs that were split from a quad get joined back up first
+}
+
+type Edge = (usize, usize); // welded vertices, low index first
+
+fn edge(a: usize, b: usize) -> Edge {
+    (a.min(b), a.max(b))
+}
+
+// the control mesh of one primitive, faces index attribute vertices which each sit on one welded position
+struct Cage {
+    geo: Vec<Vector3<f32>>,
+    attr_geo: Vec<usize>,
+    attrs: Vec<f32>, // stride floats per attribute vertex
+    stride: usize,
+    faces: Vec<Vec<usize>>,
+    creases: HashSet<Edge>,
+}
+
+impl Cage {
+    fn empty_like(&self) -> Cage {
+        Cage { geo: vec![], attr_geo: vec![], attrs: vec![], stride: self.stride, faces: vec![], creases: HashSet::new() }
+    }
+
+    fn pos(&self, a: usize) -> Vector3<f32> {
+        self.geo[self.attr_geo[a]]
+    }
+
+    fn attr(&self, a: usize) -> &[f32] {
+        &self.attrs[a * self.stride..(a + 1) * self.stride]
+    }
+
+    fn face_edges(&self, f: usize) -> impl Iterator<Item = Edge> + '_ {
+        let face = &self.faces[f];
+        (0..face.len()).map(move |i| edge(self.attr_geo[face[i]], self.attr_geo[face[(i + 1) % face.len()]]))
+    }
+
+    fn edge_faces(&self) -> HashMap<Edge, Vec<usize>> {
+        let mut adj: HashMap<Edge, Vec<usize>> = HashMap::new();
+        (0..self.faces.len()).for_each(|f| self.face_edges(f).for_each(|e| adj.entry(e).or_default().push(f)));
+        adj
+    }
+
+    // area weighted, works for quads too
+    fn face_norm(&self, f: usize) -> Vector3<f32> {
+        let p: Vec<Vector3<f32>> = self.faces[f].iter().map(|a| self.pos(*a)).collect();
+        (1..p.len() - 1).map(|i| (p[i] - p[0]).cross(&(p[i + 1] - p[0]))).sum::<Vector3<f32>>() * 0.5
+    }
+
+    fn corner_at(&self, f: usize, g: usize) -> Option<usize> {
+        self.faces[f].iter().copied().find(|a| self.attr_geo[*a] == g)
+    }
+
+    fn push_vert(&mut self, g: usize, attr: &[f32]) -> usize {
+        self.attr_geo.push(g);
+        self.attrs.extend_from_slice(attr);
+        self.attr_geo.len() - 1
+    }
+
+    fn avg_attr(&self, idxs: &[usize]) -> Vec<f32> {
+        let mut out = vec![0.0; self.stride];
+        for a in idxs {
+            out.iter_mut().zip(self.attr(*a)).for_each(|(o, v)| *o += v / idxs.len() as f32);
+        }
+        out
+    }
+
+    // old vertices keep their attribute vertices and welded indices in the finer cage
+    fn start_finer(&self) -> Cage {
+        let mut out = self.empty_like();
+        out.attr_geo = self.attr_geo.clone();
+        out.attrs = self.attrs.clone();
+        out
+    }
+
+    fn vert_edges(&self, adj: &HashMap<Edge, Vec<usize>>) -> Vec<Vec<Edge>> {
+        let mut out = vec![vec![]; self.geo.len()];
+        adj.keys().for_each(|e| { out[e.0].push(*e); out[e.1].push(*e); });
+        out
+    }
+
+    // creases pull a vertex along them like a b-spline curve, three or more pin it in place
+    fn crease_rule(&self, g: usize, edges: &[Edge]) -> Option<Vector3<f32>> {
+        let along: Vec<usize> = edges.iter()
+            .filter(|e| self.creases.contains(e))
+            .map(|e| if e.0 == g { e.1 } else { e.0 })
+            .collect();
+        match along.len() {
+            0 | 1 => None,
+            2 => Some((self.geo[g] * 6.0 + self.geo[along[0]] + self.geo[along[1]]) / 8.0),
+            _ => Some(self.geo[g]),
+        }
+    }
+
+    fn split_creases(&mut self, old: &HashSet<Edge>, mids: &HashMap<Edge, usize>) {
+        for e in old {
+            let m = mids[e];
+            self.creases.insert(edge(e.0, m));
+            self.creases.insert(edge(m, e.1));
+        }
+    }
+
+    fn loop_step(&self) -> Cage {
+        let adj = self.edge_faces();
+        let vert_edges = self.vert_edges(&adj);
+        let mut out = self.start_finer();
+
+        out.geo = (0..self.geo.len()).map(|g| {
+            let edges = &vert_edges[g];
+            self.crease_rule(g, edges).unwrap_or_else(|| {
+                let n = edges.len();
+                if n == 0 {
+                    return self.geo[g];
+                }
+                let beta = if n == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * n as f32) };
+                let ring: Vector3<f32> = edges.iter().map(|e| self.geo[if e.0 == g { e.1 } else { e.0 }]).sum();
+                self.geo[g] * (1.0 - n as f32 * beta) + ring * beta
+            })
+        }).collect();
+
+        let mut mids: HashMap<Edge, usize> = HashMap::new();
+        for (e, faces) in &adj {
+            let (a, b) = (self.geo[e.0], self.geo[e.1]);
+            let p = if faces.len() == 2 && !self.creases.contains(e) {
+                let opposite: Vector3<f32> = faces.iter()
+                    .flat_map(|f| self.faces[*f].iter().map(|a| self.attr_geo[*a]))
+                    .filter(|g| *g != e.0 && *g != e.1)
+                    .map(|g| self.geo[g])
+                    .sum();
+                (a + b) * 0.375 + opposite * 0.125
+            } else {
+                (a + b) * 0.5
+            };
+            out.geo.push(p);
+            mids.insert(*e, out.geo.len() - 1);
+        }
+
+        let mut attr_mids: HashMap<Edge, usize> = HashMap::new();
+        for face in &self.faces {
+            let [a, b, c] = [face[0], face[1], face[2]];
+            let mut mid = |x: usize, y: usize| -> usize {
+                *attr_mids.entry(edge(x, y)).or_insert_with(|| {
+                    let g = mids[&edge(self.attr_geo[x], self.attr_geo[y])];
+                    out.push_vert(g, &self.avg_attr(&[x, y]))
+                })
+            };
+            let (ab, bc, ca) = (mid(a, b), mid(b, c), mid(c, a));
+            out.faces.extend([vec![a, ab, ca], vec![ab, b, bc], vec![ca, bc, c], vec![ab, bc, ca]]);
+        }
+
+        out.split_creases(&self.creases, &mids);
+        out
+    }
+
+    fn catmull_clark_step(&self) -> Cage {
+        let adj = self.edge_faces();
+        let vert_edges = self.vert_edges(&adj);
+        let mut out = self.start_finer();
+
+        let face_pts: Vec<Vector3<f32>> = self.faces.iter()
+            .map(|f| f.iter().map(|a| self.pos(*a)).sum::<Vector3<f32>>() / f.len() as f32)
+            .collect();
+        let mut vert_faces: Vec<Vec<usize>> = vec![vec![]; self.geo.len()];
+        self.faces.iter().enumerate().for_each(|(i, f)| f.iter().for_each(|a| vert_faces[self.attr_geo[*a]].push(i)));
+
+        out.geo = (0..self.geo.len()).map(|g| {
+            let edges = &vert_edges[g];
+            self.crease_rule(g, edges).unwrap_or_else(|| {
+                let n = edges.len() as f32;
+                if edges.is_empty() {
+                    return self.geo[g];
+                }
+                let f = vert_faces[g].iter().map(|i| face_pts[*i]).sum::<Vector3<f32>>() / vert_faces[g].len() as f32;
+                let r = edges.iter().map(|e| (self.geo[e.0] + self.geo[e.1]) * 0.5).sum::<Vector3<f32>>() / n;
+                (f + r * 2.0 + self.geo[g] * (n - 3.0)) / n
+            })
+        }).collect();
+
+        let face_geo_start = out.geo.len();
+        out.geo.extend(&face_pts);
+
+        let mut mids: HashMap<Edge, usize> = HashMap::new();
+        for (e, faces) in &adj {
+            let (a, b) = (self.geo[e.0], self.geo[e.1]);
+            let p = if faces.len() == 2 && !self.creases.contains(e) {
+                (a + b + face_pts[faces[0]] + face_pts[faces[1]]) * 0.25
+            } else {
+                (a + b) * 0.5
+            };
+            out.geo.push(p);
+            mids.insert(*e, out.geo.len() - 1);
+        }
+
+        let mut attr_mids: HashMap<Edge, usize> = HashMap::new();
+        for (i, face) in self.faces.iter().enumerate() {
+            let centre = out.push_vert(face_geo_start + i, &self.avg_attr(face));
+            let mut mid = |x: usize, y: usize| -> usize {
+                *attr_mids.entry(edge(x, y)).or_insert_with(|| {
+                    let g = mids[&edge(self.attr_geo[x], self.attr_geo[y])];
+                    out.push_vert(g, &self.avg_attr(&[x, y]))
+                })
+            };
+            let k = face.len();
+            let edge_mids: Vec<usize> = (0..k).map(|j| mid(face[j], face[(j + 1) % k])).collect();
+            out.faces.extend((0..k).map(|j| vec![face[j], edge_mids[j], centre, edge_mids[(j + k - 1) % k]]));
+        }
+
+        out.split_creases(&self.creases, &mids);
+        out
+    }
+}
+
+// joins consecutive triangles that share an edge and lie flat against each other into quads
+fn pair_quads(tris: &[[usize; 3]], cage: &Cage) -> Vec<Vec<usize>> {
+    let geo = |a: usize| cage.attr_geo[a];
+    let unit_norm = |t: &[usize; 3]| {
+        let [a, b, c] = t.map(|a| cage.pos(a));
+        (b - a).cross(&(c - a)).try_normalize(1e-12)
+    };
+    let join = |t1: &[usize; 3], t2: &[usize; 3]| -> Option<Vec<usize>> {
+        let flat = unit_norm(t1)?.dot(&unit_norm(t2)?) > 10.0_f32.to_radians().cos();
+        if !flat {
+            return None;
+        }
+        // t1 goes x -> y along the shared edge and t2 comes back y -> x
+        let k = (0..3).find(|k| (0..3).any(|j| geo(t2[j]) == geo(t1[(k + 1) % 3]) && geo(t2[(j + 1) % 3]) == geo(t1[*k])))?;
+        let s = t2.iter().copied().find(|a| geo(*a) != geo(t1[k]) && geo(*a) != geo(t1[(k + 1) % 3]))?;
+        Some(vec![t1[(k + 1) % 3], t1[(k + 2) % 3], t1[k], s])
+    };
+
+    let mut faces = vec![];
+    let mut i = 0;
+    while i < tris.len() {
+        match tris.get(i + 1).and_then(|t2| join(&tris[i], t2)) {
+            Some(quad) => { faces.push(quad); i += 2; },
+            None => { faces.push(tris[i].to_vec()); i += 1; },
+        }
+    }
+    faces
+}
+
+fn flat2(v: &[Vector2<f32>]) -> Vec<f32> {
+    v.iter().flat_map(|x| [x.x, x.y]).collect()
+}
+
+fn flat3(v: &[Vector3<f32>]) -> Vec<f32> {
+    v.iter().flat_map(|x| [x.x, x.y, x.z]).collect()
+}
+
+impl Mesh {
+    // crease_angle in degrees, edges folded sharper than it stay sharp, as do boundaries and edges
+    // where the file already had split normals
+    pub fn subdivided(&self, scheme: SubdivScheme, level: u32, crease_angle: Option<f32>) -> Mesh {
+        let mut mesh = self.clone();
+        if level == 0 {
+            return mesh;
+        }
+        for p in 0..mesh.indices.len() {
+            if !mesh.indices[p].is_empty() {
+                self.subdivide_primitive(&mut mesh, p, scheme, level, crease_angle);
+            }
+        }
+        mesh.check_num_primitives();
+        mesh
+    }
+
+    fn subdivide_primitive(&self, mesh: &mut Mesh, p: usize, scheme: SubdivScheme, level: u32, crease_angle: Option<f32>) {
+        let poses = &self.poses[p];
+        let norms = &self.norms[p];
+
+        // everything that rides along with the vertices, packed together and unpacked in the same order
+        let channels: [(usize, Option<Vec<f32>>); 5] = [
+            (3, self.tangents[p].as_deref().map(flat3)),
+            (2, self.rgb_info[p].coords.as_deref().map(flat2)),
+            (3, self.rgb_info[p].vert_colors.as_deref().map(flat3)),
+            (2, self.norm_info[p].as_ref().map(|n| flat2(&n.coords))),
+            (2, self.metal_rough[p].coords.as_deref().map(flat2)),
+        ];
+        let stride = channels.iter().filter(|c| c.1.is_some()).map(|c| c.0).sum();
+
+        let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut cage = Cage { geo: vec![], attr_geo: vec![], attrs: vec![], stride, faces: vec![], creases: HashSet::new() };
+        for (i, pos) in poses.iter().enumerate() {
+            let next = welded.len();
+            let g = *welded.entry(pos.map(f32::to_bits).into()).or_insert(next);
+            if g == cage.geo.len() {
+                cage.geo.push(*pos);
+            }
+            cage.attr_geo.push(g);
+            channels.iter().for_each(|(dims, c)| if let Some(c) = c {
+                cage.attrs.extend_from_slice(&c[i * dims..(i + 1) * dims]);
+            });
+        }
+
+        cage.faces = match scheme {
+            SubdivScheme::Loop => self.indices[p].iter().map(|t| t.to_vec()).collect(),
+            SubdivScheme::CatmullClark => pair_quads(&self.indices[p], &cage),
+        };
+
+        let crease_cos = crease_angle.map(|a| a.to_radians().cos());
+        cage.creases = cage.edge_faces().into_iter().filter(|(e, faces)| {
+            let [f1, f2] = faces[..] else { return true }; // boundary or non manifold
+            let split_normals = [e.0, e.1].iter().any(|g| {
+                let (a1, a2) = (cage.corner_at(f1, *g).unwrap(), cage.corner_at(f2, *g).unwrap());
+                a1 != a2 && norms[a1].dot(&norms[a2]) < 0.999
+            });
+            let folded = crease_cos.is_some_and(|c| {
+                let (n1, n2) = (cage.face_norm(f1).try_normalize(1e-12), cage.face_norm(f2).try_normalize(1e-12));
+                n1.zip(n2).is_some_and(|(n1, n2)| n1.dot(&n2) < c)
+            });
+            split_normals || folded
+        }).map(|(e, _)| e).collect();
+
+        for _ in 0..level {
+            cage = match scheme {
+                SubdivScheme::Loop => cage.loop_step(),
+                SubdivScheme::CatmullClark => cage.catmull_clark_step(),
+            };
+        }
+
+        // normals are smoothed over the faces around a vertex that aren't cut off from each other by a crease
+        let corner_start: Vec<usize> = cage.faces.iter().scan(0, |n, f| { let s = *n; *n += f.len(); Some(s) }).collect();
+        let n_corners = cage.faces.iter().map(Vec::len).sum();
+        let mut parent: Vec<usize> = (0..n_corners).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for (e, faces) in cage.edge_faces() {
+            let [f1, f2] = faces[..] else { continue };
+            if cage.creases.contains(&e) {
+                continue;
+            }
+            for g in [e.0, e.1] {
+                let corner = |f: usize| corner_start[f] + cage.faces[f].iter().position(|a| cage.attr_geo[*a] == g).unwrap();
+                let (r1, r2) = (root(&mut parent, corner(f1)), root(&mut parent, corner(f2)));
+                parent[r1] = r2;
+            }
+        }
+        let mut wedge_norms: HashMap<usize, Vector3<f32>> = HashMap::new();
+        for (f, face) in cage.faces.iter().enumerate() {
+            let n = cage.face_norm(f);
+            for c in corner_start[f]..corner_start[f] + face.len() {
+                *wedge_norms.entry(root(&mut parent, c)).or_insert(Vector3::zeros()) += n;
+            }
+        }
+
+        // one output vertex per attribute vertex and smoothing wedge it's used in
+        let mut verts: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut out_attrs: Vec<usize> = vec![];
+        let mut out_norms: Vec<Vector3<f32>> = vec![];
+        let mut indices: Vec<[usize; 3]> = vec![];
+        for (f, face) in cage.faces.iter().enumerate() {
+            let ids: Vec<usize> = face.iter().enumerate().map(|(k, a)| {
+                let wedge = root(&mut parent, corner_start[f] + k);
+                *verts.entry((*a, wedge)).or_insert_with(|| {
+                    out_attrs.push(*a);
+                    out_norms.push(wedge_norms[&wedge].try_normalize(1e-12).unwrap_or(Vector3::y()));
+                    out_attrs.len() - 1
+                })
+            }).collect();
+            indices.extend((1..ids.len() - 1).map(|i| [ids[0], ids[i], ids[i + 1]]));
+        }
+
+        let mut offset = 0;
+        let mut unpack = |dims: usize, present: bool| -> Option<Vec<f32>> {
+            if !present {
+                return None;
+            }
+            let vals = out_attrs.iter().flat_map(|a| cage.attr(*a)[offset..offset + dims].to_vec()).collect();
+            offset += dims;
+            Some(vals)
+        };
+        let to2 = |v: Vec<f32>| -> Vec<Vector2<f32>> { v.chunks(2).map(|c| Vector2::new(c[0], c[1])).collect() };
+        let to3 = |v: Vec<f32>| -> Vec<Vector3<f32>> { v.chunks(3).map(|c| Vector3::new(c[0], c[1], c[2])).collect() };
+        let [tangents, rgb_coords, vert_colors, norm_coords, mr_coords] = channels.map(|(dims, c)| unpack(dims, c.is_some()));
+
+        mesh.poses[p] = out_attrs.iter().map(|a| cage.pos(*a)).collect();
+        // poses come already baked through trans_mat but normals get its inverse transpose at hit time, so take it
+        // back off
+        let to_mesh = self.trans_mat.fixed_view::<3, 3>(0, 0).transpose();
+        mesh.norms[p] = out_norms.iter().map(|n| (to_mesh * n).try_normalize(1e-12).unwrap_or(*n)).collect();
+        mesh.indices[p] = indices;
+        mesh.tangents[p] = tangents.map(|t| to3(t).into_iter().map(|t| t.try_normalize(1e-12).unwrap_or(t)).collect());
+        mesh.rgb_info[p].coords = rgb_coords.map(to2);
+        mesh.rgb_info[p].vert_colors = vert_colors.map(to3);
+        if let (Some(n), Some(coords)) = (mesh.norm_info[p].as_mut(), norm_coords) {
+            n.coords = to2(coords);
+        }
+        mesh.metal_rough[p].coords = mr_coords.map(to2);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nalgebra::{Matrix4, vector};
+    use crate::elements::mesh::{RgbInfo, PbrMetalRoughInfo, TransmissionInfo};
+
+    fn cube() -> Mesh {
+        let poses: Vec<Vector3<f32>> = (0..8).map(|i| vector![(i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32] * 2.0 - Vector3::repeat(1.0)).collect();
+        let quads = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        let indices = quads.iter().flat_map(|[a, b, c, d]| [[*a, *b, *c], [*a, *c, *d]]).collect();
+        Mesh {
+            norms: vec![poses.iter().map(|p| p.normalize()).collect()],
+            poses: vec![poses],
+            indices: vec![indices],
+            rgb_info: vec![RgbInfo { factor: Vector3::repeat(1.0), coords: None, vert_colors: None }],
+            norm_info: vec![None],
+            tangents: vec![None],
+            metal_rough: vec![PbrMetalRoughInfo { metal: 0.0, rough: 1.0, coords: None }],
+            transmission: vec![TransmissionInfo::default()],
+            textures: vec![None],
+            normal_maps: vec![None],
+            metal_rough_maps: vec![None],
+            trans_mat: Matrix4::identity(),
+        }
+    }
+
+    #[test]
+    fn test_catmull_clark_cube_counts() {
+        let mesh = cube().subdivided(SubdivScheme::CatmullClark, 1, None);
+        // 6 quads into 24, and 8 corners + 6 faces + 12 edges for vertices
+        assert_eq!(mesh.indices[0].len(), 48);
+        assert_eq!(mesh.poses[0].len(), 26);
+        // corners get pulled in towards the centre
+        assert!(mesh.poses[0].iter().all(|p| p.norm() < 1.1));
+    }
+
+    #[test]
+    fn test_loop_stays_closed() {
+        let mesh = cube().subdivided(SubdivScheme::Loop, 2, None);
+        let mut edges: HashMap<Edge, usize> = HashMap::new();
+        mesh.indices[0].iter().for_each(|t| (0..3).for_each(|i| *edges.entry(edge(t[i], t[(i + 1) % 3])).or_default() += 1));
+        assert!(edges.values().all(|n| *n == 2));
+    }
+
+    #[test]
+    fn test_crease_angle_keeps_cube() {
+        let mesh = cube().subdivided(SubdivScheme::CatmullClark, 2, Some(30.0));
+        // every edge of the cube is a crease, so nothing moves off its faces
+        assert!(mesh.poses[0].iter().all(|p| (p.amax() - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_normals_stay_in_mesh_space() {
+        // loaders bake poses through trans_mat, the renderer puts normals through it at hit time
+        let trans_mat = Matrix4::new_translation(&vector![3.0, -2.0, 5.0]) * Matrix4::new_scaling(2.5) * Matrix4::from_euler_angles(0.4, -1.1, 0.7);
+        let plain = cube().subdivided(SubdivScheme::Loop, 1, None);
+        let mut moved = cube().transformed(&trans_mat);
+        moved.norms[0] = cube().norms[0].clone();
+        let moved = moved.subdivided(SubdivScheme::Loop, 1, None);
+
+        let to_world = moved.trans_mat.fixed_view::<3, 3>(0, 0).try_inverse().unwrap().transpose();
+        for (n, m) in plain.norms[0].iter().zip(moved.norms[0].iter()) {
+            let expected = trans_mat.transform_vector(n).normalize();
+            assert!(((to_world * m).normalize() - expected).norm() < 1e-4);
+        }
+    }
+}
@@ -4,7 +4,7 @@ use crate::ray::Ray;
 use super::Mesh;
 use std::ops::Index;
 use std::iter::zip;
-use crate::material::DynDiffSpec;
+use crate::material::{DynDiffSpec, refract};
 pub type MeshTriangle<'a> = Triangle<VertexFromMesh<'a>, NormFromMesh<'a>, RgbFromMesh<'a>, DivertsRayFromMesh<'a>>;
 
 pub struct VertexFromMesh<'m> {
@@ -166,13 +166,17 @@ pub struct RgbFromMesh<'m> {
 impl GimmeRgb for RgbFromMesh<'_> {
     fn get_rgb(&self, barycentric: &(f32, f32)) -> Vector3<f32> {
         let (prim_idx, _inner_idx) = self.index;
-        match &self.mesh.rgb_info[prim_idx].coords {
+        let rgb = match &self.mesh.rgb_info[prim_idx].coords {
             Some(tex_coords) => {
                 let tex_coord = tex_coord_from_bary(self.mesh, &tex_coords, barycentric, self.index);
                 let pixel = self.mesh.textures[prim_idx].as_ref().expect("Textures does not exist").get_pixel(tex_coord.x, tex_coord.y);
                 self.mesh.rgb_info[prim_idx].factor.component_mul(&pixel)
             },
             None => self.mesh.rgb_info[prim_idx].factor,
+        };
+        match &self.mesh.rgb_info[prim_idx].vert_colors {
+            Some(colors) => rgb.component_mul(&vert_attr_from_bary(self.mesh, colors, barycentric, self.index)),
+            None => rgb,
         }
     }
 }
@@ -184,7 +188,7 @@ pub struct DivertsRayFromMesh<'m> {
 }
 
 impl DivertsRay for DivertsRayFromMesh<'_> {
-    type Seeding = (bool, f32); // (should_diff, roughness)
+    type Seeding = (bool, bool, f32); // (should_transmit, should_diff, roughness)
 
     //TODO: opt candidate
     fn divert_ray_seed(&self, ray: &Ray, norm: &Vector3<f32>, barycentric: &(f32, f32)) -> Self::Seeding {
@@ -203,12 +207,19 @@ impl DivertsRay for DivertsRayFromMesh<'_> {
         let r0 = 0.04 + (1.0 - 0.04) * metalness; // based on gltf definition of metalness for fresnel
         let reflectance = r0 + (1.0 - r0) * CUSTOM_ATTEN * (1.0 - (ray.d.dot(&norm)).abs().powf(5.0)); // schlick approximation
 
-        (DynDiffSpec::should_diff(1.0 - reflectance), roughness)
+        let should_transmit = DynDiffSpec::should_diff(self.mesh.transmission[prim_idx].amount); // just a weighted coin flip
+
+        (should_transmit, DynDiffSpec::should_diff(1.0 - reflectance), roughness)
     }
 
     fn divert_new_ray(&self, ray: &Ray, norm: &Vector3<f32>, o: &Vector3<f32>, seeding: &Self::Seeding) -> (Ray, f32) {
-        let (should_diff, roughness) = *seeding;
-        let (mut ray, p) = DynDiffSpec::gen_new_ray(ray, norm, o, should_diff);
+        let (should_transmit, should_diff, roughness) = *seeding;
+        let (mut ray, p) = if should_transmit {
+            let (prim_idx, _inner_idx) = self.index;
+            refract(ray, norm, o, &1.0, &self.mesh.transmission[prim_idx].ior)
+        } else {
+            DynDiffSpec::gen_new_ray(ray, norm, o, should_diff)
+        };
 
         // we do roughness here, modify the ray
         let scatter: Vector3<f32> = {
@@ -223,6 +234,11 @@ impl DivertsRay for DivertsRayFromMesh<'_> {
         ray.d = (ray.d + scatter).normalize();
         (ray, p)
     }
+
+    fn should_dls(&self, seeding: &Self::Seeding) -> bool {
+        let (should_transmit, should_diff, _roughness) = *seeding;
+        should_diff && !should_transmit
+    }
 }
 
 pub fn tex_coord_from_bary(mesh: &Mesh, coords: &Vec<Vector2<f32>>, barycentric: &(f32, f32), full_idx: (usize, usize)) -> Vector2<f32> {
@@ -236,6 +252,16 @@ pub fn tex_coord_from_bary(mesh: &Mesh, coords: &Vec<Vector2<f32>>, barycentric:
         .sum()
 }
 
+pub fn vert_attr_from_bary(mesh: &Mesh, attrs: &[Vector3<f32>], barycentric: &(f32, f32), full_idx: (usize, usize)) -> Vector3<f32> {
+    let (b1, b2) = *barycentric;
+    let baryc: [f32; 3] = [1.0 - b2 - b1, b1, b2];
+
+    let (prim_idx, inner_idx) = full_idx;
+    zip(mesh.indices[prim_idx][inner_idx].iter(), baryc.iter())
+        .map(|(i, b)| attrs[*i] * *b)
+        .sum()
+}
+
 pub fn create_mesh_triangles_from_meshes(meshes: &Vec<Mesh>) -> Vec<MeshTriangle> {
     let mesh_triangles: Vec<MeshTriangle> = meshes.iter().enumerate().flat_map(|(mesh_idx, mesh)| {
         mesh.indices.iter().enumerate()
@@ -259,7 +285,7 @@ pub fn create_mesh_triangles_from_meshes(meshes: &Vec<Mesh>) -> Vec<MeshTriangle
                             mesh_index,
                             mesh,
                         },
-                        type_name: "MeshTriangle".to_string(),
+                        type_name: "MeshTriangle",
                     }
                 })
             }).flatten()
@@ -1,7 +1,14 @@
 mod defns;
 
 pub mod sphere;
+pub mod csg;
+pub mod sdf;
+pub mod heightfield;
+pub mod curve;
 pub mod distant_cube_map;
+pub mod environment;
+pub mod sky;
 pub mod triangle;
 pub mod mesh;
+pub mod light;
 pub use defns::*;
\ No newline at end of file
@@ -0,0 +1,177 @@
+// implicit surfaces from signed distance functions, found by sphere tracing inside a given bounding box
+use nalgebra::{Vector3, Vector2};
+use serde::Deserialize;
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::material::*;
+use crate::elements::IsCompleteElement;
+use crate::elements::sphere::Coloring;
+use crate::accel::Aabb;
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum SdfNode {
+    Sphere { c: Vector3<f32>, r: f32 },
+    Box { c: Vector3<f32>, half_size: Vector3<f32> },
+    Torus { c: Vector3<f32>, major_r: f32, minor_r: f32 }, // lies flat in xz
+    Mandelbulb { c: Vector3<f32>, scale: f32, power: Option<f32>, iterations: Option<u32> }, // power 8, 10 iterations by default
+
+    Union(Vec<SdfNode>),
+    Intersection(Vec<SdfNode>),
+    Difference(Vec<SdfNode>), // first minus all the rest
+    SmoothUnion { k: f32, children: Vec<SdfNode> }, // k is roughly how far apart things start melting together
+
+    Round { r: f32, child: Box<SdfNode> }, // inflates the child's surface by r
+    Twist { rate: f32, child: Box<SdfNode> }, // radians per unit of height around the y axis
+    Repeat { period: Vector3<f32>, child: Box<SdfNode> }, // tiles space around the origin, 0 means no repeat along that axis
+    Translate { offset: Vector3<f32>, child: Box<SdfNode> },
+}
+
+impl SdfNode {
+    pub fn dist(&self, p: &Vector3<f32>) -> f32 {
+        use SdfNode::*;
+        match self {
+            Sphere { c, r } => (p - c).norm() - r,
+            Box { c, half_size } => {
+                let q = (p - c).abs() - half_size;
+                q.sup(&Vector3::zeros()).norm() + q.max().min(0.0)
+            },
+            Torus { c, major_r, minor_r } => {
+                let q = p - c;
+                Vector2::new(q.xz().norm() - major_r, q.y).norm() - minor_r
+            },
+            Mandelbulb { c, scale, power, iterations } => mandelbulb((p - c) / *scale, power.unwrap_or(8.0), iterations.unwrap_or(10)) * scale,
+
+            Union(children) => children.iter().map(|c| c.dist(p)).fold(f32::INFINITY, f32::min),
+            Intersection(children) => children.iter().map(|c| c.dist(p)).fold(f32::NEG_INFINITY, f32::max),
+            Difference(children) => children[1..].iter().fold(children[0].dist(p), |d, c| d.max(-c.dist(p))),
+            SmoothUnion { k, children } => children.iter().map(|c| c.dist(p)).reduce(|a, b| smooth_min(a, b, *k)).unwrap_or(f32::INFINITY),
+
+            Round { r, child } => child.dist(p) - r,
+            Twist { rate, child } => {
+                let (s, c) = (rate * p.y).sin_cos();
+                child.dist(&Vector3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+            },
+            Repeat { period, child } => {
+                let q = Vector3::from_fn(|a, _| if period[a] > 0.0 { p[a] - period[a] * (p[a] / period[a]).round() } else { p[a] });
+                child.dist(&q)
+            },
+            Translate { offset, child } => child.dist(&(p - offset)),
+        }
+    }
+}
+
+// polynomial smooth min from inigo quilez
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+// distance estimate from the running derivative of the power iteration
+fn mandelbulb(p: Vector3<f32>, power: f32, iterations: u32) -> f32 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+    for _ in 0..iterations {
+        r = z.norm();
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + p;
+    }
+    0.5 * r.ln() * r / dr
+}
+
+pub struct Sdf {
+    pub tree: SdfNode,
+    pub bounds: Aabb, // marching only happens in here, so it has to hold the whole surface
+    pub coloring: Coloring,
+    pub mat: UniformDiffuseSpec,
+    pub max_steps: u32,
+    pub step_scale: f32, // below 1 for twists and anything else that stretches distances
+}
+
+const SURFACE: f32 = crate::EPS * 0.5; // close enough to call it a hit
+const OFFSET: f32 = crate::EPS * 4.0; // where bounced rays start from, well clear of SURFACE
+
+struct BounceInfo {
+    seeding: SeedingRay,
+    surface_pos: Vector3<f32>,
+}
+
+impl Sdf {
+    // gradient by the tetrahedron trick, 4 evaluations instead of 6
+    fn normal(&self, p: &Vector3<f32>) -> Vector3<f32> {
+        let h = crate::EPS;
+        [Vector3::new(1.0, -1.0, -1.0), Vector3::new(-1.0, -1.0, 1.0), Vector3::new(-1.0, 1.0, -1.0), Vector3::new(1.0, 1.0, 1.0)]
+            .iter()
+            .map(|k| k * self.tree.dist(&(p + k * h)))
+            .sum::<Vector3<f32>>()
+            .try_normalize(1e-12)
+            .unwrap_or(Vector3::y())
+    }
+}
+
+impl IsCompleteElement for Sdf {}
+
+impl Hitable for Sdf {
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        let ((_, entry), (_, exit)) = self.bounds.get_entry_exit(ray)?;
+        let mut t = entry.max(0.0);
+
+        // rays that start inside (refraction) march on the negated distance
+        let side = if self.tree.dist(&(ray.o + ray.d * t)) < 0.0 { -1.0 } else { 1.0 };
+        for _ in 0..self.max_steps {
+            let p = ray.o + ray.d * t;
+            let d = side * self.tree.dist(&p);
+            if d < SURFACE {
+                return Some(HitResult { l: t.into(), intermed: Some(Box::new(p)) });
+            }
+            t += d * self.step_scale;
+            if t > exit {
+                return None;
+            }
+        }
+        None
+    }
+    fn give_aabb(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl HasHitInfo for Sdf {
+    fn hit_info(&self, info: &HitResult, _ray: &Ray) -> HitInfo {
+        let surface_pos: Vector3<f32> = *info.intermed.as_ref().unwrap().downcast_ref().unwrap();
+        let norm = self.normal(&surface_pos);
+        let continue_info = BounceInfo { seeding: self.mat.generate_seed(), surface_pos };
+
+        HitInfo {
+            emissive: self.mat.emissive.unwrap_or(Vector3::zeros()),
+            pos: surface_pos + norm * OFFSET,
+            norm,
+            dls: self.mat.should_dls(&continue_info.seeding),
+            continue_info: Some(Box::new(continue_info)),
+        }
+    }
+}
+
+impl InteractsWithRay for Sdf {
+    fn continue_ray(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> {
+        let bounce = hit_info.continue_info.as_ref().unwrap().downcast_ref::<BounceInfo>().unwrap();
+        let Coloring::Solid(rgb) = self.coloring;
+        let (mut new_ray, p) = self.mat.gen_new_ray(ray, &hit_info.norm, &hit_info.pos, &bounce.seeding);
+        if new_ray.d.dot(&hit_info.norm) < 0.0 { // going through, start on the inside
+            new_ray.o = bounce.surface_pos - hit_info.norm * OFFSET;
+        }
+
+        Some((rgb * p, new_ray))
+    }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> {
+        None
+    }
+}
@@ -0,0 +1,202 @@
+// analytic daylight from preetham, shirley and smits, "a practical analytic model for daylight" (1999)
+// the sun's disk is drawn here too, but lighting from it comes through a matching DirectionalLight
+use nalgebra::{Vector3, Matrix3};
+use image::Rgb32FImage;
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::elements::IsCompleteElement;
+use crate::elements::environment::Environment;
+use crate::elements::light::DirectionalLight;
+use crate::accel::Aabb;
+
+const SUN_ANGULAR_DIAMETER: f32 = 0.53; // degrees
+const KCD_TO_RADIANCE: f32 = 0.04; // preetham gives kcd/m^2, this puts a white surface under a high sun at about 1
+const SUN_ILLUMINANCE: f32 = 120.0; // klux above the atmosphere
+
+// perez distribution coefficients A to E for Y, x and y, as linear functions of turbidity
+const PEREZ: [[(f32, f32); 5]; 3] = [
+    [(0.1787, -1.4630), (-0.3554, 0.4275), (-0.0227, 5.3251), (0.1206, -2.5771), (-0.0670, 0.3703)],
+    [(-0.0193, -0.2592), (-0.0665, 0.0008), (-0.0004, 0.2125), (-0.0641, -0.8989), (-0.0033, 0.0452)],
+    [(-0.0167, -0.2608), (-0.0950, 0.0092), (-0.0079, 0.2102), (-0.0441, -1.6537), (-0.0109, 0.0529)],
+];
+
+pub struct Sky {
+    sun: Vector3<f32>, // unit, towards the sun
+    perez: [[f32; 5]; 3],
+    zenith: Vector3<f32>, // Y, x, y straight up
+    ground: Vector3<f32>, // radiance of everything below the horizon
+    sun_radiance: Vector3<f32>,
+    cos_sun_radius: f32,
+    intensity: f32,
+}
+
+impl Sky {
+    // sun doesn't have to be normalised, it just can't be below the horizon for the model to hold
+    pub fn new(sun: Vector3<f32>, turbidity: f32, ground_albedo: Vector3<f32>, intensity: f32, sun_intensity: f32) -> Self {
+        let sun = sun.normalize();
+        let t = turbidity;
+        let theta_s = sun.y.clamp(0.0, 1.0).acos();
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let poly = |c: [[f32; 4]; 3]| {
+            let th = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let row = |r: [f32; 4]| r.iter().zip(th).map(|(a, b)| a * b).sum::<f32>();
+            t * t * row(c[0]) + t * row(c[1]) + row(c[2])
+        };
+        let zenith_x = poly([[0.00166, -0.00375, 0.00209, 0.0], [-0.02903, 0.06377, -0.03202, 0.00394], [0.11693, -0.21196, 0.06052, 0.25886]]);
+        let zenith_yy = poly([[0.00275, -0.00610, 0.00317, 0.0], [-0.04214, 0.08970, -0.04153, 0.00516], [0.15346, -0.26756, 0.06670, 0.26688]]);
+        let perez = PEREZ.map(|coeffs| coeffs.map(|(a, b)| a * t + b));
+
+        // the atmosphere's transmittance along the sun's path, rayleigh and aerosol scattering only
+        let zenith_deg = theta_s.to_degrees();
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - zenith_deg).max(1e-3).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let transmittance = Vector3::new(0.65_f32, 0.57, 0.475) // rough wavelengths of r, g and b in micrometres
+            .map(|l| (-air_mass * (0.008735 * l.powf(-4.08) + beta * l.powf(-1.3))).exp());
+        let half = 0.5 * SUN_ANGULAR_DIAMETER.to_radians();
+        let sun_solid_angle = std::f32::consts::TAU * (1.0 - half.cos());
+        let sun_irradiance = transmittance * SUN_ILLUMINANCE * KCD_TO_RADIANCE * sun_intensity;
+
+        let mut sky = Sky {
+            sun,
+            perez,
+            zenith: Vector3::new(zenith_y, zenith_x, zenith_yy),
+            ground: Vector3::zeros(),
+            sun_radiance: sun_irradiance / sun_solid_angle,
+            cos_sun_radius: half.cos(),
+            intensity,
+        };
+
+        // ground bounces back what the sky and sun put on it, the sky part summed over a coarse dome
+        let n = 32;
+        let sky_irradiance: Vector3<f32> = (0..n).flat_map(|i| (0..2 * n).map(move |j| (i, j))).map(|(i, j)| {
+            let theta = (i as f32 + 0.5) / n as f32 * std::f32::consts::FRAC_PI_2;
+            let phi = (j as f32 + 0.5) / (2 * n) as f32 * std::f32::consts::TAU;
+            let d = Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+            let d_omega = (std::f32::consts::FRAC_PI_2 / n as f32) * (std::f32::consts::TAU / (2 * n) as f32) * theta.sin();
+            sky.dome(&d) * theta.cos() * d_omega
+        }).sum();
+        sky.ground = ground_albedo.component_mul(&(sky_irradiance + sun_irradiance * sun.y.max(0.0))) / std::f32::consts::PI;
+        sky
+    }
+
+    fn perez_f(c: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+    }
+
+    // scattered skylight only, no sun disk and nothing below the horizon
+    fn dome(&self, d: &Vector3<f32>) -> Vector3<f32> {
+        let cos_theta = d.y.max(1e-3);
+        let gamma = d.dot(&self.sun).clamp(-1.0, 1.0).acos();
+        let theta_s = self.sun.y.clamp(0.0, 1.0).acos();
+        let [yy, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * Self::perez_f(&self.perez[i], cos_theta, gamma) / Self::perez_f(&self.perez[i], 1.0, theta_s)
+        });
+        xyy_to_rgb(yy, x, y) * KCD_TO_RADIANCE
+    }
+
+    fn in_sun(&self, d: &Vector3<f32>) -> bool {
+        d.normalize().dot(&self.sun) > self.cos_sun_radius && self.sun.y > 0.0
+    }
+
+    pub fn radiance(&self, d: &Vector3<f32>) -> Vector3<f32> {
+        let d = d.normalize();
+        let l = if d.y < 0.0 { self.ground } else { self.dome(&d) };
+        let l = if self.in_sun(&d) { l + self.sun_radiance } else { l };
+        l * self.intensity
+    }
+
+    // the sun's light as a soft directional light, none once it's set
+    pub fn sun_light(&self) -> Option<DirectionalLight> {
+        if self.sun.y <= 0.0 {
+            return None;
+        }
+        let sun_solid_angle = std::f32::consts::TAU * (1.0 - self.cos_sun_radius);
+        Some(DirectionalLight {
+            dir: -self.sun,
+            irradiance: self.sun_radiance * sun_solid_angle * self.intensity,
+            sin2_half_angle: 1.0 - self.cos_sun_radius * self.cos_sun_radius,
+        })
+    }
+
+    // the gpu has no analytic sky or delta lights, so it gets a baked equirect with the sun's energy put into
+    // whichever pixel it lands in, since the real disk is smaller than one
+    pub fn bake_equirect(&self, width: u32, height: u32) -> Environment {
+        let mut env = Environment { image: Rgb32FImage::new(1, 1).into(), rotation: 0.0, intensity: 1.0, background: true };
+        let pixel_dir = |x: f32, y: f32| {
+            let (phi, theta) = ((x / width as f32 - 0.5) * std::f32::consts::TAU, y / height as f32 * std::f32::consts::PI);
+            Vector3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+        };
+        let mut image = Rgb32FImage::from_fn(width, height, |x, y| {
+            let d = pixel_dir(x as f32 + 0.5, y as f32 + 0.5);
+            let l = if d.y < 0.0 { self.ground } else { self.dome(&d) } * self.intensity;
+            image::Rgb([l.x, l.y, l.z])
+        });
+        if let Some(sun) = self.sun_light() {
+            let (u, v) = env.uv(&self.sun);
+            let (x, y) = (((u * width as f32) as u32).min(width - 1), ((v * height as f32) as u32).min(height - 1));
+            let theta = (y as f32 + 0.5) / height as f32 * std::f32::consts::PI;
+            let pixel_solid_angle = (std::f32::consts::TAU / width as f32) * (std::f32::consts::PI / height as f32) * theta.sin();
+            let extra = sun.irradiance / pixel_solid_angle;
+            let p = image.get_pixel_mut(x, y);
+            *p = image::Rgb([p.0[0] + extra.x, p.0[1] + extra.y, p.0[2] + extra.z]);
+        }
+        env.image = image.into();
+        env
+    }
+}
+
+fn xyy_to_rgb(yy: f32, x: f32, y: f32) -> Vector3<f32> {
+    if y <= 0.0 {
+        return Vector3::zeros();
+    }
+    let xyz = Vector3::new(x / y * yy, yy, (1.0 - x - y) / y * yy);
+    let to_srgb = Matrix3::new(
+        3.2406, -1.5372, -0.4986,
+        -0.9689, 1.8758, 0.0415,
+        0.0557, -0.2040, 1.0570,
+    );
+    (to_srgb * xyz).map(|c| c.max(0.0))
+}
+
+// where the sun is from the day of the year, local solar time in hours and latitude in degrees, preetham's appendix
+// north is -z and east is +x
+pub fn sun_direction(day_of_year: f32, hour: f32, latitude: f32) -> Vector3<f32> {
+    use std::f32::consts::PI;
+    let lat = latitude.to_radians();
+    let decl = 0.4093 * (2.0 * PI * (day_of_year - 81.0) / 368.0).sin();
+    let hour_angle = PI * hour / 12.0;
+    let elevation = (lat.sin() * decl.sin() - lat.cos() * decl.cos() * hour_angle.cos()).asin();
+    // measured from south, positive towards the west
+    let azimuth = (-decl.cos() * hour_angle.sin()).atan2(lat.cos() * decl.sin() - lat.sin() * decl.cos() * hour_angle.cos());
+    Vector3::new(-elevation.cos() * azimuth.sin(), elevation.sin(), elevation.cos() * azimuth.cos())
+}
+
+impl IsCompleteElement for Sky {}
+
+impl InteractsWithRay for Sky {
+    fn continue_ray(&self, _ray: &Ray, _hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> { None }
+    fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> { None }
+    fn delta_stand_in(&self, ray: &Ray) -> Vector3<f32> {
+        if self.in_sun(&ray.d) { self.sun_radiance * self.intensity } else { Vector3::zeros() }
+    }
+}
+
+impl HasHitInfo for Sky {
+    fn hit_info(&self, _info: &HitResult, ray: &Ray) -> HitInfo {
+        HitInfo {
+            emissive: self.radiance(&ray.d),
+            pos: ray.d * f32::INFINITY,
+            norm: -ray.d,
+            dls: false,
+            continue_info: None,
+        }
+    }
+}
+
+impl Hitable for Sky {
+    fn intersect(&self, _ray: &Ray) -> Option<HitResult> {
+        Some(HitResult{l: f32::INFINITY.into(), intermed: None})
+    }
+    fn give_aabb(&self) -> Option<Aabb> { None }
+}
@@ -1,10 +1,11 @@
 use nalgebra::Vector3;
-use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter};
+use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay, DLSEmitter, DLSSample};
 use crate::material::*;
 use serde::Deserialize;
 use crate::elements::IsCompleteElement;
+use crate::elements::light::sample_cone;
 use crate::accel::{Aabb, PlaneBounds};
-use crate::builder::Anim;
+use crate::builder::{Anim, Track};
 
 #[derive(Deserialize, Debug, Clone)]
 // pub enum Coloring<S> {
@@ -26,6 +27,7 @@ pub struct Sphere {
     pub coloring: Coloring, //<Self>,
     pub mat: UniformDiffuseSpec,
     pub animation: Option<Anim>,
+    pub tracks: Option<Vec<Track>>, // keyframed c and r
 }
 
 impl IsCompleteElement for Sphere {}
@@ -56,8 +58,16 @@ struct DLSEmitter_<'a> {
     sp: &'a Sphere,
 }
 impl<'a> DLSEmitter for DLSEmitter_<'a> {
-    fn dls_ray(&self, pos: &Vector3<f32>, _norm: &Vector3<f32>) -> Vector3<f32> {
-        (self.sp.c - pos).normalize()
+    fn dls_ray(&self, pos: &Vector3<f32>, _norm: &Vector3<f32>) -> Option<DLSSample> {
+        // sample the cone of directions subtended by the visible cap, every sample hits the sphere
+        let to_c = self.sp.c - pos;
+        let dist2 = to_c.norm_squared();
+        let r2 = self.sp.r * self.sp.r;
+        if dist2 <= r2 { // inside the light, nothing sensible to sample
+            None
+        } else {
+            Some(sample_cone(&to_c.normalize(), r2 / dist2))
+        }
     }
 }
 
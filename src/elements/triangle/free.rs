@@ -1,19 +1,98 @@
-use nalgebra::Vector3;
+use nalgebra::{Vector3, Vector2, SVector, Matrix2, Matrix3, Matrix3x2};
 use super::{Triangle, GimmeNorm, GimmeRgb, DivertsRay};
 use crate::material::*;
 use crate::ray::Ray;
+use std::iter::zip;
+use std::sync::Arc;
 
-pub type UniformColor = Vector3<f32>;
-pub type FreeTriangle = Triangle<[Vector3<f32>; 3], UniformNorm, UniformColor, UniformDiffuseSpec>;
+pub type FreeTriangle = Triangle<[Vector3<f32>; 3], FreeNorm, FreeColor, UniformDiffuseSpec>;
 
-pub struct UniformNorm(pub Vector3<f32>);
+// the face normal, optionally smoothed by per vertex normals and bent by a normal map
+pub struct FreeNorm {
+    pub face: Vector3<f32>, // unit, also what the gpu gets
+    pub verts: Option<[Vector3<f32>; 3]>,
+    pub normal_map: Option<FreeNormalMap>,
+}
+
+// tangent space normal map, +y is up in the image like gltf
+pub struct FreeNormalMap {
+    pub image: Arc<UVRgb32FImage>,
+    pub uvs: [Vector2<f32>; 3],
+    pub tangent: Vector3<f32>, // towards +u
+    pub bitangent: Vector3<f32>, // towards -v, up in the image
+}
+
+// flat colour, optionally times per vertex colours and a texture
+pub struct FreeColor {
+    pub factor: Vector3<f32>, // also what the gpu gets
+    pub verts: Option<[Vector3<f32>; 3]>,
+    pub texture: Option<(Arc<UVRgb32FImage>, [Vector2<f32>; 3])>,
+}
 
-impl GimmeRgb for UniformColor {
-    fn get_rgb(&self, _barycentric: &(f32, f32)) -> Vector3<f32> { *self }
+// same barycentric weighting as the mesh triangles, w for the first corner
+fn interpolate<const D: usize>(corners: &[SVector<f32, D>; 3], barycentric: &(f32, f32)) -> SVector<f32, D> {
+    let (b1, b2) = *barycentric;
+    zip(corners, [1.0 - b1 - b2, b1, b2])
+        .map(|(c, b)| c * b)
+        .sum()
 }
 
-impl GimmeNorm for UniformNorm {
-    fn get_norm(&self, _barycentric: &(f32, f32)) -> Vector3<f32> { self.0 }
+impl FreeNormalMap {
+    // None if the uvs are degenerate, since then there's no way to orient the map
+    pub fn new(image: Arc<UVRgb32FImage>, verts: &[Vector3<f32>; 3], uvs: [Vector2<f32>; 3]) -> Option<Self> {
+        let inv_tex_poses = Matrix2::from_columns(&[uvs[1] - uvs[0], uvs[2] - uvs[0]]).try_inverse()?;
+        let tb = Matrix3x2::from_columns(&[verts[1] - verts[0], verts[2] - verts[0]]) * inv_tex_poses; // dP/du and dP/dv as columns
+        Some(FreeNormalMap {
+            image,
+            uvs,
+            tangent: tb.column(0).normalize(),
+            bitangent: -tb.column(1).normalize(),
+        })
+    }
+}
+
+impl GimmeNorm for FreeNorm {
+    fn get_norm(&self, barycentric: &(f32, f32)) -> Vector3<f32> {
+        let norm = match &self.verts {
+            Some(norms) => interpolate(norms, barycentric).normalize(),
+            None => self.face,
+        };
+        match &self.normal_map {
+            Some(map) => {
+                // square the frame up against the smoothed normal, keeping the uvs' handedness
+                let tan = (map.tangent - norm * norm.dot(&map.tangent)).normalize();
+                let bitan = norm.cross(&tan);
+                let bitan = if bitan.dot(&map.bitangent) < 0.0 { -bitan } else { bitan };
+                let uv = interpolate(&map.uvs, barycentric);
+                let tang_norm = map.image.get_pixel(uv.x, uv.y) * 2.0 - Vector3::repeat(1.0);
+                (Matrix3::from_columns(&[tan, bitan, norm]) * tang_norm).normalize()
+            },
+            None => norm,
+        }
+    }
+}
+
+impl GimmeRgb for FreeColor {
+    fn get_rgb(&self, barycentric: &(f32, f32)) -> Vector3<f32> {
+        let rgb = match &self.texture {
+            Some((image, uvs)) => {
+                let uv = interpolate(uvs, barycentric);
+                self.factor.component_mul(&image.get_pixel(uv.x, uv.y))
+            },
+            None => self.factor,
+        };
+        match &self.verts {
+            Some(colors) => rgb.component_mul(&interpolate(colors, barycentric)),
+            None => rgb,
+        }
+    }
+}
+
+impl FreeTriangle {
+    // whether any of it goes beyond a flat coloured face
+    pub fn is_shaded(&self) -> bool {
+        self.norm.verts.is_some() || self.norm.normal_map.is_some() || self.rgb.verts.is_some() || self.rgb.texture.is_some()
+    }
 }
 
 impl DivertsRay for UniformDiffuseSpec {
@@ -25,8 +104,15 @@ impl DivertsRay for UniformDiffuseSpec {
     fn divert_new_ray(&self, ray: &Ray, norm: &Vector3<f32>, o: &Vector3<f32>, seeding: &SeedingRay) -> (Ray, f32) {
         self.gen_new_ray(ray, norm, o, seeding)
     }
+    fn should_dls(&self, seeding: &SeedingRay) -> bool {
+        UniformDiffuseSpec::should_dls(self, seeding)
+    }
+}
+
+impl From<Vector3<f32>> for FreeNorm {
+    fn from(n: Vector3<f32>) -> Self { FreeNorm { face: n, verts: None, normal_map: None } }
 }
 
-impl From<Vector3<f32>> for UniformNorm {
-    fn from(n: Vector3<f32>) -> Self { UniformNorm(n) }
-}
\ No newline at end of file
+impl From<Vector3<f32>> for FreeColor {
+    fn from(rgb: Vector3<f32>) -> Self { FreeColor { factor: rgb, verts: None, texture: None } }
+}
@@ -3,6 +3,7 @@ use crate::ray::{Ray, Hitable, HitResult, HitInfo, HasHitInfo, InteractsWithRay,
 use crate::elements::IsCompleteElement;
 use crate::accel::{Aabb, PlaneBounds};
 use std::ops::Index;
+use std::any::Any;
 
 // #[derive(Deserialize, Debug)]
 pub struct Triangle<V, N, C, D> 
@@ -13,7 +14,7 @@ pub struct Triangle<V, N, C, D>
     pub rgb: C,
     pub diverts_ray: D,
 
-    pub type_name: String
+    pub type_name: &'static str,
 }
 
 pub trait GimmeNorm {
@@ -28,14 +29,34 @@ pub trait DivertsRay {
     type Seeding;
     fn divert_ray_seed(&self, ray: &Ray, norm: &Vector3<f32>, barycentric: &(f32, f32)) -> Self::Seeding;
     fn divert_new_ray(&self, ray: &Ray, norm: &Vector3<f32>, o: &Vector3<f32>, seeding: &Self::Seeding) -> (Ray, f32);
+    fn should_dls(&self, _seeding: &Self::Seeding) -> bool { false } // whether direct light sampling makes sense for the ray about to be diverted
 }
 
-type Barycentric = (f32, f32); // u, v barycentric, w calculated as 1 - u - v
+pub type Barycentric = (f32, f32); // u, v barycentric, w calculated as 1 - u - v
 #[derive(Clone)]
 struct Intermed {
     baryc: Barycentric
 }
 
+impl<V, N, C, D, S: 'static> Triangle<V, N, C, D> 
+where
+    V : Index<usize, Output = Vector3<f32>>,
+    N : GimmeNorm,
+    C : GimmeRgb,
+    D : DivertsRay<Seeding = S>,
+{
+    // continue_ray without needing the whole HitInfo, for wrappers that hold onto this triangle's continue_info themselves
+    pub fn continue_ray_with(&self, ray: &Ray, norm: &Vector3<f32>, pos: &Vector3<f32>, continue_info: &dyn Any) -> Option<(Vector3<f32>, Ray)> {
+        let cont_info: &ContinueInfo<S> = continue_info.downcast_ref().unwrap();
+
+        let (ray, p) = self.diverts_ray.divert_new_ray(ray, norm, pos, &cont_info.seeding);
+
+        let rgb = self.rgb.get_rgb(&cont_info.baryc);
+
+        Some((rgb * p, ray))
+    }
+}
+
 impl<V, N, C, D, S: 'static> IsCompleteElement for Triangle<V, N, C, D> 
 where
     V : Index<usize, Output = Vector3<f32>>,
@@ -57,13 +78,7 @@ where
     D : DivertsRay<Seeding = S>,
 {
     fn continue_ray(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)> { 
-        let cont_info: &ContinueInfo<S> = &hit_info.continue_info.as_ref().unwrap().downcast_ref().unwrap();
-
-        let (ray, p) = self.diverts_ray.divert_new_ray(ray, &hit_info.norm, &hit_info.pos, &cont_info.seeding);
-
-        let rgb = self.rgb.get_rgb(&cont_info.baryc);
-
-        Some((rgb * p, ray))
+        self.continue_ray_with(ray, &hit_info.norm, &hit_info.pos, hit_info.continue_info.as_ref().unwrap().as_ref())
     }
     fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>> { None } // maybe ill do this? will i use a light source that has triangles?
 }
@@ -80,13 +95,14 @@ where
         let norm = self.norm.get_norm(&intermed.baryc);
 
         let continue_info = ContinueInfo { seeding: self.diverts_ray.divert_ray_seed(ray, &norm, &intermed.baryc), baryc: intermed.baryc.clone() };
+        let dls = self.diverts_ray.should_dls(&continue_info.seeding);
         let pos = ray.d * info.l.0 + ray.o + norm * crate::EPS; // create offset from surface to prevent errors
 
         HitInfo {
             emissive: Vector3::zeros(),
             pos,
             norm,
-            dls: false,
+            dls,
             continue_info: Some(Box::new(continue_info)),
         }
     }
@@ -99,41 +115,9 @@ where
     C : GimmeRgb,
     D : DivertsRay<Seeding = S>,
 {
-    fn intersect(&self, ray: &Ray) -> Option<HitResult> { // always hits since distant and covers all
-        // adapted moller trumbore from https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
-        // for rapid intersection test using cramer's rule to solve for barycentric coordinates
-
-        let e1 = self.verts[1] - self.verts[0];
-        let e2 = self.verts[2] - self.verts[0];
-        let ray_x_e2 = ray.d.cross(&e2);
-        let det = e1.dot(&ray_x_e2);
-
-        if det.abs() < crate::EPS { // means triangle is parallel to ray
-            None
-        } else {
-            let inv_det = 1.0 / det;
-            let rhs = ray.o - self.verts[0];
-            let u = inv_det * rhs.dot(&ray_x_e2);
-
-            if u < 0.0 || u > 1.0 {
-                None
-            } else {
-                let rhs_x_e1 = rhs.cross(&e1);
-                let v = inv_det * ray.d.dot(&rhs_x_e1);
-
-                if v < 0.0 || (u + v) > 1.0 {
-                    None
-                } else {
-                    let l = inv_det * e2.dot(&rhs_x_e1);
-
-                    if l < crate::EPS {
-                        None
-                    } else {
-                        Some(HitResult{l: l.into(), intermed: Some(Box::new(Intermed{baryc: (u, v)}))})
-                    }
-                }
-            }
-        }
+    fn intersect(&self, ray: &Ray) -> Option<HitResult> {
+        moller_trumbore(ray, [&self.verts[0], &self.verts[1], &self.verts[2]])
+            .map(|(l, baryc)| hit_result_at(l, baryc))
     }
     fn give_aabb(&self) -> Option<Aabb> {
         let axes_bounds = (0..3)
@@ -154,4 +138,47 @@ where
             ]
         })
     }
-}
\ No newline at end of file
+}
+
+// what intersect gives for a hit found some other way, e.g. four triangles at a time
+pub fn hit_result_at(l: f32, baryc: Barycentric) -> HitResult {
+    HitResult{l: l.into(), intermed: Some(Box::new(Intermed{baryc}))}
+}
+
+// ray length and barycentric coords of the hit, if any
+pub fn moller_trumbore(ray: &Ray, verts: [&Vector3<f32>; 3]) -> Option<(f32, Barycentric)> {
+    // adapted moller trumbore from https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+    // for rapid intersection test using cramer's rule to solve for barycentric coordinates
+
+    let e1 = verts[1] - verts[0];
+    let e2 = verts[2] - verts[0];
+    let ray_x_e2 = ray.d.cross(&e2);
+    let det = e1.dot(&ray_x_e2);
+
+    if det.abs() < crate::EPS { // means triangle is parallel to ray
+        None
+    } else {
+        let inv_det = 1.0 / det;
+        let rhs = ray.o - verts[0];
+        let u = inv_det * rhs.dot(&ray_x_e2);
+
+        if u < 0.0 || u > 1.0 {
+            None
+        } else {
+            let rhs_x_e1 = rhs.cross(&e1);
+            let v = inv_det * ray.d.dot(&rhs_x_e1);
+
+            if v < 0.0 || (u + v) > 1.0 {
+                None
+            } else {
+                let l = inv_det * e2.dot(&rhs_x_e1);
+
+                if l < crate::EPS {
+                    None
+                } else {
+                    Some((l, (u, v)))
+                }
+            }
+        }
+    }
+}
@@ -2,4 +2,4 @@ mod generic;
 mod free;
 
 pub use generic::*;
-pub use free::FreeTriangle;
\ No newline at end of file
+pub use free::{FreeTriangle, FreeNorm, FreeColor, FreeNormalMap};
\ No newline at end of file
@@ -0,0 +1,80 @@
+use nalgebra::Vector3;
+use serde::Deserialize;
+use rand::Rng;
+
+// simplified marschner style fibre: a white reflection off the surface (R), light going
+// straight through (TT) and light bouncing off the back wall inside (TRT), the last two
+// coloured by however much the fibre absorbs on the way
+#[derive(Deserialize, Debug, Clone)]
+pub struct HairSpec {
+    pub color: Vector3<f32>, // what's left of the light after one pass through the fibre
+    pub roughness: Option<f32>, // spread of the lobes in radians, default 0.15
+    pub scale_tilt: Option<f32>, // cuticle scale tilt in degrees, shifts the highlights along the fibre, default 2
+}
+
+const IOR: f32 = 1.55; // keratin
+
+pub enum HairLobe {
+    R,
+    Tt,
+    Trt,
+}
+
+impl HairSpec {
+    // picks a lobe by how much it carries and gives back the outgoing direction and throughput
+    pub fn sample(&self, d: &Vector3<f32>, tangent: &Vector3<f32>, norm: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>, HairLobe) {
+        let wi = -d;
+        let sin_i = wi.dot(tangent).clamp(-1.0, 1.0);
+        let perp_i = (wi - tangent * sin_i).try_normalize(1e-6).unwrap_or(*norm);
+
+        // fresnel at the fibre surface, seen along the azimuth
+        let cos_gamma = (perp_i.dot(norm).abs() * (1.0 - sin_i * sin_i).sqrt()).clamp(0.0, 1.0);
+        let r0 = ((1.0 - IOR) / (1.0 + IOR)).powi(2);
+        let f = r0 + (1.0 - r0) * (1.0 - cos_gamma).powi(5);
+
+        let lobes = [
+            Vector3::repeat(f),
+            self.color * (1.0 - f) * (1.0 - f),
+            self.color.component_mul(&self.color) * (1.0 - f) * (1.0 - f) * f,
+        ];
+        let lum = |c: &Vector3<f32>| c.x * 0.2126 + c.y * 0.7152 + c.z * 0.0722;
+        let total: f32 = lobes.iter().map(lum).sum();
+
+        let (u, u1, u2): (f32, f32, f32) = crate::RNG.with_borrow_mut(|r| (r.gen(), r.gen(), r.gen()));
+        // box muller gives two gaussians for the price of one
+        let beta = self.roughness.unwrap_or(0.15);
+        let radius = beta * (-2.0 * (1.0 - u1).ln()).sqrt();
+        let (theta_noise, phi_noise) = (radius * (std::f32::consts::TAU * u2).cos(), radius * (std::f32::consts::TAU * u2).sin());
+        let pick = u * total;
+        let lobe = if pick < lum(&lobes[0]) {
+            HairLobe::R
+        } else if pick < lum(&lobes[0]) + lum(&lobes[1]) {
+            HairLobe::Tt
+        } else {
+            HairLobe::Trt
+        };
+
+        let alpha = self.scale_tilt.unwrap_or(2.0).to_radians();
+        let mirrored = (norm * 2.0 * perp_i.dot(norm) - perp_i).normalize();
+        let (shift, perp_o, weight) = match lobe {
+            HairLobe::R => (-2.0 * alpha, mirrored, lobes[0]),
+            HairLobe::Tt => (alpha, -perp_i, lobes[1]),
+            // comes back out the same side, but spread much wider around the fibre
+            HairLobe::Trt => (4.0 * alpha, rotate_about(&mirrored, tangent, phi_noise * 4.0), lobes[2]),
+        };
+        let perp_o = rotate_about(&perp_o, tangent, phi_noise);
+
+        // longitudinally it's a cone around the tangent, mirrored and shifted by the scales
+        let theta_o = (-sin_i.asin() + shift + theta_noise).clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+        let dir = (tangent * theta_o.sin() + perp_o * theta_o.cos()).normalize();
+
+        // divided by the chance of picking this lobe
+        let throughput = if total > 0.0 { weight * (total / lum(&weight).max(1e-6)) } else { Vector3::zeros() };
+        (dir, throughput, lobe)
+    }
+}
+
+fn rotate_about(v: &Vector3<f32>, axis: &Vector3<f32>, angle: f32) -> Vector3<f32> {
+    let (s, c) = angle.sin_cos();
+    v * c + axis.cross(v) * s + axis * axis.dot(v) * (1.0 - c)
+}
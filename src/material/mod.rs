@@ -2,7 +2,10 @@ mod uv_image;
 mod interaction;
 mod dyn_diff_spec;
 mod uniform_diff_spec;
+mod hair;
 
 pub use uv_image::UVRgb32FImage;
 pub use dyn_diff_spec::DynDiffSpec;
-pub use uniform_diff_spec::*;
\ No newline at end of file
+pub use interaction::refract;
+pub use uniform_diff_spec::*;
+pub use hair::{HairSpec, HairLobe};
\ No newline at end of file
@@ -1,11 +1,26 @@
 use image::{Pixel, Rgb32FImage};
 use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "RawImage", from = "RawImage")]
 pub struct UVRgb32FImage (Rgb32FImage);
 
+// how an image gets written out, e.g. to the model cache
+#[derive(Serialize, Deserialize)]
+struct RawImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<f32>,
+}
+
 impl UVRgb32FImage {
     pub fn get_width(&self) -> u32 { self.0.width() }
     pub fn get_height(&self) -> u32 { self.0.height() }
+    pub fn get_texel(&self, x: u32, y: u32) -> Vector3<f32> {
+        let [r, g, b] = self.0.get_pixel(x, y).0;
+        Vector3::new(r, g, b)
+    }
     pub fn get_pixel(&self, u: f32, v: f32) -> Vector3<f32> {
         let face = &self.0;
         let width = face.width() as f32;
@@ -39,4 +54,16 @@ impl UVRgb32FImage {
 
 impl From<Rgb32FImage> for UVRgb32FImage {
     fn from(im: Rgb32FImage) -> Self { UVRgb32FImage(im) }
+}
+
+impl From<UVRgb32FImage> for RawImage {
+    fn from(im: UVRgb32FImage) -> Self {
+        RawImage { width: im.0.width(), height: im.0.height(), pixels: im.0.into_raw() }
+    }
+}
+
+impl From<RawImage> for UVRgb32FImage {
+    fn from(raw: RawImage) -> Self {
+        UVRgb32FImage(Rgb32FImage::from_raw(raw.width, raw.height, raw.pixels).expect("image pixels don't fit its size?"))
+    }
 }
\ No newline at end of file
@@ -20,6 +20,11 @@ pub struct HitInfo {
 pub trait Hitable { // use I to determine if should select this object
     fn intersect(&self, ray: &Ray) -> Option<HitResult>;
     fn give_aabb(&self) -> Option<Aabb>;
+    // whether the ray hits this closer than max_l, past the same self hit guard as closest_ray_hit. for shadow rays,
+    // which dont care what they hit, so elements that can stop at their first hit should
+    fn occludes(&self, ray: &Ray, max_l: f32) -> bool {
+        self.intersect(ray).is_some_and(|hr| hr.l.0 >= crate::EPS * 20.0 && hr.l.0 < max_l)
+    }
 }
 
 pub trait HasHitInfo : Hitable {
@@ -29,10 +34,17 @@ pub trait HasHitInfo : Hitable {
 pub trait InteractsWithRay : HasHitInfo {
     fn continue_ray(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vector3<f32>, Ray)>; // vec is color contrib by this element
     fn give_dls_emitter(&self) -> Option<Box<dyn DLSEmitter + '_>>;
+    fn seen_by_camera(&self) -> bool { true } // backgrounds can light the scene while staying out of the picture
+    fn delta_stand_in(&self, _ray: &Ray) -> Vector3<f32> { Vector3::zeros() } // part of emissive already given by a delta light, eg a sky's sun disk
 }
 
 pub trait DLSEmitter {
-    fn dls_ray(&self, pos: &Vector3<f32>, norm: &Vector3<f32>) -> Vector3<f32>; // return a possible ray direction for dls with given pos and normal of hit point, should be unit vector
+    fn dls_ray(&self, pos: &Vector3<f32>, norm: &Vector3<f32>) -> Option<DLSSample>; // sample a direction towards the emitter from given pos and normal of hit point, None if emitter cant be reached from there
+}
+
+pub struct DLSSample {
+    pub d: Vector3<f32>, // should be unit vector
+    pub pdf: f32, // probability density of d wrt solid angle around pos
 }
 
 #[derive(Clone)]
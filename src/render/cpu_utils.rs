@@ -1,5 +1,6 @@
 use serde::Deserialize;
 use super::radiance::RadianceInfo;
+use crate::accel::AccelKind;
 #[derive(Deserialize, Debug, Clone, Copy)]
 pub struct RenderInfo {
     pub width: i32,
@@ -7,7 +8,8 @@ pub struct RenderInfo {
     pub samps_per_pix: i32,
     pub gpu_render_batch: Option<i32>,
     pub rad_info: RadianceInfo,
-    pub kd_tree_depth: usize,
+    pub kd_tree_depth: Option<usize>, // gpu only now, picked from the triangle count when left out
+    pub accel: Option<AccelKind>, // cpu only, KdTree by default
     pub use_gpu: Option<bool>,
     pub animation: Option<bool>,
     pub framerate: Option<f32>,
@@ -2,9 +2,9 @@ use std::iter::zip;
 use super::RenderTarget;
 use crate::ray::RayCompute;
 use crate::scene::{Scene, GPUScene};
-use crate::elements::{Renderable, Element};
+use crate::elements::{Renderable, Element, Illuminator};
 use super::radiance::radiance;
-use crate::accel::KdTree;
+use crate::accel::{SceneAccel, take_traced_rays};
 use crate::render::cpu_utils::RenderInfo;
 use crate::render::gpu_utils::GPUState;
 use crate::render::gpu_structs::{
@@ -13,6 +13,7 @@ use crate::render::gpu_structs::{
 };
 use indicatif::ProgressBar;
 use rayon::prelude::*;
+use std::time::Instant;
 
 pub fn render_to_target_gpu<F : Fn() -> ()>(render_target: &RenderTarget, scene: &GPUScene, update_hook: F, render_info: &RenderInfo, iter_progress: &ProgressBar) {
     iter_progress.set_message("Transferring Data and Creating GPU Pipeline...");
@@ -66,8 +67,19 @@ pub fn render_to_target_cpu<F : Fn() -> ()>(render_target: &RenderTarget, scene:
     let elems_and_aabbs: Vec<_> = renderables.iter().enumerate()
         .filter_map(|(i, r)| r.give_aabb().map(|aabb| (i, *r, aabb)))
         .collect();
-    let kdtree = KdTree::build(&elems_and_aabbs, &unconditional, render_info.kd_tree_depth);
+    let accel_kind = render_info.accel.unwrap_or_default();
+    let build_start = Instant::now();
+    let accel = SceneAccel::build(accel_kind, &elems_and_aabbs, &unconditional);
+    iter_progress.println(format!("built {:?} over {} elements in {:.3?}: {}", accel_kind, elems_and_aabbs.len(), build_start.elapsed(), accel.stats()));
+    let lights: Vec<Illuminator> = scene.members.iter()
+        .filter_map(|m| match m {
+            Member::Light(l) => Some(l.as_ref()),
+            _ => None,
+        })
+        .collect();
 
+    take_traced_rays();
+    let render_start = Instant::now();
     for _ in 0..render_info.samps_per_pix {
         iter_progress.set_message(format!("CPU Frame Progress..."));
         target.par_iter_mut()
@@ -75,7 +87,7 @@ pub fn render_to_target_cpu<F : Fn() -> ()>(render_target: &RenderTarget, scene:
             .map(|(i, pix)| (render_target.chunk_to_pix(i.try_into().unwrap()), pix))
             .for_each(|((x, y), pix)| {
                 let ray = ray_compute.pix_cam_to_rand_ray((x,y), &scene.cam);
-                let (rgb, _) = radiance(&ray, &kdtree, &renderables, 0, &render_info.rad_info);
+                let (rgb, _) = radiance(&ray, &accel, &renderables, &lights, 0, &render_info.rad_info);
                 let rgb: Vec<f32> = rgb.iter().copied().collect();
 
                 zip(pix.iter_mut(), &rgb).for_each(|(p, r)| {
@@ -96,6 +108,8 @@ pub fn render_to_target_cpu<F : Fn() -> ()>(render_target: &RenderTarget, scene:
         update_hook();
         iter_progress.inc(1);
     }
+    let (rays, elapsed) = (take_traced_rays(), render_start.elapsed());
+    iter_progress.println(format!("traced {} rays in {:.3?}, {:.2} Mrays/s", rays, elapsed, rays as f64 / elapsed.as_secs_f64() / 1e6));
     iter_progress.set_message("CPU Render Complete!");
     iter_progress.finish();
 }
@@ -121,6 +135,7 @@ fn decompose_groups<'e>(members: &'e Vec<Member<'e>>) -> (Vec<Renderable<'e>>, V
                 group_iters.push(g.decompose_to_elems(mesh_index));
                 mesh_index += 1;
             },
+            Light(_) => {},
         }
     });
 
@@ -2,8 +2,10 @@
 use nalgebra::Vector3;
 use serde::Deserialize;
 use bytemuck;
-use crate::accel::{PlaneBounds, Aabb};
+use crate::accel::{PlaneBounds, Aabb, TreeStats, PARALLEL_BUILD_MIN};
+use rayon::prelude::*;
 use crate::elements::distant_cube_map::DistantCubeMap;
+use crate::elements::environment::Environment;
 use crate::elements::sphere::{Sphere, Coloring};
 use crate::material::{DivertRayMethod, UniformDiffuseSpec};
 use crate::elements::triangle::FreeTriangle;
@@ -68,6 +70,9 @@ pub struct GPURenderInfo {
     // padding: [u32; 3],
 }
 
+// kd_tree_depth when the scheme leaves it to build_gpu_kd_tree
+pub const AUTO_KD_TREE_DEPTH: u32 = u32::MAX;
+
 impl GPURenderInfo {
     pub fn from_render_info(render_info: &RenderInfo) -> Self {
         Self {
@@ -76,7 +81,7 @@ impl GPURenderInfo {
             samps_per_pix: render_info.gpu_render_batch.unwrap() as u32,//render_info.samps_per_pix as u32,
             assured_depth: render_info.rad_info.russ_roull_info.assured_depth as u32,
             max_threshold: render_info.rad_info.russ_roull_info.max_thres,
-            kd_tree_depth: render_info.kd_tree_depth as u32,
+            kd_tree_depth: render_info.kd_tree_depth.map_or(AUTO_KD_TREE_DEPTH, |d| d as u32),
             debug_single_ray: render_info.rad_info.debug_single_ray as u32,
             dir_light_samp: render_info.rad_info.dir_light_samp as u32,
             // seed_time: (std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap().as_secs()) as u32,
@@ -161,8 +166,8 @@ impl GPUFreeTriangle {
             vert1: [triangle.verts[0].x, triangle.verts[0].y, triangle.verts[0].z, 1.0],
             vert2: [triangle.verts[1].x, triangle.verts[1].y, triangle.verts[1].z, 1.0],
             vert3: [triangle.verts[2].x, triangle.verts[2].y, triangle.verts[2].z, 1.0],
-            norm: [triangle.norm.0.x, triangle.norm.0.y, triangle.norm.0.z, 0.0],
-            rgb: [triangle.rgb.x, triangle.rgb.y, triangle.rgb.z, 0.0],
+            norm: [triangle.norm.face.x, triangle.norm.face.y, triangle.norm.face.z, 0.0],
+            rgb: [triangle.rgb.factor.x, triangle.rgb.factor.y, triangle.rgb.factor.z, 0.0],
             _padding: [0.0; 3],
             is_valid: 1,
             material: GPUUniformDiffuseSpec::from_material(&triangle.diverts_ray),
@@ -227,6 +232,10 @@ pub struct GPUCubeMapFaceHeader {
     pub height: u32,
     pub uv_scale_x: f32,
     pub uv_scale_y: f32,
+    pub is_equirect: u32, // a single equirectangular image instead of six faces, only read from the first header
+    pub rotation: f32, // radians around +y, only read from the first header
+    pub intensity: f32,
+    pub visible: u32, // camera rays that miss everything see the background
 }
 
 impl GPUCubeMapFaceHeader {
@@ -236,6 +245,10 @@ impl GPUCubeMapFaceHeader {
             height: 0,
             uv_scale_x: 0.0,
             uv_scale_y: 0.0,
+            is_equirect: 0,
+            rotation: 0.0,
+            intensity: 0.0,
+            visible: 0,
         }
     }
 }
@@ -261,6 +274,10 @@ impl GPUCubeMapData {
                 height: faces[i].0.get_height(),
                 uv_scale_x: faces[i].1,
                 uv_scale_y: faces[i].2,
+                is_equirect: 0,
+                rotation: cube_map.rotation,
+                intensity: 1.0,
+                visible: 1,
             }
         });
         let data: [Vec<f32>; 6] = std::array::from_fn(|i| {
@@ -272,6 +289,20 @@ impl GPUCubeMapData {
             data,
         }
     }
+    // the equirect rides in the same buffers as one oversized face
+    pub fn from_environment(env: &Environment) -> (GPUCubeMapFaceHeader, Vec<f32>) {
+        let header = GPUCubeMapFaceHeader {
+            width: env.image.get_width(),
+            height: env.image.get_height(),
+            uv_scale_x: 1.0,
+            uv_scale_y: 1.0,
+            is_equirect: 1,
+            rotation: env.rotation,
+            intensity: env.intensity,
+            visible: env.background as u32,
+        };
+        (header, env.image.as_raw())
+    }
     pub fn get_raw_buffers(&self) -> (Vec<GPUCubeMapFaceHeader>, Vec<f32>) {
         let mut header_buffer: Vec<GPUCubeMapFaceHeader> = Vec::new();
         let mut data_buffer: Vec<f32> = Vec::new();
@@ -320,6 +351,10 @@ pub struct GPUPrimitiveHeader {
     pub metal_rough_coords_offset: u32,
     pub metal_rough_coords_count: u32,
 
+    pub transmission_offset: u32, // amount then ior
+    pub vert_colors_offset: u32,
+    pub vert_colors_count: u32,
+
     pub texture_data_offset: u32,
     pub texture_data_width: u32,
     pub texture_data_height: u32,
@@ -332,6 +367,7 @@ pub struct GPUPrimitiveHeader {
     pub metal_rough_map_data_width: u32,
     pub metal_rough_map_data_height: u32,
 
+    pub _padding: [u32; 1],
 }
 
 impl GPUPrimitiveHeader {
@@ -352,7 +388,9 @@ impl GPUPrimitiveHeader {
         let metal_rough_metal_offset = norm_info_coords_offset + mesh.norm_info[i].as_ref().map_or(0, |v| v.coords.len() as u32 * 2);
         let metal_rough_rough_offset = metal_rough_metal_offset + 1;
         let metal_rough_coords_offset = metal_rough_rough_offset + 1;
-        let texture_data_offset = metal_rough_coords_offset + mesh.metal_rough[i].coords.as_ref().map_or(0, |v| v.len() as u32 * 2);
+        let transmission_offset = metal_rough_coords_offset + mesh.metal_rough[i].coords.as_ref().map_or(0, |v| v.len() as u32 * 2);
+        let vert_colors_offset = transmission_offset + 2;
+        let texture_data_offset = vert_colors_offset + mesh.rgb_info[i].vert_colors.as_ref().map_or(0, |v| v.len() as u32 * 3);
         let normal_map_data_offset = texture_data_offset + mesh.textures[i].as_ref().map_or(0, |img| img.get_width() * img.get_height() * 3) as u32;
         let metal_rough_map_data_offset = normal_map_data_offset + mesh.normal_maps[i].as_ref().map_or(0, |img| img.get_width() * img.get_height() * 3) as u32;
 
@@ -383,6 +421,10 @@ impl GPUPrimitiveHeader {
             metal_rough_coords_offset,
             metal_rough_coords_count: mesh.metal_rough[i].coords.as_ref().map_or(0, |v| v.len() as u32),
 
+            transmission_offset,
+            vert_colors_offset,
+            vert_colors_count: mesh.rgb_info[i].vert_colors.as_ref().map_or(0, |v| v.len() as u32),
+
             texture_data_offset,
             texture_data_width: mesh.textures[i].as_ref().map_or(0, |img| img.get_width() as u32),
             texture_data_height: mesh.textures[i].as_ref().map_or(0, |img| img.get_height() as u32),
@@ -394,6 +436,8 @@ impl GPUPrimitiveHeader {
             metal_rough_map_data_offset,
             metal_rough_map_data_width: mesh.metal_rough_maps[i].as_ref().map_or(0, |img| img.get_width() as u32),
             metal_rough_map_data_height: mesh.metal_rough_maps[i].as_ref().map_or(0, |img| img.get_height() as u32),
+
+            _padding: [0; 1],
         };
 
         return prim_header;
@@ -415,6 +459,9 @@ pub struct GPUPrimitiveData {
     pub metal_rough_rough: f32,
     pub metal_rough_coords: Option<Vec<[f32; 2]>>,
 
+    pub transmission: [f32; 2],
+    pub vert_colors: Option<Vec<[f32; 3]>>,
+
     pub texture_data: Option<Vec<f32>>,
 
     pub normal_map_data: Option<Vec<f32>>,
@@ -435,6 +482,10 @@ impl GPUPrimitiveData {
         buffer.extend_from_slice(bytemuck::cast_slice(&[self.metal_rough_metal]));
         buffer.extend_from_slice(bytemuck::cast_slice(&[self.metal_rough_rough]));
         self.metal_rough_coords.as_ref().map(|v| buffer.extend_from_slice(bytemuck::cast_slice(v)));
+        buffer.extend_from_slice(&self.transmission);
+        if let Some(v) = &self.vert_colors {
+            buffer.extend_from_slice(bytemuck::cast_slice(v));
+        }
         self.texture_data.as_ref().map(|v| buffer.extend_from_slice(bytemuck::cast_slice(v)));
         self.normal_map_data.as_ref().map(|v| buffer.extend_from_slice(bytemuck::cast_slice(v)));
         self.metal_rough_map_data.as_ref().map(|v| buffer.extend_from_slice(bytemuck::cast_slice(v)));
@@ -512,6 +563,8 @@ impl GPUMeshData {
                 metal_rough_metal: mesh.metal_rough[i].metal,
                 metal_rough_rough: mesh.metal_rough[i].rough,
                 metal_rough_coords: mesh.metal_rough[i].coords.as_ref().map(|v| v.iter().map(|v| [v.x, v.y]).collect()),
+                transmission: [mesh.transmission[i].amount, mesh.transmission[i].ior],
+                vert_colors: mesh.rgb_info[i].vert_colors.as_ref().map(|v| v.iter().map(|v| [v.x, v.y, v.z]).collect()),
                 texture_data: mesh.textures[i].as_ref().map(|v| v.as_raw()),
                 normal_map_data: mesh.normal_maps[i].as_ref().map(|v| v.as_raw()),
                 metal_rough_map_data: mesh.metal_rough_maps[i].as_ref().map(|v| v.as_raw()),
@@ -626,9 +679,7 @@ impl GPUAabb {
     // We want the kd tree for the meshes only for the GPU mode. KD tree doesn't help much for the other primitives as we can't add as many
     // So, the KD tree is its own primitive in a way like the Spheres and FreeTriangles
     pub fn build_gpu_kd_tree(mesh_triangles: &Vec<MeshTriangle>, max_depth: usize) -> (GPUAabb, Vec<GPUTreeNode>, Vec<u32>){
-        let mut nodes = Vec::<GPUTreeNode>::new();
-        let mut leaf_node_meshes = Vec::<u32>::with_capacity(mesh_triangles.len()); // size of leaf_node_meshes is the same as the number of meshes
-        let aabbs: Vec<(usize, Aabb)> = mesh_triangles.iter().enumerate().filter_map(|(i, tri)| tri.give_aabb().map(|aabb| (i, aabb))).collect();
+        let aabbs: Vec<(usize, Aabb)> = mesh_triangles.par_iter().enumerate().filter_map(|(i, tri)| tri.give_aabb().map(|aabb| (i, aabb))).collect();
 
         // Get the entire structure's kd tree
         // We can consider separating the kd tree for each mesh member for better granularity.
@@ -657,61 +708,85 @@ impl GPUAabb {
         // GPUTreeNode::new_branch_node();
 
         // Finally, build the tree nodes
-        Self::build_gpu_tree_nodes(&aabbs, 0, max_depth, &mut nodes, &mut leaf_node_meshes, 0, false);
+        let (nodes, leaf_node_meshes) = Self::build_gpu_tree_nodes(&aabbs, 0, max_depth);
 
         // Return the aabb of the entire kd-tree and its nodes
         (GPUAabb{bounds: kd_tree_aabb.bounds, padding: [0.0; 2]}, nodes, leaf_node_meshes)
 
     }
 
-    // Call this function recursively, but construct an easily accessible vector with it suitable for the GPU
-    pub fn build_gpu_tree_nodes(index_and_aabbs: &Vec<(usize, Aabb)>, cur_depth: usize, max_depth: usize, nodes: &mut Vec<GPUTreeNode>, leaf_node_meshes: &mut Vec<u32>, parent_node_idx: usize, high_node: bool){
+    // Call this function recursively, but construct an easily accessible vector with it suitable for the GPU.
+    // Each call gives back its own subtree root first, low then high, with node and leaf mesh pointers relative to
+    // its own vectors so big subtrees can be built on other threads and spliced in after
+    pub fn build_gpu_tree_nodes(index_and_aabbs: &Vec<(usize, Aabb)>, cur_depth: usize, max_depth: usize) -> (Vec<GPUTreeNode>, Vec<u32>) {
         // Iterate between each axis for the 
         let axis = cur_depth % 3;
 
         // Create a leaf node here
-        // TODO: If performance impact of the push is high, initialize nodes with maximum theoretical size before running so it won't take as long. 
         if cur_depth > max_depth || index_and_aabbs.len() <= 1 {
-            nodes.push(GPUTreeNode::new_leaf_node(index_and_aabbs, leaf_node_meshes));
-        } else {
-            let aabbs: Vec<Aabb> = index_and_aabbs.iter().map(|(_,aabb)| *aabb).collect();
-            let split = (&aabbs).into_iter().map(|aabb| aabb.centroid()).sum::<Vector3<f32>>() / (aabbs.len() as f32);
-            
-            // Add new branch node
-            nodes.push(GPUTreeNode::new_branch_node(axis as u32,  split[axis]));
-        
-            let cur_idx = nodes.len() - 1;
-            
-            // Update the parent's node pointer index with the child node based on whether it's low or high 
-            // For head node, it'll get updated twice. Once when it updates its own low node, then for the action low node
-            if high_node {
-                nodes.get_mut(parent_node_idx).unwrap().high = cur_idx as u32;
+            let mut leaf_node_meshes = Vec::with_capacity(index_and_aabbs.len());
+            let leaf = GPUTreeNode::new_leaf_node(index_and_aabbs, &mut leaf_node_meshes);
+            return (vec![leaf], leaf_node_meshes);
+        }
+
+        let parallel = index_and_aabbs.len() >= PARALLEL_BUILD_MIN;
+        let split = index_and_aabbs.par_iter().with_min_len(PARALLEL_BUILD_MIN)
+            .map(|(_, aabb)| aabb.centroid())
+            .reduce(Vector3::zeros, |a, b| a + b) / (index_and_aabbs.len() as f32);
+
+        // this can handle case of element in both nodes
+        let side = |goes: fn(&PlaneBounds, f32) -> bool| -> Vec<(usize, Aabb)> {
+            index_and_aabbs.par_iter().with_min_len(PARALLEL_BUILD_MIN)
+                .filter(|(_, aabb)| goes(&aabb.bounds[axis], split[axis]))
+                .copied()
+                .collect()
+        };
+        let low = side(|b, split| b.low <= split);
+        let high = side(|b, split| b.high >= split);
+
+        let build_low = || GPUAabb::build_gpu_tree_nodes(&low, cur_depth + 1, max_depth);
+        let build_high = || GPUAabb::build_gpu_tree_nodes(&high, cur_depth + 1, max_depth);
+        let ((low_nodes, low_meshes), (high_nodes, high_meshes)) = if parallel { rayon::join(build_low, build_high) } else { (build_low(), build_high()) };
+
+        // Add new branch node, children right after it
+        let mut branch = GPUTreeNode::new_branch_node(axis as u32, split[axis]);
+        branch.low = 1;
+        branch.high = 1 + low_nodes.len() as u32;
+        let mut nodes = Vec::with_capacity(1 + low_nodes.len() + high_nodes.len());
+        nodes.push(branch);
+        let mut leaf_node_meshes = Vec::with_capacity(low_meshes.len() + high_meshes.len());
+        for (node_offset, subtree, meshes) in [(1, low_nodes, low_meshes), (branch.high, high_nodes, high_meshes)] {
+            let mesh_offset = leaf_node_meshes.len() as u32;
+            nodes.extend(subtree.into_iter().map(|mut node| {
+                if node.is_leaf == 1 {
+                    node.leaf_mesh_index += mesh_offset;
+                } else {
+                    node.low += node_offset;
+                    node.high += node_offset;
+                }
+                node
+            }));
+            leaf_node_meshes.extend(meshes);
+        }
+        (nodes, leaf_node_meshes)
+    }
+}
+
+impl GPUTreeNode {
+    // shape of a tree built by build_gpu_kd_tree, root at index 0
+    pub fn stats(nodes: &[GPUTreeNode]) -> TreeStats {
+        let mut stats = TreeStats::default();
+        let mut stack: Vec<(usize, usize)> = if nodes.is_empty() { vec![] } else { vec![(0, 0)] };
+        while let Some((node_idx, depth)) = stack.pop() {
+            let node = &nodes[node_idx];
+            if node.is_leaf == 1 {
+                stats.leaf(node.leaf_mesh_size as usize, depth);
             } else {
-                nodes.get_mut(parent_node_idx).unwrap().low = cur_idx as u32;
+                stats.branch(depth);
+                stack.extend([(node.low as usize, depth + 1), (node.high as usize, depth + 1)]);
             }
-
-            let (low, high): (Vec<(usize, Aabb)>, Vec<(usize, Aabb)>) = {
-                let mut low: Vec<(usize, Aabb)> = vec![];
-                let mut high: Vec<(usize, Aabb)> = vec![];
-        
-                index_and_aabbs.iter().for_each(|(i, aabb)| {
-                    // this can handle case of element in both nodes
-                    if aabb.bounds[axis].high >= split[axis] {
-                        high.push((*i, *aabb));
-                    }
-                    if aabb.bounds[axis].low <= split[axis] {
-                        low.push((*i, *aabb));
-                    }
-                });
-                (low, high)
-            };
-
-            // Low recursive call
-            GPUAabb::build_gpu_tree_nodes(&low, cur_depth + 1, max_depth, nodes, leaf_node_meshes, cur_idx, false);
-
-            // High recursive call
-            GPUAabb::build_gpu_tree_nodes(&high, cur_depth + 1, max_depth, nodes, leaf_node_meshes, cur_idx, true);
         }
+        stats
     }
 }
 
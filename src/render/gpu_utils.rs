@@ -2,7 +2,8 @@
 use bytemuck;
 use wgpu::util::DeviceExt;
 use crate::elements::mesh::{self};
-use crate::render::gpu_structs::{GPUAabb, GPUCubeMapFaceHeader, GPUTreeNode};
+use crate::render::gpu_structs::{GPUAabb, GPUCubeMapFaceHeader, GPUTreeNode, AUTO_KD_TREE_DEPTH};
+use crate::accel::auto_depth;
 use crate::types::{GPUElements, GPU_NUM_MESH_BUFFERS};
 use super::gpu_structs::{
     GPUCamera, 
@@ -20,6 +21,7 @@ use super::gpu_structs::{
 use crate::elements::mesh::create_mesh_triangles_from_meshes;
 use super::RenderTarget;
 use pollster;
+use std::time::Instant;
 use futures_channel;
 
 
@@ -156,7 +158,7 @@ impl ComputePipeline {
     }
 
     fn create_renderables_buffer(device: &wgpu::Device, elements: &GPUElements, render_info: &GPURenderInfo) -> (wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer) {
-        let (spheres, cube_maps, free_triangles, meshes) = elements;
+        let (spheres, cube_maps, free_triangles, meshes, environments) = elements;
         let mut sphere_data: Vec<GPUSphere> = vec![];
         let mut cube_map_headers: Vec<GPUCubeMapFaceHeader> = vec![];
         let mut cube_map_data: Vec<f32> = vec![];
@@ -168,13 +170,18 @@ impl ComputePipeline {
             let gpu_sphere = GPUSphere::from_sphere(sphere);
             sphere_data.push(gpu_sphere);
         }
-        assert!(cube_maps.len() <= 1, "Expected maximum 1 cube map");
+        assert!(cube_maps.len() + environments.len() <= 1, "Expected maximum 1 cube map or environment");
         for cube_map in cube_maps {
             let gpu_cube_map = GPUCubeMapData::from_cube_map(cube_map);
             let (headers, data) = gpu_cube_map.get_raw_buffers();
             cube_map_headers.extend(headers);
             cube_map_data.extend(data);
         }
+        for env in environments {
+            let (header, data) = GPUCubeMapData::from_environment(env);
+            cube_map_headers.push(header);
+            cube_map_data.extend(data);
+        }
         for free_triangle in free_triangles {
             let gpu_free_triangle = GPUFreeTriangle::from_free_triangle(free_triangle);
             free_triangle_data.push(gpu_free_triangle);
@@ -186,7 +193,12 @@ impl ComputePipeline {
         // let index_with_aabb: Vec<(usize, GPUAabb)> = GPUAabb::get_aabb_meshes(&mesh_triangles);
         // let leaf_node_meshes = Vec::<u32>::with_capacity(mesh_triangles.len());
         if !mesh_triangles.is_empty() {
-            (kd_tree, tree_nodes, leaf_node_meshes) = GPUAabb::build_gpu_kd_tree(&mesh_triangles, render_info.kd_tree_depth as usize);
+            let start = Instant::now();
+            (kd_tree, tree_nodes, leaf_node_meshes) = GPUAabb::build_gpu_kd_tree(&mesh_triangles, match render_info.kd_tree_depth {
+                AUTO_KD_TREE_DEPTH => auto_depth(mesh_triangles.len()),
+                depth => depth as usize,
+            });
+            println!("built gpu kd-tree over {} triangles in {:.3?}: {}", mesh_triangles.len(), start.elapsed(), GPUTreeNode::stats(&tree_nodes));
         }
         for mesh_triangle in mesh_triangles {
             let gpu_mesh_triangle = GPUMeshTriangle::from_mesh_triangle(&mesh_triangle);
@@ -1,7 +1,7 @@
 use nalgebra::{Vector3, vector};
-use crate::ray::{Ray, closest_ray_hit, HitInfo};
-use crate::elements::Renderable;
-use crate::accel::KdTree;
+use crate::ray::{Ray, HitInfo};
+use crate::elements::{Renderable, Illuminator};
+use crate::accel::SceneAccel;
 use rand::Rng;
 
 use serde::Deserialize;
@@ -17,20 +17,43 @@ pub struct RussianRoullInfo {
     pub max_thres: f32,
 }
 
-pub fn radiance(ray: &Ray, kdtree: &KdTree, elems: &Vec<Renderable>, depth: i32, rad_info: &RadianceInfo) -> (Vector3<f32>, Option<usize>) { // color from a ray in a collection of hittable objects, and index of object that was hit
-    let (hit_results, idxo) = kdtree.closest_ray_hit(ray);
-    // let (hit_results, idxo) = closest_ray_hit(ray, elems.into_iter().enumerate().map(|(i, r)| (i, *r)));
+pub fn radiance(ray: &Ray, accel: &SceneAccel, elems: &Vec<Renderable>, lights: &[Illuminator], depth: i32, rad_info: &RadianceInfo) -> (Vector3<f32>, Option<usize>) { // color from a ray in a collection of hittable objects, and index of object that was hit
+    trace_radiance(ray, accel, elems, lights, depth, rad_info, Sampled{emitters: false, deltas: false})
+}
+
+// what the previous hit already gathered direct light from, so hitting it now must not count its emission twice
+#[derive(Clone, Copy)]
+struct Sampled {
+    emitters: bool, // dls emitters
+    deltas: bool, // delta lights, whose visible stand ins (the sun disk of a sky) would otherwise be counted twice
+}
+
+fn trace_radiance(ray: &Ray, accel: &SceneAccel, elems: &Vec<Renderable>, lights: &[Illuminator], depth: i32, rad_info: &RadianceInfo, sampled: Sampled) -> (Vector3<f32>, Option<usize>) {
+    let (hit_results, idxo) = accel.closest_ray_hit(ray);
     
     if let Some(hr_idx) = idxo { 
         let (elem_idx, hit_result) = &hit_results[hr_idx];
         let elem_idx = *elem_idx;
         let elem = &elems[elem_idx];
         let hit_result = &hit_result.as_ref().unwrap();
+        if depth == 0 && !elem.seen_by_camera() {
+            return (Vector3::zeros(), Some(elem_idx));
+        }
         let hit_info = elem.hit_info(hit_result, ray);
 
         if rad_info.debug_single_ray {
             (hit_info.emissive, Some(elem_idx))
         } else {
+            let emissive = if sampled.emitters && elem.give_dls_emitter().is_some() {
+                Vector3::zeros()
+            } else {
+                hit_info.emissive
+            };
+            let emissive = if sampled.deltas {
+                emissive - elem.delta_stand_in(ray)
+            } else {
+                emissive
+            };
             let (roull_pass, atten) = russian_roulette_filter(depth, &rad_info.russ_roull_info);
             
             if roull_pass {
@@ -41,29 +64,32 @@ pub fn radiance(ray: &Ray, kdtree: &KdTree, elems: &Vec<Renderable>, depth: i32,
                             Some(f) => rgb / *f,
                             None => rgb,
                         };
-                        let (incoming_rgb, incoming_idx) = radiance(&new_ray, kdtree, elems, depth + 1, rad_info);
+                        let do_dls = rad_info.dir_light_samp && hit_info.dls;
+                        let do_delta = hit_info.dls && !lights.is_empty();
+                        let (incoming_rgb, _) = trace_radiance(&new_ray, accel, elems, lights, depth + 1, rad_info, Sampled{emitters: do_dls, deltas: do_delta});
         
-                        let mul = if rad_info.dir_light_samp && hit_info.dls {
-                            let omit_idxs = if incoming_idx.is_some() {
-                                vec![elem_idx, incoming_idx.unwrap()]
-                            } else {
-                                vec![elem_idx]
-                            };
-                            let light_contrib = establish_dls_contrib(&omit_idxs, elems, &hit_info, ray);
+                        let mul = if do_dls {
+                            let light_contrib = establish_dls_contrib(&[elem_idx], accel, elems, &hit_info);
                             incoming_rgb + light_contrib
                         } else {
                             incoming_rgb
                         };
+                        // delta lights cant be hit by a bounce (their stand ins are taken off above), so they always get sampled when the surface allows it
+                        let mul = if do_delta {
+                            mul + establish_delta_contrib(lights, accel, &hit_info)
+                        } else {
+                            mul
+                        };
                         // let mul = incoming_rgb / p;
         
-                        (hit_info.emissive + rgb.component_mul(&mul), Some(elem_idx))
+                        (emissive + rgb.component_mul(&mul), Some(elem_idx))
                     },
                     None => {
-                        (hit_info.emissive, Some(elem_idx))
+                        (emissive, Some(elem_idx))
                     }
                 }
             } else {
-                (hit_info.emissive, Some(elem_idx))
+                (emissive, Some(elem_idx))
             }
         }
     } else { 
@@ -86,8 +112,10 @@ fn russian_roulette_filter(depth: i32, russ_roull_info: &RussianRoullInfo) -> (b
 }
 
 // direct light sampling based on https://iquilezles.org/articles/simplepathtracing/
-fn establish_dls_contrib(omit_idxs: &[usize], elems: &Vec<Renderable>, hit_info: &HitInfo, ray: &Ray) -> Vector3<f32> {
-    const NORMZE: f32 = 1.0 / (30.0 * std::f32::consts::PI);
+// each emitter gets one sample, weighted by its solid angle pdf. the 1/pi is the lambertian brdf,
+// albedo gets multiplied in by the caller same as for the bounced ray. the light counts if nothing is hit before it
+fn establish_dls_contrib(omit_idxs: &[usize], accel: &SceneAccel, elems: &Vec<Renderable>, hit_info: &HitInfo) -> Vector3<f32> {
+    use std::f32::consts::PI;
 
     // only use valid lights
     let emitters = elems.iter().enumerate()
@@ -96,25 +124,49 @@ fn establish_dls_contrib(omit_idxs: &[usize], elems: &Vec<Renderable>, hit_info:
         .map(|(i,e)| (i, e.unwrap()));
 
     emitters.fold(vector![0.0,0.0,0.0], |a, (i,emitter)| {
-        let d = emitter.dls_ray(&hit_info.pos, &hit_info.norm);
-        let light_dot = d.dot(&hit_info.norm);
+        let sample = match emitter.dls_ray(&hit_info.pos, &hit_info.norm) {
+            Some(s) if s.pdf > 0.0 && s.pdf.is_finite() => s,
+            _ => return a,
+        };
+        let light_dot = sample.d.dot(&hit_info.norm);
 
         if light_dot > 0.0 {
-            let dls_ray = Ray{ d, o: hit_info.pos }; 
-            let (hrs, idxo) = closest_ray_hit(&dls_ray, elems.into_iter().enumerate().map(|(i, r)| (i, *r)));
+            let dls_ray = Ray{ d: sample.d, o: hit_info.pos }; 
+            let light_hit = elems[i].intersect(&dls_ray).filter(|hr| hr.l.0 >= crate::EPS * 20.0);
 
-            if let Some(idx) = idxo {
-                if i == idx { // make sure its the same light source!!
-                    let hit_info = elems[idx].hit_info(&hrs[idx].1.as_ref().unwrap(), ray);
-                    a + light_dot * hit_info.emissive * NORMZE // brdf-esque normalizing, perhaps ask object for brdf value for a ray?
-                } else {
-                    a
-                }
-            } else {
+            match light_hit {
+                Some(hr) if !accel.occluded(&dls_ray, hr.l.0) => {
+                    let light_info = elems[i].hit_info(&hr, &dls_ray);
+                    a + light_info.emissive * (light_dot / (PI * sample.pdf))
+                },
+                _ => a,
+            }
+        } else {
+            a
+        }
+    })
+}
+
+// same lambertian weighting as above, but there is only one direction to pick so the pdf drops out
+fn establish_delta_contrib(lights: &[Illuminator], accel: &SceneAccel, hit_info: &HitInfo) -> Vector3<f32> {
+    use std::f32::consts::PI;
+
+    lights.iter().fold(vector![0.0,0.0,0.0], |a, light| {
+        let sample = match light.illuminate(&hit_info.pos) {
+            Some(s) => s,
+            None => return a,
+        };
+        let light_dot = sample.d.dot(&hit_info.norm);
+
+        if light_dot > 0.0 {
+            let shadow_ray = Ray{ d: sample.d, o: hit_info.pos };
+            if accel.occluded(&shadow_ray, sample.dist) {
                 a
+            } else {
+                a + sample.irradiance * (light_dot / PI)
             }
         } else {
             a
         }
     })
-}
\ No newline at end of file
+}
@@ -76,6 +76,10 @@ struct CubeMapFaceHeader {
     height: u32,
     uv_scale_x: f32,
     uv_scale_y: f32,
+    is_equirect: u32,
+    rotation: f32,
+    intensity: f32,
+    visible: u32,
 }
 
 struct MeshChunkHeader {
@@ -126,6 +130,10 @@ struct PrimitiveHeader {
     metal_rough_coords_offset: u32,
     metal_rough_coords_count: u32,
 
+    transmission_offset: u32, // amount then ior
+    vert_colors_offset: u32,
+    vert_colors_count: u32,
+
     texture_data_offset: u32,
     texture_data_width: u32,
     texture_data_height: u32,
@@ -137,6 +145,8 @@ struct PrimitiveHeader {
     metal_rough_map_data_offset: u32,
     metal_rough_map_data_width: u32,
     metal_rough_map_data_height: u32,
+
+    padding0: u32,
 }
 
 struct MeshTriangle {
@@ -289,6 +299,9 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         var bounce = 0u;
         loop {
             ray_intersect = get_ray_intersect(ray, &hit_info, &seed);
+            if bounce == 0u && ray_intersect.element_type == CUBEMAP && cube_map_headers[0].visible == 0u {
+                break; // hidden background, only lights what's in front of it
+            }
             
             // generate another ray to bounce off of 
             ray = hit_info.refl_ray.ray;
@@ -494,7 +507,10 @@ fn get_hit_info(ray: Ray, intersect: Intersection, barycentric: vec2<f32>, rng:
             let norm = get_norm_for_mesh_triangle(mesh_triangle, barycentric);
             let pos = ray.direction * intersect.ray_distance + ray.origin + norm * MIN_INTERSECT;
             let dyn_diff_spec = get_diff_spec_and_roughness(mesh_triangle, ray, norm, barycentric, rng);
-            if dyn_diff_spec.should_diff {
+            let transmission = get_transmission_for_mesh_triangle(mesh_triangle);
+            if get_random_f32(rng) < transmission.x {
+                refl_ray = get_refract(ray, norm, pos, transmission.y, 1f, rng);
+            } else if dyn_diff_spec.should_diff {
                 refl_ray = get_diff(ray, norm, pos, rng);
             } else {
                 refl_ray = get_spec(ray, norm, pos);
@@ -909,7 +925,10 @@ fn get_rgba_for_mesh_triangle(mesh_triangle: MeshTriangle, barycentric: vec2<f32
     let inner_id = mesh_triangle.inner_index;
     let mesh_header = mesh_headers[mesh_id];
     let prim_header = primitive_headers[mesh_header.primitive_header_offset + prim_id];
-    let rgb_info_factor = get_rgb_factor_for_mesh_triangle(mesh_triangle);
+    var rgb_info_factor = get_rgb_factor_for_mesh_triangle(mesh_triangle);
+    if prim_header.vert_colors_count != 0u {
+        rgb_info_factor *= get_vert_color_for_mesh_triangle(mesh_triangle, barycentric);
+    }
     if prim_header.rgb_info_coords_count == 0u {
         return vec4<f32>(rgb_info_factor, 0);
     }
@@ -924,6 +943,37 @@ fn get_rgba_for_mesh_triangle(mesh_triangle: MeshTriangle, barycentric: vec2<f32
     return vec4<f32>(scaled_rgb, 0);
 }
 
+fn read_mesh_data(chunk_id: u32, idx: u32) -> f32 {
+    switch chunk_id {
+        case 0u: {return mesh_data_chunk_0[idx];}
+        case 1u: {return mesh_data_chunk_1[idx];}
+        case 2u: {return mesh_data_chunk_2[idx];}
+        case 3u: {return mesh_data_chunk_3[idx];}
+        default: {return 0f;}
+    }
+}
+
+fn get_vert_color_for_mesh_triangle(mesh_triangle: MeshTriangle, barycentric: vec2<f32>) -> vec3<f32> {
+    let mesh_header = mesh_headers[mesh_triangle.mesh_index];
+    let prim_header = primitive_headers[mesh_header.primitive_header_offset + mesh_triangle.prim_index];
+    let data_offset = mesh_header.data_offset + prim_header.mesh_data_offset;
+    let triangle_offset = data_offset + prim_header.triangle_offset + mesh_triangle.inner_index * 3u;
+    let colors_offset = data_offset + prim_header.vert_colors_offset;
+    let bary = vec3<f32>(1.0 - barycentric.x - barycentric.y, barycentric.x, barycentric.y);
+
+    var color = vec3<f32>(0f);
+    for (var v = 0u; v < 3u; v++) {
+        let idx = u32(read_mesh_data(mesh_header.chunk_id, triangle_offset + v));
+        let c = vec3<f32>(
+            read_mesh_data(mesh_header.chunk_id, colors_offset + idx * 3u),
+            read_mesh_data(mesh_header.chunk_id, colors_offset + idx * 3u + 1u),
+            read_mesh_data(mesh_header.chunk_id, colors_offset + idx * 3u + 2u),
+        );
+        color += c * bary[v];
+    }
+    return color;
+}
+
 fn get_norm_from_norms(mesh_triangle: MeshTriangle, normal_transform: mat3x3<f32>) -> vec3<f32> {
     let mesh_id = mesh_triangle.mesh_index;
     let prim_id = mesh_triangle.prim_index;
@@ -1106,6 +1156,26 @@ fn get_metal_rough_direct(mesh_triangle: MeshTriangle) -> vec2<f32> {
     return vec2<f32>(0.0, 0.0);
 }
 
+// amount and ior
+fn get_transmission_for_mesh_triangle(mesh_triangle: MeshTriangle) -> vec2<f32> {
+    let mesh_header = mesh_headers[mesh_triangle.mesh_index];
+    let prim_header = primitive_headers[mesh_header.primitive_header_offset + mesh_triangle.prim_index];
+    let transmission_offset = mesh_header.data_offset + prim_header.mesh_data_offset + prim_header.transmission_offset;
+    if mesh_header.chunk_id == 0 {
+        return vec2<f32>(mesh_data_chunk_0[transmission_offset], mesh_data_chunk_0[transmission_offset + 1u]);
+    }
+    else if mesh_header.chunk_id == 1 {
+        return vec2<f32>(mesh_data_chunk_1[transmission_offset], mesh_data_chunk_1[transmission_offset + 1u]);
+    }
+    else if mesh_header.chunk_id == 2 {
+        return vec2<f32>(mesh_data_chunk_2[transmission_offset], mesh_data_chunk_2[transmission_offset + 1u]);
+    }
+    else if mesh_header.chunk_id == 3 {
+        return vec2<f32>(mesh_data_chunk_3[transmission_offset], mesh_data_chunk_3[transmission_offset + 1u]);
+    }
+    return vec2<f32>(0.0, 1.5);
+}
+
 fn get_scaled_metal_rough(mesh_triangle: MeshTriangle, barycentric: vec2<f32>, metalness: f32, roughness: f32) -> vec2<f32> {
     let mesh_id = mesh_triangle.mesh_index;
     let prim_id = mesh_triangle.prim_index;
@@ -1169,83 +1239,111 @@ fn get_cube_map_face_offset(face_index: u32) -> u32 {
     return offset;
 }
 
-fn get_cube_map_face_pixel(face_id: u32, u: f32, v: f32) -> vec3<f32> {
+fn get_cube_map_face_texel(face_id: u32, x: u32, y: u32) -> vec3<f32> {
     let header = cube_map_headers[face_id];
-    let offset = get_cube_map_face_offset(face_id);
-    let px = u32(trunc(clamp(u * f32(header.width), 0.0, f32(header.width - 1u))));
-    let py = u32(trunc(clamp(v * f32(header.height), 0.0, f32(header.height - 1u))));
-
-    let pixel_offset = offset + 3u * (px + py * header.width);
+    let pixel_offset = get_cube_map_face_offset(face_id) + 3u * (x + y * header.width);
     return vec3<f32>(
         cube_map_faces[pixel_offset],
         cube_map_faces[pixel_offset + 1u],
         cube_map_faces[pixel_offset + 2u],
     );
+}
 
+fn get_cube_map_face_pixel(face_id: u32, u: f32, v: f32) -> vec3<f32> {
+    let header = cube_map_headers[face_id];
+    let px = u32(trunc(clamp(u * f32(header.width), 0.0, f32(header.width - 1u))));
+    let py = u32(trunc(clamp(v * f32(header.height), 0.0, f32(header.height - 1u))));
+    return get_cube_map_face_texel(face_id, px, py);
 }
 
-fn sample_face(face_index: u32, u: f32, v: f32, fact: f32) -> vec3<f32> {
-    let header = cube_map_headers[face_index];
+struct CubeMapLookup {
+    face: u32,
+    uv: vec2<f32>,
+}
 
-    var scaled_u = u * header.uv_scale_x / fact;
-    var scaled_v = v * header.uv_scale_y / fact;
+// components of the direction that run along a face's u and v
+fn cube_map_uv_axes(axis: u32) -> vec2<u32> {
+    if axis == 0u {
+        return vec2<u32>(2u, 1u);
+    }
+    if axis == 1u {
+        return vec2<u32>(0u, 2u);
+    }
+    return vec2<u32>(0u, 1u);
+}
 
-    scaled_u = 0.5 * scaled_u + 0.5;
-    scaled_v = 0.5 * scaled_v + 0.5;
+// face and uv a direction lands on, faces are 0 -> neg_z, 1 -> pos_z, 2 -> neg_x, 3 -> pos_x, 4 -> neg_y, 5 -> pos_y
+fn cube_map_locate(d: vec3<f32>) -> CubeMapLookup {
+    let comps = abs(d);
+    var axis = 2u;
+    if comps.x >= comps.y && comps.x >= comps.z {
+        axis = 0u;
+    } else if comps.y >= comps.z {
+        axis = 1u;
+    }
+    let face = ((axis + 1u) % 3u) * 2u + select(0u, 1u, d[axis] > 0.0);
+    let header = cube_map_headers[face];
+    let uv_axes = cube_map_uv_axes(axis);
+    let u = 0.5 + 0.5 * d[uv_axes.x] * header.uv_scale_x / d[axis];
+    let v = 0.5 + 0.5 * d[uv_axes.y] * header.uv_scale_y / d[axis];
+    return CubeMapLookup(face, vec2<f32>(u, v));
+}
 
-    return get_cube_map_face_pixel(face_index, scaled_u, scaled_v);
+// inverse of cube_map_locate, uv can run off the face into its neighbours
+fn cube_map_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let header = cube_map_headers[face];
+    let axis = (face / 2u + 2u) % 3u;
+    let sign = select(-1.0, 1.0, face % 2u == 1u);
+    let uv_axes = cube_map_uv_axes(axis);
+    var d = vec3<f32>(0.0);
+    d[axis] = sign;
+    d[uv_axes.x] = (2.0 * uv.x - 1.0) * sign / header.uv_scale_x;
+    d[uv_axes.y] = (2.0 * uv.y - 1.0) * sign / header.uv_scale_y;
+    return d;
 }
 
-fn hit_info_distant_cube_map(ray: Ray) -> vec4<f32> {
-    let comps = abs(ray.direction.xyz);
-    var face_index = 0u;
-    var u: f32 = -1f;
-    var v: f32 = -1f;
-    var fact: f32 = -1f;
-    let d = normalize(ray.direction.xyz);
-    // Find the largest component and determine which face to sample
-    // 0 -> neg_z, 1 -> pos_z, 2 -> neg_x, 3 -> pos_x, 4 -> neg_y, 5 -> pos_y
-    if comps.x >= comps.y && comps.x >= comps.z {
-        if d.x < 0.0 {
-            u = d.z;
-            v = d.y;
-            fact = d.x;
-            face_index = 2u; // neg_x
-        } else {
-            u = d.z;
-            v = d.y;
-            fact = d.x;
-            face_index = 3u; // pos_x
-        }
-    } else if comps.y >= comps.x && comps.y >= comps.z {
-        if d.y < 0.0 {
-            u = d.x;
-            v = d.z;
-            fact = d.y;
-            face_index = 4u; // neg_y
-        } else {
-            u = d.x;
-            v = d.z;
-            fact = d.y;
-            face_index = 5u; // pos_y
-        }
-    } else if comps.z >= comps.x && comps.z >= comps.y {
-        if d.z < 0.0 {
-            u = d.x;
-            v = d.y;
-            fact = d.z;
-            face_index = 0u; // neg_z
-        } else {
-            u = d.x;
-            v = d.y;
-            fact = d.z;
-            face_index = 1u; // pos_z
-        }
+// texels past the edge of a face come from whichever face they fold onto
+fn cube_map_texel(face: u32, tx: f32, ty: f32) -> vec3<f32> {
+    let header = cube_map_headers[face];
+    let w = f32(header.width);
+    let h = f32(header.height);
+    if tx >= 0.0 && ty >= 0.0 && tx < w && ty < h {
+        return get_cube_map_face_texel(face, u32(tx), u32(ty));
     }
+    let lookup = cube_map_locate(cube_map_direction(face, vec2<f32>((tx + 0.5) / w, (ty + 0.5) / h)));
+    return get_cube_map_face_pixel(lookup.face, lookup.uv.x, lookup.uv.y);
+}
+
+// centre of the image looks down -z, top row straight up, same as the cpu side
+fn hit_info_equirect(ray: Ray) -> vec4<f32> {
+    let header = cube_map_headers[0];
+    let d = normalize(ray.direction.xyz);
+    let s = sin(header.rotation);
+    let c = cos(header.rotation);
+    let x = c * d.x - s * d.z;
+    let z = s * d.x + c * d.z;
+    let u = 0.5 + atan2(x, -z) / (2.0 * PI);
+    let v = acos(clamp(d.y, -1.0, 1.0)) / PI;
+    return vec4<f32>(get_cube_map_face_pixel(0u, u, v) * header.intensity, 0.0);
+}
 
-    let rgb = sample_face(face_index, u, v, fact);
-    let rgba = vec4<f32>(rgb, 0.0);
-    return rgba;
+fn hit_info_distant_cube_map(ray: Ray) -> vec4<f32> {
+    if cube_map_headers[0].is_equirect == 1u {
+        return hit_info_equirect(ray);
+    }
+    let rot = cube_map_headers[0].rotation;
+    let dir = normalize(ray.direction.xyz);
+    let d = vec3<f32>(cos(rot) * dir.x - sin(rot) * dir.z, dir.y, sin(rot) * dir.x + cos(rot) * dir.z);
+    let lookup = cube_map_locate(d);
+
+    // bilinear, same as the cpu side
+    let header = cube_map_headers[lookup.face];
+    let xy = lookup.uv * vec2<f32>(f32(header.width), f32(header.height)) - 0.5;
+    let xy0 = floor(xy);
+    let f = xy - xy0;
+    let top = mix(cube_map_texel(lookup.face, xy0.x, xy0.y), cube_map_texel(lookup.face, xy0.x + 1.0, xy0.y), f.x);
+    let bottom = mix(cube_map_texel(lookup.face, xy0.x, xy0.y + 1.0), cube_map_texel(lookup.face, xy0.x + 1.0, xy0.y + 1.0), f.x);
+    return vec4<f32>(mix(top, bottom, f.y), 0.0);
 }
 
 
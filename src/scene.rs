@@ -1,7 +1,7 @@
 use nalgebra::Vector3;
 use serde::Deserialize;
 use crate::types::GPUElements;
-use crate::elements::{Element, Group};
+use crate::elements::{Element, Group, Light};
 
 #[derive(Deserialize, Debug)]
 pub struct Cam {
@@ -22,6 +22,7 @@ pub struct Scene<'e> {
 pub enum Member<'e> {
     Elem(Element<'e>),
     Grp(Group),
+    Light(Light),
 }
 
 pub struct GPUScene {